@@ -0,0 +1,57 @@
+use std::hint::black_box;
+
+use aic::{codegen::CodeGen, parser};
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use inkwell::context::Context;
+
+/// Generate a synthetic program with the given number of functions
+fn synthetic_program(functions: usize) -> String {
+    let mut src = String::new();
+    for i in 0..functions {
+        src.push_str(&format!(
+            "fn f{i}(a: i32, b: i32) -> i32 {{\n    \
+                 let c = a + b * {i};\n    \
+                 var d = c;\n    \
+                 while d > 10 {{\n        \
+                     d = d - 10;\n    \
+                 }}\n    \
+                 if c > 10 && !(d == 0) {{\n        \
+                     return c - d;\n    \
+                 }}\n    \
+                 c\n\
+             }}\n\n"
+        ));
+    }
+    src.push_str("f0(1, 2)\n");
+    src
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let src = synthetic_program(1000);
+
+    let mut group = c.benchmark_group("parse");
+    group.throughput(Throughput::Bytes(src.len() as u64));
+    group.bench_function("1000 functions", |b| {
+        b.iter(|| parser::parse(black_box(&src)).into_result().unwrap())
+    });
+    group.finish();
+}
+
+fn bench_compile(c: &mut Criterion) {
+    let src = synthetic_program(1000);
+    let program = parser::parse(&src).into_result().unwrap();
+
+    let mut group = c.benchmark_group("compile");
+    group.throughput(Throughput::Bytes(src.len() as u64));
+    group.bench_function("1000 functions", |b| {
+        b.iter(|| {
+            let context = Context::create();
+            let mut codegen = CodeGen::new(&context, "bench");
+            codegen.compile(black_box(&program)).unwrap();
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_parse, bench_compile);
+criterion_main!(benches);
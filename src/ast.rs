@@ -1,12 +1,37 @@
 use serde::Serialize;
 
+/// A byte range in the source code
+pub type Span = std::ops::Range<usize>;
+
 /// Expression
 #[derive(Debug, Clone, PartialEq, Serialize)]
-pub enum Expr<'a> {
+#[serde(transparent)]
+pub struct Expr<'a> {
+    /// The kind of expression
+    pub kind: ExprKind<'a>,
+    /// The source location of the expression
+    #[serde(skip)]
+    pub span: Span,
+}
+
+impl<'a> Expr<'a> {
+    /// Create a new expression
+    pub fn new(kind: ExprKind<'a>, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+
+/// Expression kind
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum ExprKind<'a> {
     /// An integer literal
     IntLit(i64),
+    /// A floating-point literal
+    FloatLit(f64),
     /// A boolean literal
     BoolLit(bool),
+    /// A string literal
+    StrLit(String),
     /// A binary operation
     BinOp {
         /// The left-hand side expression
@@ -29,12 +54,121 @@ pub enum Expr<'a> {
         name: &'a str,
         /// The arguments
         args: Vec<Expr<'a>>,
+        /// The names of the trailing named arguments (`name: value`), which follow the positional ones
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        arg_names: Vec<&'a str>,
     },
     /// A variable reference (identifier)
     VarRef {
         /// The variable name
         name: &'a str,
     },
+    /// A struct literal
+    StructLit {
+        /// The struct name
+        name: &'a str,
+        /// The field initializers
+        fields: Vec<StructLitField<'a>>,
+    },
+    /// A field access
+    FieldAccess {
+        /// The struct expression
+        expr: Box<Expr<'a>>,
+        /// The field name
+        field: &'a str,
+    },
+    /// An enum variant path (`Name::Variant`)
+    EnumVariant {
+        /// The enum name
+        name: &'a str,
+        /// The variant name
+        variant: &'a str,
+    },
+    /// A type cast (`expr as type`)
+    Cast {
+        /// The expression to cast
+        expr: Box<Expr<'a>>,
+        /// The target type
+        r#type: Type<'a>,
+    },
+    /// The size of a type in bytes (`sizeof(type)`)
+    SizeOf {
+        /// The type to measure
+        r#type: Type<'a>,
+    },
+    /// A labeled block (`'label: { ... }`), whose value is its trailing expression
+    /// or the value of a `break 'label value;` inside it
+    Block {
+        /// The label, without the leading quote
+        label: &'a str,
+        /// The statements of the block
+        body: Vec<Stmt<'a>>,
+    },
+    /// An if expression (`if c { a } else { b }`), whose value is the trailing expression
+    /// of the branch taken. Unlike an if statement it must have an `else`
+    If {
+        /// The condition
+        condition: Box<Expr<'a>>,
+        /// The statements run when the condition is true
+        then_branch: Vec<Stmt<'a>>,
+        /// The statements run otherwise. An `else if` is a nested if expression
+        else_branch: Vec<Stmt<'a>>,
+    },
+    /// A match expression
+    Match {
+        /// The value being matched
+        scrutinee: Box<Expr<'a>>,
+        /// The arms, tried in order
+        arms: Vec<MatchArm<'a>>,
+    },
+    /// A comma expression (`(a, b)`), which evaluates the expressions from left
+    /// to right and has the value of the last one
+    Sequence {
+        /// The expressions, at least two
+        exprs: Vec<Expr<'a>>,
+    },
+    /// An increment or decrement of a mutable integer variable (`++x`, `x--`, ...).
+    /// A prefix operator has the updated value, a postfix one the old value
+    IncDec {
+        /// The operator
+        op: IncDecOp,
+        /// The variable name
+        name: &'a str,
+        /// Whether the operator follows the variable
+        postfix: bool,
+    },
+}
+
+/// Match arm
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MatchArm<'a> {
+    /// The pattern to match against
+    pub pattern: Pattern,
+    /// The source location of the pattern
+    #[serde(skip)]
+    pub pattern_span: Span,
+
+    /// The value of the arm
+    pub value: Expr<'a>,
+}
+
+/// Match arm pattern
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum Pattern {
+    /// An integer literal
+    IntLit(i64),
+    /// The wildcard pattern (`_`)
+    Wildcard,
+}
+
+/// Struct literal field initializer
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StructLitField<'a> {
+    /// The name of the field
+    pub name: &'a str,
+
+    /// The value of the field
+    pub value: Expr<'a>,
 }
 
 /// Binary operator
@@ -64,6 +198,8 @@ pub enum BinOp {
     And,
     /// Logical OR (||)
     Or,
+    /// Logical XOR (^^), true when exactly one operand is
+    Xor,
 }
 
 /// Unary operator
@@ -73,17 +209,95 @@ pub enum UnaryOp {
     Neg,
     /// Logical NOT (!)
     Not,
+    /// Bitwise NOT (~)
+    BitNot,
+}
+
+/// Increment/decrement operator
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum IncDecOp {
+    /// Increment (++)
+    Increment,
+    /// Decrement (--)
+    Decrement,
 }
 
 /// Type
 #[derive(Debug, Clone, Copy, PartialEq, Serialize)]
-pub enum Type {
+pub enum Type<'a> {
+    Bool,
     I32,
     I64,
     F32,
     F64,
     Void,
     String,
+    /// A user-defined struct type
+    Struct(&'a str),
+    /// A user-defined enum type
+    Enum(&'a str),
+}
+
+/// The type of an integer literal with the given value, which may be negated:
+/// i32 if the value fits, and i64 otherwise (as in C).
+///
+/// This is only the default. Where the context expects an integer type, i.e. the
+/// annotation of a `let`/`var`, a parameter or a declared return type, the literal
+/// takes that type instead and it is an error if the value doesn't fit.
+pub fn int_literal_type(value: i128) -> Type<'static> {
+    if i32::try_from(value).is_ok() {
+        Type::I32
+    } else {
+        Type::I64
+    }
+}
+
+/// The value of an integer literal such as `5` or `-5`, if `expr` is one
+pub fn int_literal_value(expr: &Expr) -> Option<i128> {
+    match &expr.kind {
+        ExprKind::IntLit(value) => Some(i128::from(*value)),
+        ExprKind::UnaryOp {
+            op: UnaryOp::Neg,
+            expr,
+        } => match expr.kind {
+            ExprKind::IntLit(value) => Some(-i128::from(value)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Whether an integer literal with the given value fits in `ty`, which must be an integer type
+pub fn int_literal_fits(value: i128, ty: Type) -> bool {
+    match ty {
+        Type::I32 => i32::try_from(value).is_ok(),
+        Type::I64 => i64::try_from(value).is_ok(),
+        _ => false,
+    }
+}
+
+impl std::fmt::Display for Type<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Bool => write!(f, "bool"),
+            Self::I32 => write!(f, "i32"),
+            Self::I64 => write!(f, "i64"),
+            Self::F32 => write!(f, "f32"),
+            Self::F64 => write!(f, "f64"),
+            Self::Void => write!(f, "void"),
+            Self::String => write!(f, "string"),
+            Self::Struct(name) | Self::Enum(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+/// Function attribute (`#[name]` before `fn`)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum FnAttribute {
+    /// Always inline the function (`#[inline]`)
+    Inline,
+    /// Never inline the function (`#[noinline]`)
+    NoInline,
 }
 
 /// Function parameter
@@ -93,7 +307,27 @@ pub struct FunctionParameter<'a> {
     pub name: &'a str,
 
     /// The type of the parameter
-    pub r#type: Type,
+    pub r#type: Type<'a>,
+}
+
+/// Struct field declaration
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct StructField<'a> {
+    /// The name of the field
+    pub name: &'a str,
+
+    /// The type of the field
+    pub r#type: Type<'a>,
+}
+
+/// Enum variant declaration
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct EnumVariant<'a> {
+    /// The name of the variant
+    pub name: &'a str,
+
+    /// The explicit discriminant (optional)
+    pub value: Option<i64>,
 }
 
 /// Statements
@@ -101,24 +335,77 @@ pub struct FunctionParameter<'a> {
 pub enum Stmt<'a> {
     /// A function declaration
     FnDecl {
+        /// The attributes written before `fn`, such as `#[inline]`
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        attributes: Vec<FnAttribute>,
+        /// Whether the function is a `const fn`, whose calls with constant arguments
+        /// are evaluated at compile time
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        is_const: bool,
         /// The name of the function
         name: &'a str,
         /// The parameters of the function
         params: Vec<FunctionParameter<'a>>,
-        /// The return type of the function
-        r#type: Type,
+        /// The return type of the function, or `None` if it is inferred from the body
+        r#type: Option<Type<'a>>,
         /// The body of the function
         body: Vec<Stmt<'a>>,
+        /// The source location of the statement
+        #[serde(skip)]
+        span: Span,
     },
 
-    /// A variable declaration (let)
+    /// An external function declaration (`extern fn name(params) -> type;`),
+    /// whose definition is linked in from elsewhere, e.g. libc
+    ExternDecl {
+        /// The symbol name of the function
+        name: &'a str,
+        /// The parameters of the function
+        params: Vec<FunctionParameter<'a>>,
+        /// The return type of the function
+        r#type: Type<'a>,
+        /// The source location of the statement
+        #[serde(skip)]
+        span: Span,
+    },
+
+    /// A struct declaration
+    StructDecl {
+        /// The name of the struct
+        name: &'a str,
+        /// The fields of the struct
+        fields: Vec<StructField<'a>>,
+        /// The source location of the statement
+        #[serde(skip)]
+        span: Span,
+    },
+
+    /// An enum declaration
+    EnumDecl {
+        /// The name of the enum
+        name: &'a str,
+        /// The variants of the enum
+        variants: Vec<EnumVariant<'a>>,
+        /// The source location of the statement
+        #[serde(skip)]
+        span: Span,
+    },
+
+    /// A variable declaration (let), or a constant item (`const NAME: type = expr;`)
+    /// whose initializer is evaluated at compile time
     LetDecl {
+        /// Whether the declaration is a `const` item
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        is_const: bool,
         /// The variable name
         name: &'a str,
         /// The type (optional)
-        r#type: Option<Type>,
+        r#type: Option<Type<'a>>,
         /// The value (optional)
         value: Option<Expr<'a>>,
+        /// The source location of the statement
+        #[serde(skip)]
+        span: Span,
     },
 
     /// A mutable variable declaration (var)
@@ -126,9 +413,12 @@ pub enum Stmt<'a> {
         /// The variable name
         name: &'a str,
         /// The type (optional)
-        r#type: Option<Type>,
+        r#type: Option<Type<'a>>,
         /// The value (optional)
         value: Option<Expr<'a>>,
+        /// The source location of the statement
+        #[serde(skip)]
+        span: Span,
     },
 
     /// An assignment statement
@@ -137,6 +427,9 @@ pub enum Stmt<'a> {
         name: &'a str,
         /// The value to assign
         value: Box<Expr<'a>>,
+        /// The source location of the statement
+        #[serde(skip)]
+        span: Span,
     },
 
     /// An if statement
@@ -149,20 +442,65 @@ pub enum Stmt<'a> {
         else_branch: Option<Vec<Stmt<'a>>>,
     },
 
+    /// A while loop
+    While {
+        /// The condition
+        condition: Box<Expr<'a>>,
+        /// The loop body
+        body: Vec<Stmt<'a>>,
+    },
+
+    /// A do-while loop, whose body runs at least once
+    DoWhile {
+        /// The loop body
+        body: Vec<Stmt<'a>>,
+        /// The condition, checked after each iteration
+        condition: Box<Expr<'a>>,
+    },
+
+    /// A bare block (`{ ... }`), whose variables go out of scope at its end
+    Block {
+        /// The statements of the block
+        body: Vec<Stmt<'a>>,
+    },
+
+    /// A break statement, leaving the innermost loop or, with a label, a labeled block
+    Break {
+        /// The label of the block to leave (optional)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        label: Option<&'a str>,
+        /// The value the labeled block yields (optional)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        value: Option<Box<Expr<'a>>>,
+        /// The source location of the statement
+        #[serde(skip)]
+        span: Span,
+    },
+
+    /// A continue statement
+    Continue {
+        /// The source location of the statement
+        #[serde(skip)]
+        span: Span,
+    },
+
     /// A return statement
     Return {
         /// The expression to return (optional)
         expr: Option<Box<Expr<'a>>>,
+        /// The source location of the statement
+        #[serde(skip)]
+        span: Span,
     },
 
-    /// An expression statement
+    /// An expression statement (`expr;`), whose value is discarded even at the end of a block
     #[allow(clippy::enum_variant_names)]
     ExprStmt {
         /// The expression
         expr: Box<Expr<'a>>,
     },
 
-    /// An expression
+    /// The trailing expression of a block (`expr` without a `;`), which is the block's value
     Expr {
         /// The expression
         expr: Box<Expr<'a>>,
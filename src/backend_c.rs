@@ -0,0 +1,738 @@
+//! A backend that transpiles a program to C99 source instead of LLVM IR
+//!
+//! Only the core of the language is supported: functions, the primitive types,
+//! variables, arithmetic, `if` and loops. Anything else is reported as an error.
+//!
+//! Integer `+`, `-`, `*` and negation go through unsigned types so that they wrap
+//! like in codegen. `++` and `--` past the largest or smallest value are still
+//! undefined behavior in C.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+};
+
+use anyhow::{Result, bail};
+
+use crate::{
+    ast,
+    visit::{self, Visitor},
+};
+
+/// Translate a type-checked program to C99 source
+pub fn program_to_c(program: &ast::Program) -> Result<String> {
+    let mut writer = CWriter::new();
+    writer
+        .out
+        .push_str("#include <stdbool.h>\n#include <stdint.h>\n\n");
+
+    // Declare every function up front so that declaration order doesn't matter, like in codegen
+    let mut has_functions = false;
+    // External functions only get the prototype, and are resolved by the C linker
+    for stmt in &program.statements {
+        let (name, params, r#type) = match stmt {
+            ast::Stmt::FnDecl {
+                attributes,
+                name,
+                params,
+                r#type,
+                ..
+            } => {
+                if !attributes.is_empty() {
+                    bail!(
+                        "Attributes on function '{}' are not supported by the C backend",
+                        name
+                    );
+                }
+                (name, params, return_type(name, *r#type)?)
+            }
+            ast::Stmt::ExternDecl {
+                name,
+                params,
+                r#type,
+                ..
+            } => (name, params, *r#type),
+            _ => continue,
+        };
+        writer.functions.insert(*name, r#type);
+        let signature = signature(name, params, r#type)?;
+        writeln!(writer.out, "{signature};").unwrap();
+        has_functions = true;
+    }
+    if has_functions {
+        writer.out.push('\n');
+    }
+
+    for stmt in &program.statements {
+        if let ast::Stmt::FnDecl {
+            name,
+            params,
+            r#type,
+            body,
+            ..
+        } = stmt
+        {
+            let r#type = return_type(name, *r#type)?;
+            writeln!(writer.out, "{} {{", signature(name, params, r#type)?).unwrap();
+            let mut names = VarNames::default();
+            names
+                .0
+                .extend(params.iter().map(|param| param.name.to_string()));
+            body.iter().for_each(|stmt| names.visit_stmt(stmt));
+            writer.begin_function(r#type, names.0);
+            for param in params {
+                writer.declare(param.name, param.r#type);
+            }
+            writer.block(body, 1)?;
+            writer.out.push_str("}\n\n");
+        }
+    }
+
+    // The remaining top-level statements make up `main`
+    writer.out.push_str("int main(void) {\n");
+    let is_main_stmt = |stmt: &&ast::Stmt| {
+        !matches!(
+            stmt,
+            ast::Stmt::FnDecl { .. } | ast::Stmt::ExternDecl { .. }
+        )
+    };
+    let mut names = VarNames::default();
+    program
+        .statements
+        .iter()
+        .filter(is_main_stmt)
+        .for_each(|stmt| names.visit_stmt(stmt));
+    writer.begin_function(ast::Type::I32, names.0);
+    let mut returns = false;
+    for stmt in program.statements.iter().filter(is_main_stmt) {
+        writer.stmt(stmt, 1)?;
+        returns = matches!(stmt, ast::Stmt::Expr { .. } | ast::Stmt::Return { .. });
+    }
+    if !returns {
+        writer.line(1, "return 0;");
+    }
+    writer.out.push_str("}\n");
+    Ok(writer.out)
+}
+
+/// The return type of a function declaration, which sema infers when it is omitted
+fn return_type<'a>(name: &str, r#type: Option<ast::Type<'a>>) -> Result<ast::Type<'a>> {
+    r#type.ok_or_else(|| anyhow::anyhow!("Return type of '{}' was not inferred", name))
+}
+
+/// A C integer literal, parenthesized if negative. Like in aic, literals that
+/// don't fit in an int are 64-bit
+fn c_int_literal(value: i128) -> String {
+    match ast::int_literal_type(value) {
+        // `-2147483648` is the negation of a literal that doesn't fit in an int
+        _ if value == i128::from(i32::MIN) => "(-2147483647 - 1)".to_string(),
+        ast::Type::I32 if value < 0 => format!("({value})"),
+        ast::Type::I32 => value.to_string(),
+        _ if value < 0 => format!("(INT64_C({value}))"),
+        _ => format!("INT64_C({value})"),
+    }
+}
+
+/// Collects the names of the variables declared in the visited statements
+#[derive(Default)]
+struct VarNames(HashSet<String>);
+
+impl<'a> Visitor<'a> for VarNames {
+    fn visit_stmt(&mut self, stmt: &ast::Stmt<'a>) {
+        if let ast::Stmt::LetDecl { name, .. } | ast::Stmt::VarDecl { name, .. } = stmt {
+            self.0.insert(name.to_string());
+        }
+        visit::walk_stmt(self, stmt);
+    }
+}
+
+/// The C prototype of a function, without a trailing semicolon
+fn signature(
+    name: &str,
+    params: &[ast::FunctionParameter],
+    return_type: ast::Type,
+) -> Result<String> {
+    let params = if params.is_empty() {
+        "void".to_string()
+    } else {
+        params
+            .iter()
+            .map(|param| {
+                Ok(format!(
+                    "{} {}",
+                    c_type(param.r#type)?,
+                    c_identifier(param.name)
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?
+            .join(", ")
+    };
+    Ok(format!(
+        "{} {}({params})",
+        c_type(return_type)?,
+        c_identifier(name)
+    ))
+}
+
+struct CWriter<'a> {
+    out: String,
+    /// Return types of the top-level functions
+    functions: HashMap<&'a str, ast::Type<'a>>,
+    /// Stack of variable scopes, mapping each variable to its C name and type
+    scopes: Vec<HashMap<&'a str, (String, ast::Type<'a>)>>,
+    /// Number of variables declared so far under each name in the current function,
+    /// to give shadowing declarations fresh C names
+    declared: HashMap<&'a str, usize>,
+    /// C names a shadowing declaration can't be renamed to: the names of the current
+    /// function's variables and the fresh names given out so far
+    reserved: HashSet<String>,
+    /// Return type of the function being translated
+    return_type: ast::Type<'a>,
+}
+
+impl<'a> CWriter<'a> {
+    fn new() -> Self {
+        Self {
+            out: String::new(),
+            functions: HashMap::new(),
+            scopes: Vec::new(),
+            declared: HashMap::new(),
+            reserved: HashSet::new(),
+            return_type: ast::Type::Void,
+        }
+    }
+
+    /// Start translating a function body, whose parameters go in a fresh outermost scope.
+    /// `names` are the names of all the variables the function declares
+    fn begin_function(&mut self, return_type: ast::Type<'a>, names: HashSet<String>) {
+        self.return_type = return_type;
+        self.scopes = vec![HashMap::new()];
+        self.declared.clear();
+        self.reserved = names;
+    }
+
+    /// Declare a variable in the innermost scope and return its C name
+    fn declare(&mut self, name: &'a str, ty: ast::Type<'a>) -> String {
+        let count = self.declared.entry(name).or_default();
+        // Skip fresh names that another variable, e.g. a `let x_1`, already spells
+        let c_name = match *count {
+            0 => c_identifier(name),
+            n => (n..)
+                .map(|n| format!("{name}_{n}"))
+                .find(|c_name| !self.reserved.contains(c_name))
+                .unwrap(),
+        };
+        *count += 1;
+        self.reserved.insert(c_name.clone());
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(name, (c_name.clone(), ty));
+        c_name
+    }
+
+    fn lookup(&self, name: &str) -> Result<&(String, ast::Type<'a>)> {
+        match self.scopes.iter().rev().find_map(|scope| scope.get(name)) {
+            Some(variable) => Ok(variable),
+            None => bail!("Variable '{}' not found", name),
+        }
+    }
+
+    fn line(&mut self, indent: usize, line: &str) {
+        writeln!(self.out, "{}{line}", "    ".repeat(indent)).unwrap();
+    }
+
+    /// Translate the statements of a block, in a scope of their own
+    fn block(&mut self, stmts: &[ast::Stmt<'a>], indent: usize) -> Result<()> {
+        self.scopes.push(HashMap::new());
+        for stmt in stmts {
+            self.stmt(stmt, indent)?;
+        }
+        self.scopes.pop();
+        Ok(())
+    }
+
+    fn stmt(&mut self, stmt: &ast::Stmt<'a>, indent: usize) -> Result<()> {
+        match stmt {
+            ast::Stmt::FnDecl { name, .. } => {
+                bail!(
+                    "Nested function '{}' is not supported by the C backend",
+                    name
+                )
+            }
+            ast::Stmt::ExternDecl { name, .. } => {
+                bail!(
+                    "Nested extern function '{}' is not supported by the C backend",
+                    name
+                )
+            }
+            ast::Stmt::StructDecl { .. } | ast::Stmt::EnumDecl { .. } => {
+                bail!("Structs and enums are not supported by the C backend")
+            }
+            ast::Stmt::LetDecl {
+                name,
+                r#type: None,
+                value: Some(value),
+                ..
+            } if *name == "_" => {
+                let value = self.expr(value)?;
+                self.line(indent, &format!("(void)({value});"));
+            }
+            ast::Stmt::LetDecl {
+                name,
+                r#type,
+                value,
+                ..
+            }
+            | ast::Stmt::VarDecl {
+                name,
+                r#type,
+                value,
+                ..
+            } => {
+                let ty = match (r#type, value) {
+                    (Some(ty), _) => *ty,
+                    (None, Some(value)) => self.expr_type(value)?,
+                    (None, None) => bail!("Type annotation required for '{}'", name),
+                };
+                // The initializer is translated before the variable is in scope, so `let x = x + 1;` works
+                let value = value.as_ref().map(|value| self.expr(value)).transpose()?;
+                let c_name = self.declare(name, ty);
+                let line = match value {
+                    Some(value) => format!("{} {c_name} = {value};", c_type(ty)?),
+                    None => format!("{} {c_name};", c_type(ty)?),
+                };
+                self.line(indent, &line);
+            }
+            ast::Stmt::Assign { name, value, .. } => {
+                let value = self.expr(value)?;
+                let c_name = self.lookup(name)?.0.clone();
+                self.line(indent, &format!("{c_name} = {value};"));
+            }
+            ast::Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                let condition = self.expr(condition)?;
+                self.line(indent, &format!("if ({condition}) {{"));
+                self.block(then_branch, indent + 1)?;
+                if let Some(else_branch) = else_branch {
+                    self.line(indent, "} else {");
+                    self.block(else_branch, indent + 1)?;
+                }
+                self.line(indent, "}");
+            }
+            ast::Stmt::While { condition, body } => {
+                let condition = self.expr(condition)?;
+                self.line(indent, &format!("while ({condition}) {{"));
+                self.block(body, indent + 1)?;
+                self.line(indent, "}");
+            }
+            ast::Stmt::DoWhile { body, condition } => {
+                self.line(indent, "do {");
+                self.block(body, indent + 1)?;
+                let condition = self.expr(condition)?;
+                self.line(indent, &format!("}} while ({condition});"));
+            }
+            ast::Stmt::Block { body } => {
+                self.line(indent, "{");
+                self.block(body, indent + 1)?;
+                self.line(indent, "}");
+            }
+            ast::Stmt::Break { label: None, .. } => self.line(indent, "break;"),
+            ast::Stmt::Break { label: Some(_), .. } => {
+                bail!("Labeled blocks are not supported by the C backend")
+            }
+            ast::Stmt::Continue { .. } => self.line(indent, "continue;"),
+            ast::Stmt::ExprStmt { expr } => {
+                let expr = self.expr(expr)?;
+                self.line(indent, &format!("{expr};"));
+            }
+            // A trailing expression returns from the function, like in codegen
+            ast::Stmt::Return {
+                expr: Some(expr), ..
+            }
+            | ast::Stmt::Expr { expr } => {
+                let value = self.expr(expr)?;
+                if self.return_type == ast::Type::Void {
+                    self.line(indent, &format!("{value};"));
+                    self.line(indent, "return;");
+                } else {
+                    self.line(indent, &format!("return {value};"));
+                }
+            }
+            ast::Stmt::Return { expr: None, .. } => self.line(indent, "return;"),
+        }
+        Ok(())
+    }
+
+    /// Translate an expression, parenthesized wherever precedence could matter
+    fn expr(&self, expr: &ast::Expr<'a>) -> Result<String> {
+        let code = match &expr.kind {
+            ast::ExprKind::IntLit(value) => c_int_literal(i128::from(*value)),
+            ast::ExprKind::FloatLit(value) if !value.is_finite() => {
+                bail!("Float literal {} has no C equivalent", value)
+            }
+            ast::ExprKind::FloatLit(value) => format!("{value:?}"),
+            ast::ExprKind::BoolLit(value) => value.to_string(),
+            ast::ExprKind::StrLit(value) => c_string(value),
+            ast::ExprKind::BinOp {
+                lhs,
+                op: op @ (ast::BinOp::Add | ast::BinOp::Sub | ast::BinOp::Mul),
+                rhs,
+            } if unsigned_type(self.expr_type(lhs)?).is_some() => {
+                let ty = self.expr_type(lhs)?;
+                let unsigned = unsigned_type(ty).unwrap();
+                let op = match op {
+                    ast::BinOp::Add => "+",
+                    ast::BinOp::Sub => "-",
+                    _ => "*",
+                };
+                format!(
+                    "(({})(({unsigned}){} {op} ({unsigned}){}))",
+                    c_type(ty)?,
+                    self.expr(lhs)?,
+                    self.expr(rhs)?
+                )
+            }
+            ast::ExprKind::BinOp { lhs, op, rhs } => {
+                let op = match op {
+                    ast::BinOp::Add => "+",
+                    ast::BinOp::Sub => "-",
+                    ast::BinOp::Mul => "*",
+                    ast::BinOp::Div => "/",
+                    ast::BinOp::Equal => "==",
+                    ast::BinOp::NotEqual => "!=",
+                    ast::BinOp::LessThan => "<",
+                    ast::BinOp::LessThanOrEqual => "<=",
+                    ast::BinOp::GreaterThan => ">",
+                    ast::BinOp::GreaterThanOrEqual => ">=",
+                    ast::BinOp::And => "&&",
+                    ast::BinOp::Or => "||",
+                    // Both operands are bools, which differ exactly when one is true
+                    ast::BinOp::Xor => "!=",
+                };
+                format!("({} {op} {})", self.expr(lhs)?, self.expr(rhs)?)
+            }
+            ast::ExprKind::UnaryOp { op, expr } => {
+                // A negated literal such as `-2147483648` is typed by its value
+                if let (ast::UnaryOp::Neg, ast::ExprKind::IntLit(value)) = (op, &expr.kind) {
+                    return Ok(c_int_literal(-i128::from(*value)));
+                }
+                if *op == ast::UnaryOp::Neg {
+                    let ty = self.expr_type(expr)?;
+                    if let Some(unsigned) = unsigned_type(ty) {
+                        return Ok(format!(
+                            "(({})(-({unsigned}){}))",
+                            c_type(ty)?,
+                            self.expr(expr)?
+                        ));
+                    }
+                }
+                let op = match op {
+                    ast::UnaryOp::Neg => "-",
+                    ast::UnaryOp::Not => "!",
+                    ast::UnaryOp::BitNot => "~",
+                };
+                format!("({op}{})", self.expr(expr)?)
+            }
+            ast::ExprKind::FnCall {
+                name,
+                args,
+                arg_names,
+            } => {
+                if !self.functions.contains_key(name) {
+                    bail!("Function '{}' is not supported by the C backend", name);
+                }
+                if !arg_names.is_empty() {
+                    bail!("Named arguments are not supported by the C backend");
+                }
+                let args = args
+                    .iter()
+                    .map(|arg| self.expr(arg))
+                    .collect::<Result<Vec<_>>>()?;
+                format!("{}({})", c_identifier(name), args.join(", "))
+            }
+            ast::ExprKind::VarRef { name } => self.lookup(name)?.0.clone(),
+            ast::ExprKind::Cast { expr, r#type } => {
+                format!("(({}){})", c_type(*r#type)?, self.expr(expr)?)
+            }
+            ast::ExprKind::SizeOf { r#type } => {
+                format!("((int32_t)sizeof({}))", c_type(*r#type)?)
+            }
+            ast::ExprKind::StructLit { .. }
+            | ast::ExprKind::FieldAccess { .. }
+            | ast::ExprKind::EnumVariant { .. } => {
+                bail!("Structs and enums are not supported by the C backend")
+            }
+            ast::ExprKind::Block { .. } => {
+                bail!("Labeled blocks are not supported by the C backend")
+            }
+            ast::ExprKind::Match { .. } => bail!("Match is not supported by the C backend"),
+            ast::ExprKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                let (then_expr, else_expr) = if_arms(then_branch, else_branch)?;
+                format!(
+                    "({} ? {} : {})",
+                    self.expr(condition)?,
+                    self.expr(then_expr)?,
+                    self.expr(else_expr)?
+                )
+            }
+            ast::ExprKind::Sequence { exprs } => {
+                let exprs = exprs
+                    .iter()
+                    .map(|expr| self.expr(expr))
+                    .collect::<Result<Vec<_>>>()?;
+                format!("({})", exprs.join(", "))
+            }
+            ast::ExprKind::IncDec { op, name, postfix } => {
+                let op = match op {
+                    ast::IncDecOp::Increment => "++",
+                    ast::IncDecOp::Decrement => "--",
+                };
+                let name = self.lookup(name)?.0.clone();
+                if *postfix {
+                    format!("({name}{op})")
+                } else {
+                    format!("({op}{name})")
+                }
+            }
+        };
+        Ok(code)
+    }
+
+    /// Infer the type of an expression, which C needs for declarations without an annotation
+    fn expr_type(&self, expr: &ast::Expr<'a>) -> Result<ast::Type<'a>> {
+        let ty = match &expr.kind {
+            ast::ExprKind::IntLit(value) => ast::int_literal_type(i128::from(*value)),
+            ast::ExprKind::SizeOf { .. } => ast::Type::I32,
+            ast::ExprKind::FloatLit(_) => ast::Type::F64,
+            ast::ExprKind::BoolLit(_) => ast::Type::Bool,
+            ast::ExprKind::StrLit(_) => ast::Type::String,
+            // A literal operand takes the type of the other operand
+            ast::ExprKind::BinOp {
+                lhs,
+                op: ast::BinOp::Add | ast::BinOp::Sub | ast::BinOp::Mul | ast::BinOp::Div,
+                rhs,
+            } => match ast::int_literal_value(lhs) {
+                Some(_) => self.expr_type(rhs)?,
+                None => self.expr_type(lhs)?,
+            },
+            ast::ExprKind::BinOp { .. }
+            | ast::ExprKind::UnaryOp {
+                op: ast::UnaryOp::Not,
+                ..
+            } => ast::Type::Bool,
+            ast::ExprKind::UnaryOp { expr, .. } => match expr.kind {
+                ast::ExprKind::IntLit(value) => ast::int_literal_type(-i128::from(value)),
+                _ => self.expr_type(expr)?,
+            },
+            ast::ExprKind::FnCall { name, .. } => match self.functions.get(name) {
+                Some(return_type) => *return_type,
+                None => bail!("Function '{}' is not supported by the C backend", name),
+            },
+            ast::ExprKind::VarRef { name } | ast::ExprKind::IncDec { name, .. } => {
+                self.lookup(name)?.1
+            }
+            ast::ExprKind::Cast { r#type, .. } => *r#type,
+            ast::ExprKind::If {
+                then_branch,
+                else_branch,
+                ..
+            } => self.expr_type(if_arms(then_branch, else_branch)?.0)?,
+            ast::ExprKind::Sequence { exprs } => match exprs.last() {
+                Some(last) => self.expr_type(last)?,
+                None => bail!("Cannot infer the type of the expression"),
+            },
+            // Rejected with a better message once the expression itself is translated
+            _ => {
+                self.expr(expr)?;
+                bail!("Cannot infer the type of the expression")
+            }
+        };
+        Ok(ty)
+    }
+}
+
+/// The values of an if expression whose branches are a single expression each,
+/// which become a conditional operator
+fn if_arms<'b, 'a>(
+    then_branch: &'b [ast::Stmt<'a>],
+    else_branch: &'b [ast::Stmt<'a>],
+) -> Result<(&'b ast::Expr<'a>, &'b ast::Expr<'a>)> {
+    match (then_branch, else_branch) {
+        ([ast::Stmt::Expr { expr: then_expr }], [ast::Stmt::Expr { expr: else_expr }]) => {
+            Ok((then_expr.as_ref(), else_expr.as_ref()))
+        }
+        _ => bail!("If expressions with statements are not supported by the C backend"),
+    }
+}
+
+/// The C spelling of a type
+fn c_type(ty: ast::Type) -> Result<&'static str> {
+    Ok(match ty {
+        ast::Type::Bool => "bool",
+        ast::Type::I32 => "int32_t",
+        ast::Type::I64 => "int64_t",
+        ast::Type::F32 => "float",
+        ast::Type::F64 => "double",
+        ast::Type::Void => "void",
+        ast::Type::String => "const char *",
+        ast::Type::Struct(_) | ast::Type::Enum(_) => {
+            bail!("Structs and enums are not supported by the C backend")
+        }
+    })
+}
+
+/// The unsigned C type that arithmetic on an integer type goes through to wrap
+fn unsigned_type(ty: ast::Type) -> Option<&'static str> {
+    match ty {
+        ast::Type::I32 => Some("uint32_t"),
+        ast::Type::I64 => Some("uint64_t"),
+        _ => None,
+    }
+}
+
+/// Names that are keywords in C but not in AIC get a trailing underscore
+fn c_identifier(name: &str) -> String {
+    const KEYWORDS: &[&str] = &[
+        "auto", "bool", "case", "char", "const", "default", "double", "extern", "float", "for",
+        "goto", "inline", "int", "long", "register", "restrict", "short", "signed", "sizeof",
+        "static", "switch", "typedef", "union", "unsigned", "void", "volatile",
+    ];
+    if KEYWORDS.contains(&name) {
+        format!("{name}_")
+    } else {
+        name.to_string()
+    }
+}
+
+/// A C string literal. Bytes outside printable ASCII are written as three-digit octal escapes,
+/// which unlike `\x` escapes can't run into a following hex digit
+fn c_string(value: &str) -> String {
+    let mut out = String::from("\"");
+    for byte in value.bytes() {
+        match byte {
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            b'\n' => out.push_str("\\n"),
+            b'\t' => out.push_str("\\t"),
+            b' '..=b'~' => out.push(byte as char),
+            _ => write!(out, "\\{byte:03o}").unwrap(),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use indoc::indoc;
+
+    fn to_c(input: &str) -> Result<String> {
+        let program = parse(input).into_result().unwrap();
+        program_to_c(&program)
+    }
+
+    #[test]
+    fn test_function_and_main() {
+        let input = indoc! {"
+            fn add(a: i32, b: i32) -> i32 {
+                return a + b;
+            }
+            let x = add(1, 2);
+            x * 2
+        "};
+        assert_eq!(
+            to_c(input).unwrap(),
+            indoc! {"
+                #include <stdbool.h>
+                #include <stdint.h>
+
+                int32_t add(int32_t a, int32_t b);
+
+                int32_t add(int32_t a, int32_t b) {
+                    return ((int32_t)((uint32_t)a + (uint32_t)b));
+                }
+
+                int main(void) {
+                    int32_t x = add(1, 2);
+                    return ((int32_t)((uint32_t)x * (uint32_t)2));
+                }
+            "}
+        );
+    }
+
+    #[test]
+    fn test_shadowing_gets_fresh_names() {
+        let input = indoc! {"
+            let x = 1;
+            let x = x + 1.5 as i32;
+            x
+        "};
+        let c = to_c(input).unwrap();
+        assert!(c.contains("int32_t x = 1;"), "{c}");
+        assert!(
+            c.contains("int32_t x_1 = ((int32_t)((uint32_t)x + (uint32_t)((int32_t)1.5)));"),
+            "{c}"
+        );
+        assert!(c.contains("return x_1;"), "{c}");
+    }
+
+    #[test]
+    fn test_fresh_names_avoid_declared_names() {
+        let input = indoc! {"
+            let x = 1;
+            let x = x;
+            let x_1 = 3;
+            x == x_1
+        "};
+        let c = to_c(input).unwrap();
+        assert!(c.contains("int32_t x_2 = x;"), "{c}");
+        assert!(c.contains("int32_t x_1 = 3;"), "{c}");
+        assert!(c.contains("return (x_2 == x_1);"), "{c}");
+    }
+
+    #[test]
+    fn test_integer_arithmetic_wraps() {
+        let input = indoc! {"
+            let big: i64 = 1;
+            let x = -(2147483647 + 1);
+            let y = 1.5 * 2.0;
+            -big
+        "};
+        let c = to_c(input).unwrap();
+        assert!(
+            c.contains("int32_t x = ((int32_t)(-(uint32_t)((int32_t)((uint32_t)2147483647 + (uint32_t)1))));"),
+            "{c}"
+        );
+        assert!(c.contains("double y = (1.5 * 2.0);"), "{c}");
+        assert!(c.contains("return ((int64_t)(-(uint64_t)big));"), "{c}");
+    }
+
+    #[test]
+    fn test_string_escapes() {
+        assert_eq!(c_string("a\"b\\c\nd\0é"), r#""a\"b\\c\nd\000\303\251""#);
+    }
+
+    #[test]
+    fn test_unsupported() {
+        assert_eq!(
+            to_c("struct Point {\n    x: i32,\n}\n0")
+                .unwrap_err()
+                .to_string(),
+            "Structs and enums are not supported by the C backend"
+        );
+        assert_eq!(
+            to_c("pow(2, 3)").unwrap_err().to_string(),
+            "Function 'pow' is not supported by the C backend"
+        );
+    }
+}
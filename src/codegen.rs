@@ -1,40 +1,137 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::CString,
+    fmt::Write,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
 
 use anyhow::{Result, bail};
 use inkwell::{
+    AddressSpace,
     OptimizationLevel,
+    attributes::{Attribute, AttributeLoc},
+    basic_block::BasicBlock,
     context::Context,
-    module::Module,
-    targets::{CodeModel, InitializationConfig, RelocMode, Target, TargetMachine},
-    types::{BasicMetadataTypeEnum, BasicType, BasicTypeEnum}, // Import BasicType trait
-    values::{BasicValueEnum, PointerValue},
+    intrinsics::Intrinsic,
+    module::{Linkage, Module},
+    passes::PassBuilderOptions,
+    targets::{
+        CodeModel, InitializationConfig, RelocMode, Target, TargetData, TargetMachine, TargetTriple,
+    },
+    types::{AnyType, BasicMetadataTypeEnum, BasicType, BasicTypeEnum, FunctionType, StructType}, // Import BasicType trait
+    values::{
+        BasicMetadataValueEnum, BasicValue, BasicValueEnum, FloatValue, FunctionValue,
+        InstructionOpcode, IntValue, PointerValue,
+    },
 };
-
-use crate::ast;
-
+use serde::Serialize;
+
+use crate::{ast, diagnostic::Diagnostic};
+
+/// Optimization level of the target machine that emits object files
+pub const OPT_LEVEL: OptimizationLevel = OptimizationLevel::Default;
+
+/// `LLVMFastMathAll`: reassociation, no NaNs, no infinities, no signed zeros,
+/// reciprocals, contraction and approximate functions, printed as `fast`
+const FAST_MATH_ALL: u32 = 0x7F;
+
+/// LLVM passes that can be selected with `--passes`
+const SUPPORTED_PASSES: &[&str] = &[
+    "adce",
+    "dce",
+    "dse",
+    "early-cse",
+    "gvn",
+    "indvars",
+    "inline",
+    "instcombine",
+    "licm",
+    "loop-rotate",
+    "loop-unroll",
+    "mem2reg",
+    "reassociate",
+    "sccp",
+    "simplifycfg",
+    "sroa",
+    "tailcallelim",
+];
+
+#[derive(Clone, Copy)]
 struct VariableInfo<'ctx> {
     ptr: PointerValue<'ctx>,
     ty: BasicTypeEnum<'ctx>, // Store the type of the variable
     is_mutable: bool,
 }
 
+struct StructInfo<'ctx> {
+    ty: StructType<'ctx>,
+    fields: Vec<&'ctx str>, // Field names in declaration order
+    hoisted: bool,          // Declared ahead of time by `gen_program`
+}
+
+struct EnumInfo<'ctx> {
+    variants: HashMap<&'ctx str, i64>, // Discriminant of each variant
+    hoisted: bool,                     // Declared ahead of time by `gen_program`
+}
+
+/// Branch targets of the innermost enclosing loop
+struct LoopTarget<'ctx> {
+    continue_block: BasicBlock<'ctx>,
+    break_block: BasicBlock<'ctx>,
+}
+
+/// An enclosing labeled block and the values branching to its end
+struct BlockTarget<'ctx> {
+    label: &'ctx str,
+    merge_block: BasicBlock<'ctx>,
+    incoming: Vec<(BasicValueEnum<'ctx>, BasicBlock<'ctx>)>,
+}
+
 pub struct Env<'ctx> {
     scopes: Vec<HashMap<&'ctx str, VariableInfo<'ctx>>>,
+    fn_scopes: Vec<HashMap<&'ctx str, FunctionValue<'ctx>>>, // Nested functions visible in each scope
+    globals: HashMap<&'ctx str, VariableInfo<'ctx>>, // Top-level `var`s, visible in every function
 }
 
 impl<'ctx> Env<'ctx> {
     fn new() -> Self {
         Self {
             scopes: vec![HashMap::new()],
+            fn_scopes: vec![HashMap::new()],
+            globals: HashMap::new(),
         }
     }
 
+    /// Hide the variables of the enclosing function, except for the globals, returning them
+    /// so that `leave_function` can restore them
+    fn enter_function(&mut self) -> Vec<HashMap<&'ctx str, VariableInfo<'ctx>>> {
+        std::mem::replace(&mut self.scopes, vec![self.globals.clone()])
+    }
+
+    fn leave_function(&mut self, scopes: Vec<HashMap<&'ctx str, VariableInfo<'ctx>>>) {
+        self.scopes = scopes;
+    }
+
     fn push_scope(&mut self) {
         self.scopes.push(HashMap::new());
+        self.fn_scopes.push(HashMap::new());
     }
 
     fn pop_scope(&mut self) {
         self.scopes.pop();
+        self.fn_scopes.pop();
+    }
+
+    fn declare_fn(&mut self, name: &'ctx str, function: FunctionValue<'ctx>) {
+        self.fn_scopes.last_mut().unwrap().insert(name, function);
+    }
+
+    fn resolve_fn(&self, name: &str) -> Option<FunctionValue<'ctx>> {
+        self.fn_scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).copied())
     }
 
     fn declare_var(
@@ -71,6 +168,25 @@ impl<'ctx> Env<'ctx> {
         }
         bail!("Variable '{}' not found", name);
     }
+
+    /// Declare a mutable variable in the current scope that every function can also use
+    fn declare_global(
+        &mut self,
+        name: &'ctx str,
+        ptr: PointerValue<'ctx>,
+        ty: BasicTypeEnum<'ctx>,
+    ) -> Result<()> {
+        self.declare_var(name, ptr, ty, true)?;
+        self.globals.insert(
+            name,
+            VariableInfo {
+                ptr,
+                ty,
+                is_mutable: true,
+            },
+        );
+        Ok(())
+    }
 }
 
 /// Code generator for compiling AST to LLVM IR
@@ -79,6 +195,192 @@ pub struct CodeGen<'ctx> {
     module: Module<'ctx>,
     builder: inkwell::builder::Builder<'ctx>,
     env: Env<'ctx>,
+    structs: HashMap<&'ctx str, StructInfo<'ctx>>,
+    enums: HashMap<&'ctx str, EnumInfo<'ctx>>,
+    loops: Vec<LoopTarget<'ctx>>,
+    labeled_blocks: Vec<BlockTarget<'ctx>>,
+    fn_path: Vec<&'ctx str>,         // Names of the enclosing functions
+    global_vars: HashSet<ast::Span>, // Declaration spans of the top-level `var`s, which become globals
+    param_names: HashMap<FunctionValue<'ctx>, Vec<&'ctx str>>, // For matching named arguments
+    abis: HashMap<FunctionValue<'ctx>, FnAbi<'ctx>>, // How aggregates are passed to and from each function
+    target_triple: TargetTriple,
+    target_data: Option<TargetData>,
+    reloc_mode: RelocMode,
+    code_model: CodeModel,
+    c_truthiness: bool,     // Whether integer conditions are compared against zero
+    floor_div: bool,        // Whether integer division rounds toward negative infinity
+    wrap_exit_code: bool,   // Whether main's return value is masked to 0-255
+    checked_arith: bool,    // Whether overflowing integer negation traps
+    fast_math: bool,        // Whether float instructions carry every fast-math flag
+    sanitize_address: bool, // Whether functions are instrumented by AddressSanitizer
+    entry: String,          // Name of the function holding the top-level statements
+    no_std_main: bool,      // Whether the synthetic main is left out
+    profile_generate: bool, // Whether object files count how often each block runs
+    profile_use: Option<PathBuf>, // Profile that guides the optimization of object files
+    source_name: String,    // File named in runtime messages such as failed assertions
+    source: &'ctx str,      // Source text the AST's spans point into
+}
+
+/// The profile LLVM's `pgo-instr-use` pass reads, once set for the process
+static PGO_PROFILE_FILE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Point LLVM's `pgo-instr-use` pass at a profile. LLVM's C API has no PGO options, so
+/// the path is a process-wide command line option, which LLVM exits on if it is given twice.
+/// It is therefore parsed only once, and every later profile must be the same file
+fn set_pgo_profile_file(path: &Path) -> Result<()> {
+    let args = [
+        CString::new("aic")?,
+        CString::new(format!("-pgo-test-profile-file={}", path.display()))?,
+    ];
+    let current = PGO_PROFILE_FILE.get_or_init(|| {
+        let argv: Vec<_> = args.iter().map(|arg| arg.as_ptr()).collect();
+        let overview = CString::default();
+        // SAFETY: the strings outlive the call, and LLVM copies the option's value.
+        // `OnceLock` keeps other threads from parsing options at the same time
+        unsafe {
+            inkwell::llvm_sys::support::LLVMParseCommandLineOptions(
+                argv.len() as i32,
+                argv.as_ptr(),
+                overview.as_ptr(),
+            );
+        }
+        path.to_path_buf()
+    });
+    if current != path {
+        bail!(
+            "Cannot use profile '{}', only one profile can be used per process and '{}' already is",
+            path.display(),
+            current.display()
+        );
+    }
+    Ok(())
+}
+
+/// Structs larger than this many bytes are passed and returned through pointers,
+/// as the C calling conventions of x86-64 and AArch64 do. x86-64 passes a copy on
+/// the stack (`byval`), while AArch64 passes a pointer to a copy the caller made
+const MAX_DIRECT_AGGREGATE_SIZE: u64 = 16;
+
+/// How a function passes its large struct parameters and return value
+#[derive(Debug, Clone, Default)]
+struct FnAbi<'ctx> {
+    sret: Option<BasicTypeEnum<'ctx>>, // Returned through a pointer passed as the first argument
+    byval: Vec<Option<BasicTypeEnum<'ctx>>>, // Parameters passed as a pointer to a copy
+}
+
+impl FnAbi<'_> {
+    /// Whether every parameter and the return value are passed directly
+    fn is_direct(&self) -> bool {
+        self.sret.is_none() && self.byval.iter().all(Option::is_none)
+    }
+}
+
+/// A function in the generated module
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Symbol {
+    /// The symbol name, with nested functions mangled as `outer$inner`
+    pub name: String,
+    /// The LLVM function type, e.g. `i32 (i32, i32)`
+    pub signature: String,
+    /// Whether the function has a body, as opposed to an external declaration
+    pub defined: bool,
+}
+
+/// Strip what doesn't matter when reading textual LLVM IR: the module header,
+/// attribute groups and their uses, metadata and alignments
+pub fn clean_ir(ir: &str) -> String {
+    const NOISE: [&str; 7] = [
+        "; ModuleID",
+        "source_filename",
+        "target datalayout",
+        "target triple",
+        "attributes #",
+        "; Function Attrs",
+        "!",
+    ];
+    let mut out = String::with_capacity(ir.len());
+    for line in ir.lines() {
+        if NOISE.iter().any(|prefix| line.starts_with(prefix)) {
+            continue;
+        }
+        // Leave string constants alone, in which a `"` is escaped as `\22`
+        let line = line
+            .split('"')
+            .enumerate()
+            .map(|(i, part)| {
+                if i % 2 == 0 {
+                    clean_ir_code(part)
+                } else {
+                    part.to_string()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\"");
+        // Keep at most one blank line in a row, where the removed lines leave several
+        if line.trim().is_empty() && (out.is_empty() || out.ends_with("\n\n")) {
+            continue;
+        }
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out.truncate(out.trim_end().len());
+    out.push('\n');
+    out
+}
+
+/// Remove alignments and attribute group references from IR outside of string constants
+fn clean_ir_code(code: &str) -> String {
+    let mut code = code.to_string();
+    while let Some(start) = code.find(", align ") {
+        let digits = start + ", align ".len();
+        let end = code[digits..]
+            .find(|c: char| !c.is_ascii_digit())
+            .map_or(code.len(), |i| digits + i);
+        code.replace_range(start..end, "");
+    }
+    code.split(' ')
+        .filter(|word| {
+            !(word.len() > 1
+                && word.starts_with('#')
+                && word[1..].bytes().all(|b| b.is_ascii_digit()))
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// List the host triple and every target the linked LLVM supports, marking the host's target
+pub fn list_targets() -> String {
+    Target::initialize_all(&InitializationConfig::default());
+    let host_triple = TargetMachine::get_default_triple();
+    let host = Target::from_triple(&host_triple).ok();
+
+    let mut targets = Vec::new();
+    let mut next = Target::get_first();
+    while let Some(target) = next {
+        next = target.get_next();
+        targets.push(target);
+    }
+    let width = targets
+        .iter()
+        .map(|target| target.get_name().to_bytes().len())
+        .max()
+        .unwrap_or(0);
+
+    let mut out = format!(
+        "Host triple: {}\n\nRegistered targets:\n",
+        host_triple.as_str().to_string_lossy()
+    );
+    for target in &targets {
+        let name = target.get_name().to_string_lossy();
+        let description = target.get_description().to_string_lossy();
+        let marker = if host.as_ref() == Some(target) {
+            " (host)"
+        } else {
+            ""
+        };
+        out.push_str(&format!("  {name:<width$} - {description}{marker}\n"));
+    }
+    out
 }
 
 impl<'ctx> CodeGen<'ctx> {
@@ -87,25 +389,209 @@ impl<'ctx> CodeGen<'ctx> {
         let module = context.create_module(module_name);
         let builder = context.create_builder();
         let env = Env::new();
-        Self {
+        let mut codegen = Self {
             context,
             module,
             builder,
             env,
-        }
+            structs: HashMap::new(),
+            enums: HashMap::new(),
+            loops: Vec::new(),
+            labeled_blocks: Vec::new(),
+            fn_path: Vec::new(),
+            global_vars: HashSet::new(),
+            param_names: HashMap::new(),
+            abis: HashMap::new(),
+            target_triple: TargetMachine::get_default_triple(),
+            target_data: None,
+            reloc_mode: RelocMode::Default,
+            code_model: CodeModel::Default,
+            c_truthiness: false,
+            floor_div: false,
+            wrap_exit_code: false,
+            checked_arith: false,
+            fast_math: false,
+            sanitize_address: false,
+            entry: "main".to_string(),
+            no_std_main: false,
+            profile_generate: false,
+            profile_use: None,
+            source_name: module_name.to_string(),
+            source: "",
+        };
+        // Only the layout is needed before emission, so the target machine isn't kept
+        codegen.target_data = codegen
+            .create_target_machine()
+            .ok()
+            .map(|target_machine| target_machine.get_target_data());
+        codegen
+    }
+
+    /// Set the relocation model, e.g. `RelocMode::PIC` for shared libraries
+    pub fn set_reloc_mode(&mut self, reloc_mode: RelocMode) {
+        self.reloc_mode = reloc_mode;
+    }
+
+    /// Set the code model, which limits how far apart code and data may be placed
+    pub fn set_code_model(&mut self, code_model: CodeModel) {
+        self.code_model = code_model;
+    }
+
+    /// Accept integer conditions, which are true when non-zero (as in C)
+    pub fn set_c_truthiness(&mut self, c_truthiness: bool) {
+        self.c_truthiness = c_truthiness;
+    }
+
+    /// Round integer division toward negative infinity instead of toward zero
+    pub fn set_floor_div(&mut self, floor_div: bool) {
+        self.floor_div = floor_div;
+    }
+
+    /// Mask the value returned from main with `0xFF`, so the exit code is the
+    /// same 0-255 value on every platform (as with `exit` in C)
+    pub fn set_wrap_exit_code(&mut self, wrap_exit_code: bool) {
+        self.wrap_exit_code = wrap_exit_code;
+    }
+
+    /// Trap when negating an integer overflows, i.e. for its minimum value,
+    /// instead of wrapping around to the same value
+    pub fn set_checked_arith(&mut self, checked_arith: bool) {
+        self.checked_arith = checked_arith;
+    }
+
+    /// Mark float instructions with the fast-math flags, allowing LLVM to reorder them
+    /// and assume there are no NaNs or infinities, which may change their results
+    pub fn set_fast_math(&mut self, fast_math: bool) {
+        self.fast_math = fast_math;
+    }
+
+    /// Instrument the generated functions with AddressSanitizer. The object file
+    /// must then be linked with the ASan runtime, e.g. `cc -fsanitize=address`
+    pub fn set_sanitize_address(&mut self, sanitize_address: bool) {
+        self.sanitize_address = sanitize_address;
+    }
+
+    /// Name the entry function `entry` instead of `main`, e.g. `_start` for freestanding
+    /// code. The linker must then be told the entry point, e.g. `ld -e _start`, and the
+    /// program must end by calling `exit`, since the entry point has no caller to return to
+    pub fn set_entry(&mut self, entry: &str) {
+        self.entry = entry.to_string();
+    }
+
+    /// Leave out the synthetic `main` for freestanding code, so that the program must
+    /// define its own entry function, e.g. `_start`, and may only declare at the top level
+    pub fn set_no_std_main(&mut self, no_std_main: bool) {
+        self.no_std_main = no_std_main;
+    }
+
+    /// Instrument the object file to write an execution profile when it runs.
+    /// It must be linked with the profile runtime, e.g. `cc -fprofile-generate`
+    pub fn set_profile_generate(&mut self, profile_generate: bool) {
+        self.profile_generate = profile_generate;
+    }
+
+    /// Optimize the object file with a profile merged by `llvm-profdata` from
+    /// the runs of a `set_profile_generate` build of the same program
+    pub fn set_profile_use(&mut self, profile: Option<&Path>) {
+        self.profile_use = profile.map(Path::to_path_buf);
+    }
+
+    /// Set the file the program was read from, so that runtime messages such as
+    /// failed assertions can tell the line they come from
+    pub fn set_source(&mut self, name: &str, source: &'ctx str) {
+        self.source_name = name.to_string();
+        self.source = source;
+    }
+
+    /// Generate code for the given target triple instead of the host
+    pub fn set_target(&mut self, triple: &str) -> Result<()> {
+        self.target_triple = TargetTriple::create(triple);
+        let target_data = self.create_target_machine()?.get_target_data();
+        self.module.set_triple(&self.target_triple);
+        self.module.set_data_layout(&target_data.get_data_layout());
+        self.target_data = Some(target_data);
+        Ok(())
     }
 
     /// Compile the program and return the resulting module
     pub fn compile(&mut self, program: &'ctx ast::Program) -> Result<()> {
-        // Create a main function
-        let i32_type = self.context.i32_type();
-        let fn_type = i32_type.fn_type(&[], false);
-        let function = self.module.add_function("main", fn_type, None);
-        let basic_block = self.context.append_basic_block(function, "entry");
-        self.builder.position_at_end(basic_block);
+        if self.no_std_main {
+            // Without a synthetic main there is no function for other statements to run in
+            let only_declarations = program.statements.iter().all(|stmt| {
+                matches!(
+                    stmt,
+                    ast::Stmt::FnDecl { .. }
+                        | ast::Stmt::ExternDecl { .. }
+                        | ast::Stmt::StructDecl { .. }
+                        | ast::Stmt::EnumDecl { .. }
+                )
+            });
+            if !only_declarations {
+                bail!("Only declarations are allowed at the top level without a synthetic main");
+            }
+            self.gen_program(program)?;
+
+            // The program's own entry point, `_start` unless renamed, is jumped to without a
+            // return address on the stack, so like a renamed synthetic main it realigns the stack
+            let entry = if self.entry == "main" {
+                "_start"
+            } else {
+                self.entry.as_str()
+            };
+            if let Some(function) = self.module.get_function(entry) {
+                function.add_attribute(
+                    AttributeLoc::Function,
+                    self.context.create_string_attribute("stackrealign", ""),
+                );
+            }
+
+            // There is no unwinder to unwind through freestanding code
+            let kind = Attribute::get_named_enum_kind_id("nounwind");
+            for function in self.module.get_functions() {
+                if function.count_basic_blocks() > 0 {
+                    function.add_attribute(
+                        AttributeLoc::Function,
+                        self.context.create_enum_attribute(kind, 0),
+                    );
+                }
+            }
+        } else {
+            // Create the entry function, `main` unless renamed
+            let i32_type = self.context.i32_type();
+            let fn_type = i32_type.fn_type(&[], false);
+            let function = self.module.add_function(&self.entry, fn_type, None);
+            // An entry point other than `main` is jumped to, without a return address on the
+            // stack, so it aligns the stack itself before calling anything
+            if self.entry != "main" {
+                function.add_attribute(
+                    AttributeLoc::Function,
+                    self.context.create_string_attribute("stackrealign", ""),
+                );
+            }
+            let basic_block = self.context.append_basic_block(function, "entry");
+            self.builder.position_at_end(basic_block);
+
+            // Generate code for the program
+            self.gen_program(program)?;
 
-        // Generate code for the program
-        self.gen_program(program)?;
+            // A program that doesn't end with a value, e.g. `5;`, exits with 0
+            if !self.is_terminated() {
+                self.build_return(Some(i32_type.const_zero().into()))?;
+            }
+        }
+
+        // Only functions with a body are instrumented, not external declarations
+        if self.sanitize_address {
+            let kind = Attribute::get_named_enum_kind_id("sanitize_address");
+            for function in self.module.get_functions() {
+                if function.count_basic_blocks() > 0 {
+                    function.add_attribute(
+                        AttributeLoc::Function,
+                        self.context.create_enum_attribute(kind, 0),
+                    );
+                }
+            }
+        }
 
         // Verify the module
         if self.module.verify().is_err() {
@@ -122,58 +608,171 @@ impl<'ctx> CodeGen<'ctx> {
 
     /// Generate LLVM IR for a program
     pub fn gen_program(&mut self, program: &'ctx ast::Program) -> Result<()> {
+        // Hoist types and function prototypes so that declaration order doesn't matter.
+        // Nested functions are hoisted too, so that the module lists every function in
+        // source order rather than in the order the bodies are generated
+        let mut declarations = Vec::new();
+        nested_stmts(&program.statements, &[], &mut declarations);
+        for (_, stmt) in &declarations {
+            if let ast::Stmt::EnumDecl { name, variants, .. } = stmt {
+                self.declare_enum(name, variants, true)?;
+            }
+        }
+        for (_, stmt) in &declarations {
+            if let ast::Stmt::StructDecl { name, fields, .. } = stmt {
+                self.declare_struct(name, fields, true)?;
+            }
+        }
+        for (fn_path, stmt) in &declarations {
+            let top_level = program
+                .statements
+                .as_ptr_range()
+                .contains(&std::ptr::from_ref(*stmt));
+            let (name, params, r#type) = match stmt {
+                ast::Stmt::FnDecl {
+                    name,
+                    params,
+                    r#type,
+                    ..
+                } => (name, params, return_type(name, *r#type)?),
+                // Nested external functions are declared where they are, to be found by name
+                ast::Stmt::ExternDecl {
+                    name,
+                    params,
+                    r#type,
+                    ..
+                } if top_level => (name, params, *r#type),
+                _ => continue,
+            };
+            if top_level {
+                if self.module.get_function(name).is_some() {
+                    bail!("Function '{}' already declared", name);
+                }
+                self.declare_function(name, params, r#type)?;
+                continue;
+            }
+            // Mangled like in `gen_stmt`. A function declared twice in the same scope
+            // is reported when its second body is generated
+            let symbol = match fn_path.as_slice() {
+                [] => name.to_string(),
+                _ => format!("{}${}", fn_path.join("$"), name),
+            };
+            if self.module.get_function(&symbol).is_none() {
+                self.declare_function(&symbol, params, r#type)?;
+            }
+        }
+        for stmt in &program.statements {
+            if let ast::Stmt::VarDecl { span, .. } = stmt {
+                self.global_vars.insert(span.clone());
+            }
+        }
+
         self.gen_block(&program.statements, true)
     }
 
     /// Generate LLVM IR for a block
-    pub fn gen_block(&mut self, stmts: &'ctx Vec<ast::Stmt>, is_last_block: bool) -> Result<()> {
+    pub fn gen_block(&mut self, stmts: &'ctx [ast::Stmt], is_last_block: bool) -> Result<()> {
         self.env.push_scope();
+        self.gen_stmts(stmts, is_last_block)?;
+        self.env.pop_scope();
+        Ok(())
+    }
+
+    /// Generate LLVM IR for a sequence of statements in the current scope
+    fn gen_stmts(&mut self, stmts: &'ctx [ast::Stmt], is_last_block: bool) -> Result<()> {
         for (i, stmt) in stmts.iter().enumerate() {
+            // Nothing after a `return` can run, and LLVM doesn't allow instructions after a
+            // terminator, so only declarations (which don't emit into this block) are generated
+            let is_declaration = matches!(
+                stmt,
+                ast::Stmt::FnDecl { .. }
+                    | ast::Stmt::ExternDecl { .. }
+                    | ast::Stmt::StructDecl { .. }
+                    | ast::Stmt::EnumDecl { .. }
+            );
+            if self.is_terminated() && !is_declaration {
+                continue;
+            }
+
             let is_last_stmt = is_last_block && (i == stmts.len() - 1);
             self.gen_stmt(stmt, is_last_stmt)?;
         }
-        self.env.pop_scope();
         Ok(())
     }
 
+    /// Whether the current block already ends in a terminator
+    fn is_terminated(&self) -> bool {
+        self.builder
+            .get_insert_block()
+            .and_then(|block| block.get_terminator())
+            .is_some()
+    }
+
     /// Generate LLVM IR for a statement
     fn gen_stmt(&mut self, stmt: &'ctx ast::Stmt, is_last_stmt: bool) -> Result<()> {
         match stmt {
             ast::Stmt::FnDecl {
+                attributes,
                 name,
                 params,
                 r#type,
                 body,
+                ..
             } => {
-                let initial_pos = self.builder.get_insert_block().unwrap();
-
-                // Create function type
-                let param_types: Vec<BasicMetadataTypeEnum> = params
-                    .iter()
-                    .map(|param| self.map_ast_type_to_llvm(param.r#type).map(|t| t.into()))
-                    .collect::<Result<Vec<_>, _>>()?;
-
-                let fn_type = match self.map_ast_type_to_llvm(*r#type) {
-                    Ok(ty) => ty.fn_type(&param_types, false),
-                    Err(_) if *r#type == ast::Type::Void => {
-                        self.context.void_type().fn_type(&param_types, false)
+                // Without a synthetic main, top-level functions aren't declared inside another
+                let initial_pos = self.builder.get_insert_block();
+                let r#type = return_type(name, *r#type)?;
+
+                let function = if self.fn_path.is_empty() {
+                    // Reuse the prototype if the function was hoisted by `gen_program`
+                    match self.module.get_function(name) {
+                        Some(function) if function.count_basic_blocks() == 0 => function,
+                        Some(_) => bail!("Function '{}' already declared", name),
+                        None => self.declare_function(name, params, r#type)?,
                     }
-                    Err(e) => return Err(e),
+                } else {
+                    // Mangle nested functions with the names of their enclosing functions,
+                    // so that same-named helpers in different functions don't collide
+                    let symbol = format!("{}${}", self.fn_path.join("$"), name);
+                    let function = match self.module.get_function(&symbol) {
+                        Some(function) if function.count_basic_blocks() == 0 => function,
+                        Some(_) => bail!("Function '{}' already declared", name),
+                        None => self.declare_function(&symbol, params, r#type)?,
+                    };
+                    self.env.declare_fn(name, function);
+                    function
                 };
-
-                let function = self.module.add_function(name, fn_type, None);
+                for attribute in attributes {
+                    let kind = Attribute::get_named_enum_kind_id(match attribute {
+                        ast::FnAttribute::Inline => "alwaysinline",
+                        ast::FnAttribute::NoInline => "noinline",
+                    });
+                    function.add_attribute(
+                        AttributeLoc::Function,
+                        self.context.create_enum_attribute(kind, 0),
+                    );
+                }
 
                 // Create basic block for the function
                 let basic_block = self.context.append_basic_block(function, "entry");
                 self.builder.position_at_end(basic_block);
 
                 // Allocate space for parameters and store initial values
+                let abi = self.abis[&function].clone();
+                let outer_scopes = self.env.enter_function();
                 self.env.push_scope(); // Push scope for function parameters
-                for (i, param) in function.get_param_iter().enumerate() {
+                let offset = usize::from(abi.sret.is_some());
+                for (i, param) in function.get_param_iter().skip(offset).enumerate() {
                     let ast_param = &params[i];
                     let param_type = self.map_ast_type_to_llvm(ast_param.r#type)?;
-                    let alloca = self.builder.build_alloca(param_type, ast_param.name)?;
-                    self.builder.build_store(alloca, param)?;
+                    // A struct passed by value already points to the callee's own copy
+                    let alloca = if abi.byval[i].is_some() {
+                        param.into_pointer_value()
+                    } else {
+                        let alloca = self.builder.build_alloca(param_type, ast_param.name)?;
+                        self.builder.build_store(alloca, param)?;
+                        alloca
+                    };
                     self.env
                         .declare_var(ast_param.name, alloca, param_type, false) // Pass param_type
                         .map_err(|e| {
@@ -186,47 +785,233 @@ impl<'ctx> CodeGen<'ctx> {
                 }
 
                 // Generate code for the function body
+                self.fn_path.push(name);
                 self.gen_block(body, true)?;
+                self.fn_path.pop();
+
+                // Void functions may fall off the end of their body, including an empty body
+                let current_block = self.builder.get_insert_block().unwrap();
+                if r#type == ast::Type::Void && current_block.get_terminator().is_none() {
+                    self.build_return(None)?;
+                }
 
                 self.env.pop_scope(); // Pop scope for function parameters
+                self.env.leave_function(outer_scopes);
 
                 // Change the position of the builder back to the initial position
-                self.builder.position_at_end(initial_pos);
+                if let Some(initial_pos) = initial_pos {
+                    self.builder.position_at_end(initial_pos);
+                }
+            }
+            ast::Stmt::ExternDecl {
+                name,
+                params,
+                r#type,
+                ..
+            } => {
+                // External functions keep their own name even when nested, so the linker finds them
+                let function = match self.module.get_function(name) {
+                    Some(function) if function.count_basic_blocks() == 0 => function,
+                    Some(_) => bail!("Function '{}' already declared", name),
+                    None => self.declare_function(name, params, *r#type)?,
+                };
+                if !self.fn_path.is_empty() {
+                    self.env.declare_fn(name, function);
+                }
             }
-            ast::Stmt::Return { expr } => match expr {
-                Some(expr) => {
-                    let value = self.gen_expr(expr)?;
+            ast::Stmt::StructDecl { name, fields, .. } => {
+                // Unless declared inside an expression, structs were already declared by `gen_program`
+                match self.structs.get_mut(name) {
+                    Some(struct_info) if struct_info.hoisted => struct_info.hoisted = false,
+                    _ => self.declare_struct(name, fields, false)?,
+                }
+            }
+            ast::Stmt::EnumDecl { name, variants, .. } => {
+                // Unless declared inside an expression, enums were already declared by `gen_program`
+                match self.enums.get_mut(name) {
+                    Some(enum_info) if enum_info.hoisted => enum_info.hoisted = false,
+                    _ => self.declare_enum(name, variants, false)?,
+                }
+            }
+            ast::Stmt::While { condition, body } => {
+                let function = self
+                    .builder
+                    .get_insert_block()
+                    .unwrap()
+                    .get_parent()
+                    .unwrap();
+
+                let cond_block = self.context.append_basic_block(function, "whilecond");
+                let body_block = self.context.append_basic_block(function, "whilebody");
+                let end_block = self.context.append_basic_block(function, "whileend");
+
+                self.builder
+                    .build_unconditional_branch(cond_block)
+                    .map_err(|e| anyhow::anyhow!("Failed to build unconditional branch: {}", e))?;
+
+                // Generate condition code
+                self.builder.position_at_end(cond_block);
+                let condition_value = self.gen_condition(condition)?;
+                self.builder
+                    .build_conditional_branch(condition_value, body_block, end_block)
+                    .map_err(|e| anyhow::anyhow!("Failed to build conditional branch: {}", e))?;
+
+                // Generate body code
+                self.builder.position_at_end(body_block);
+                self.loops.push(LoopTarget {
+                    continue_block: cond_block,
+                    break_block: end_block,
+                });
+                let result = self.gen_block(body, false);
+                self.loops.pop();
+                result?;
+
+                // Jump back to the condition if there's no terminator
+                if self
+                    .builder
+                    .get_insert_block()
+                    .unwrap()
+                    .get_terminator()
+                    .is_none()
+                {
                     self.builder
-                        .build_return(Some(&value))
-                        .map_err(|e| anyhow::anyhow!("Failed to build return: {}", e))?;
+                        .build_unconditional_branch(cond_block)
+                        .map_err(|e| {
+                            anyhow::anyhow!("Failed to build unconditional branch: {}", e)
+                        })?;
                 }
-                None => {
+
+                self.builder.position_at_end(end_block);
+            }
+            ast::Stmt::DoWhile { body, condition } => {
+                let function = self
+                    .builder
+                    .get_insert_block()
+                    .unwrap()
+                    .get_parent()
+                    .unwrap();
+
+                let body_block = self.context.append_basic_block(function, "dobody");
+                let cond_block = self.context.append_basic_block(function, "docond");
+                let end_block = self.context.append_basic_block(function, "doend");
+
+                // The body is entered unconditionally
+                self.builder
+                    .build_unconditional_branch(body_block)
+                    .map_err(|e| anyhow::anyhow!("Failed to build unconditional branch: {}", e))?;
+
+                // Generate body code; `continue` re-checks the condition
+                self.builder.position_at_end(body_block);
+                self.loops.push(LoopTarget {
+                    continue_block: cond_block,
+                    break_block: end_block,
+                });
+                let result = self.gen_block(body, false);
+                self.loops.pop();
+                result?;
+
+                // Fall through to the condition if there's no terminator
+                if self
+                    .builder
+                    .get_insert_block()
+                    .unwrap()
+                    .get_terminator()
+                    .is_none()
+                {
                     self.builder
-                        .build_return(None)
-                        .map_err(|e| anyhow::anyhow!("Failed to build return: {}", e))?;
+                        .build_unconditional_branch(cond_block)
+                        .map_err(|e| {
+                            anyhow::anyhow!("Failed to build unconditional branch: {}", e)
+                        })?;
                 }
-            },
+
+                // Generate condition code
+                self.builder.position_at_end(cond_block);
+                let condition_value = self.gen_condition(condition)?;
+                self.builder
+                    .build_conditional_branch(condition_value, body_block, end_block)
+                    .map_err(|e| anyhow::anyhow!("Failed to build conditional branch: {}", e))?;
+
+                self.builder.position_at_end(end_block);
+            }
+            ast::Stmt::Break {
+                label: Some(label),
+                value,
+                ..
+            } => {
+                let value = value
+                    .as_ref()
+                    .ok_or_else(|| anyhow::anyhow!("Break from block '{} needs a value", label))?;
+                let value = self.gen_expr(value)?;
+                let Some(index) = self
+                    .labeled_blocks
+                    .iter()
+                    .rposition(|target| target.label == *label)
+                else {
+                    bail!("Unknown label '{}", label);
+                };
+                self.yield_from_block(index, value);
+                let merge_block = self.labeled_blocks[index].merge_block;
+                self.build_jump(merge_block)?;
+            }
+            ast::Stmt::Break { span, .. } => {
+                let Some(target) = self.loops.last() else {
+                    bail!(Diagnostic::error("`break` outside of a loop", span.clone()));
+                };
+                let break_block = target.break_block;
+                self.build_jump(break_block)?;
+            }
+            ast::Stmt::Continue { span } => {
+                let Some(target) = self.loops.last() else {
+                    bail!(Diagnostic::error(
+                        "`continue` outside of a loop",
+                        span.clone()
+                    ));
+                };
+                let continue_block = target.continue_block;
+                self.build_jump(continue_block)?;
+            }
+            ast::Stmt::Block { body } => self.gen_block(body, is_last_stmt)?,
+            ast::Stmt::Return { expr, .. } => {
+                // Returning the result of a void call returns nothing
+                let value = match expr {
+                    Some(expr) => self.gen_returned_expr(expr)?,
+                    None => None,
+                };
+                self.build_return(value)?;
+            }
             ast::Stmt::ExprStmt { expr } => {
-                self.gen_expr(expr)?;
+                self.gen_optional_expr(expr)?;
             }
             ast::Stmt::Expr { expr } => {
-                let value = self.gen_expr(expr)?;
+                let value = self.gen_returned_expr(expr)?;
 
                 // Stmt::Expr can only exist at the end of a block, so it's safe to return this value
                 // The fact that it only exists at the end is defined in the parser's grammar, so we don't need to check it again here
-                self.builder
-                    .build_return(Some(&value))
-                    .map_err(|e| anyhow::anyhow!("Failed to build return: {}", e))?;
+                self.build_return(value)?;
+            }
+            ast::Stmt::LetDecl {
+                name,
+                r#type: None,
+                value: Some(value),
+                ..
+            } if *name == "_" => {
+                // `let _ = expr;` evaluates and discards the value, which may be void
+                self.gen_optional_expr(value)?;
             }
             ast::Stmt::LetDecl {
                 name,
                 r#type,
                 value,
+                ..
             } => {
-                let initial_value = if let Some(val_expr) = value {
-                    self.gen_expr(val_expr)?
-                } else {
-                    bail!("Initial value required for let declaration");
+                let initial_value = match (value, r#type) {
+                    (Some(val_expr), Some(ty)) => {
+                        let llvm_type = self.map_ast_type_to_llvm(*ty)?;
+                        self.gen_expr_as(val_expr, llvm_type)?
+                    }
+                    (Some(val_expr), None) => self.gen_expr(val_expr)?,
+                    (None, _) => bail!("Initial value required for let declaration"),
                 };
 
                 let var_type = initial_value.get_type();
@@ -234,9 +1019,9 @@ impl<'ctx> CodeGen<'ctx> {
                     let llvm_type = self.map_ast_type_to_llvm(*ty)?;
                     if var_type != llvm_type {
                         bail!(
-                            "Type mismatch in let declaration: expected {:?}, found {:?}",
-                            llvm_type,
-                            var_type
+                            "Type mismatch in let declaration: expected {}, found {}",
+                            ty,
+                            self.type_name(var_type)
                         );
                     }
                 }
@@ -253,9 +1038,16 @@ impl<'ctx> CodeGen<'ctx> {
                 name,
                 r#type,
                 value,
+                span,
             } => {
                 let initial_value = if let Some(val_expr) = value {
-                    self.gen_expr(val_expr)?
+                    match r#type {
+                        Some(ty) => {
+                            let llvm_type = self.map_ast_type_to_llvm(*ty)?;
+                            self.gen_expr_as(val_expr, llvm_type)?
+                        }
+                        None => self.gen_expr(val_expr)?,
+                    }
                 } else {
                     // Determine type and get default value if no initial value provided
                     let ty = r#type.ok_or_else(|| {
@@ -267,6 +1059,28 @@ impl<'ctx> CodeGen<'ctx> {
                 };
 
                 let var_type = initial_value.get_type();
+
+                // Top-level variables are globals, so that functions can use them too
+                if self.global_vars.contains(span) {
+                    let global = self.module.add_global(var_type, None, name);
+                    global.set_linkage(Linkage::Internal);
+                    // A constant is the global's initial value, anything else is stored when `main`
+                    // reaches the declaration, so functions called before then see zero
+                    if initial_value.as_instruction_value().is_none() {
+                        global.set_initializer(&initial_value);
+                    } else {
+                        global.set_initializer(&var_type.const_zero());
+                        self.builder
+                            .build_store(global.as_pointer_value(), initial_value)?;
+                    }
+                    self.env
+                        .declare_global(name, global.as_pointer_value(), var_type)
+                        .map_err(|e| {
+                            anyhow::anyhow!("Failed to declare variable '{}': {}", name, e)
+                        })?;
+                    return Ok(());
+                }
+
                 let ptr = self.builder.build_alloca(var_type, name)?;
                 self.builder.build_store(ptr, initial_value)?;
 
@@ -275,20 +1089,22 @@ impl<'ctx> CodeGen<'ctx> {
                     .declare_var(name, ptr, var_type, true) // Pass var_type
                     .map_err(|e| anyhow::anyhow!("Failed to declare variable '{}': {}", name, e))?;
             }
-            ast::Stmt::Assign { name, value } => {
-                let new_value = self.gen_expr(value)?;
-                let var_info = self.env.resolve_var(name)?;
+            ast::Stmt::Assign { name, value, .. } => {
+                let var_info = *self.env.resolve_var(name)?;
 
                 if !var_info.is_mutable {
                     bail!("Cannot assign to immutable variable '{}'", name);
                 }
 
-                // Load the existing value's type to ensure type match
-                let current_value =
-                    self.builder
-                        .build_load(var_info.ty, var_info.ptr, "loadtmp")?; // Use stored type
-                if new_value.get_type() != current_value.get_type() {
-                    bail!("Type mismatch in assignment to variable '{}'", name);
+                // A literal takes the type of the variable
+                let new_value = self.gen_expr_as(value, var_info.ty)?;
+                if new_value.get_type() != var_info.ty {
+                    bail!(
+                        "Type mismatch in assignment to variable '{}': expected {}, found {}",
+                        name,
+                        self.type_name(var_info.ty),
+                        self.type_name(new_value.get_type())
+                    );
                 }
 
                 self.builder.build_store(var_info.ptr, new_value)?;
@@ -298,6 +1114,13 @@ impl<'ctx> CodeGen<'ctx> {
                 then_branch,
                 else_branch,
             } => {
+                // `if c { a } else { b }` with side-effect free arms needs no branches
+                if let Some((then_expr, else_expr)) =
+                    select_arms(then_branch, else_branch.as_deref())
+                {
+                    return self.gen_select(condition, then_expr, else_expr, is_last_stmt);
+                }
+
                 // Get the current function
                 let function = self
                     .builder
@@ -316,15 +1139,7 @@ impl<'ctx> CodeGen<'ctx> {
                 let merge_block = self.context.append_basic_block(function, "ifcont");
 
                 // Generate condition code
-                let condition_value = self.gen_expr(condition)?;
-
-                // Convert the condition to i1 (boolean) type
-                let condition_value = if condition_value.is_int_value() {
-                    condition_value.into_int_value()
-                } else {
-                    // Todo support other types
-                    bail!("Condition must be an i1 (boolean) value");
-                };
+                let condition_value = self.gen_condition(condition)?;
 
                 // Build the conditional branch
                 self.builder
@@ -379,27 +1194,29 @@ impl<'ctx> CodeGen<'ctx> {
                     }
                 }
 
-                if is_last_stmt {
+                // Every branch of a final `if` usually returns, leaving the merge block unreachable
+                if is_last_stmt && merge_block.get_first_use().is_none() {
                     merge_block.remove_from_function().map_err(|_| {
                         anyhow::anyhow!("Failed to remove merge block from function")
                     })?;
+                } else {
+                    // Position the builder at the merge block
+                    self.builder.position_at_end(merge_block);
                 }
-
-                // Position the builder at the merge block
-                self.builder.position_at_end(merge_block);
             }
         }
         Ok(())
     }
 
     /// Generate LLVM IR for an expression
-    fn gen_expr(&self, expr: &'ctx ast::Expr) -> Result<inkwell::values::BasicValueEnum<'ctx>> {
-        match expr {
-            ast::Expr::IntLit(value) => {
-                let i32_type = self.context.i32_type();
-                Ok(i32_type.const_int(*value as u64, false).into())
+    fn gen_expr(&mut self, expr: &'ctx ast::Expr) -> Result<inkwell::values::BasicValueEnum<'ctx>> {
+        match &expr.kind {
+            ast::ExprKind::IntLit(value) => Ok(self.gen_int_literal(i128::from(*value))),
+            ast::ExprKind::FloatLit(value) => {
+                let f64_type = self.context.f64_type();
+                Ok(f64_type.const_float(*value).into())
             }
-            ast::Expr::BoolLit(value) => {
+            ast::ExprKind::BoolLit(value) => {
                 // Boolean literals (true/false) are represented as i1 (1-bit integer) in LLVM
                 let bool_type = self.context.bool_type();
                 let bool_value = if *value {
@@ -409,21 +1226,58 @@ impl<'ctx> CodeGen<'ctx> {
                 };
                 Ok(bool_value.into())
             }
-            ast::Expr::BinOp { lhs, op, rhs } => {
-                let lhs = self.gen_expr(lhs)?;
-                let rhs = self.gen_expr(rhs)?;
+            ast::ExprKind::StrLit(value) => {
+                // Strings are pointers to NUL-terminated constant data
+                let global = self
+                    .builder
+                    .build_global_string_ptr(value, "str")
+                    .map_err(|e| anyhow::anyhow!("Failed to build string literal: {}", e))?;
+                Ok(global.as_pointer_value().into())
+            }
+            ast::ExprKind::BinOp { lhs, op, rhs } => {
+                // A literal operand takes the type of the other operand, like in sema
+                let (lhs, rhs) = match (
+                    ast::int_literal_value(lhs).is_some(),
+                    ast::int_literal_value(rhs).is_some(),
+                ) {
+                    (true, false) => {
+                        let rhs = self.gen_expr(rhs)?;
+                        (self.gen_expr_as(lhs, rhs.get_type())?, rhs)
+                    }
+                    (false, true) => {
+                        let lhs = self.gen_expr(lhs)?;
+                        (lhs, self.gen_expr_as(rhs, lhs.get_type())?)
+                    }
+                    _ => (self.gen_expr(lhs)?, self.gen_expr(rhs)?),
+                };
 
                 // Handle comparison and logical operators
                 match op {
                     // Equality operators
                     ast::BinOp::Equal | ast::BinOp::NotEqual => {
                         if lhs.get_type() != rhs.get_type() {
-                            bail!("Type mismatch in equality operation");
+                            bail!(
+                                "Type mismatch in equality operation: {} and {}",
+                                self.type_name(lhs.get_type()),
+                                self.type_name(rhs.get_type())
+                            );
                         }
 
                         if lhs.is_int_value() && rhs.is_int_value() {
                             let lhs_int = lhs.into_int_value();
                             let rhs_int = rhs.into_int_value();
+
+                            // Inequality of two booleans is their logical XOR
+                            if *op == ast::BinOp::NotEqual
+                                && lhs_int.get_type().get_bit_width() == 1
+                            {
+                                return self
+                                    .builder
+                                    .build_xor(lhs_int, rhs_int, "xortmp")
+                                    .map_err(|e| anyhow::anyhow!("Failed to build XOR: {}", e))
+                                    .map(|v| v.into());
+                            }
+
                             let predicate = match op {
                                 ast::BinOp::Equal => inkwell::IntPredicate::EQ,
                                 ast::BinOp::NotEqual => inkwell::IntPredicate::NE,
@@ -433,8 +1287,23 @@ impl<'ctx> CodeGen<'ctx> {
                                 .build_int_compare(predicate, lhs_int, rhs_int, "cmptmp")
                                 .map_err(|e| anyhow::anyhow!("Failed to build comparison: {}", e))
                                 .map(|v| v.into())
+                        } else if lhs.is_float_value() && rhs.is_float_value() {
+                            let predicate = match op {
+                                ast::BinOp::Equal => inkwell::FloatPredicate::OEQ,
+                                ast::BinOp::NotEqual => inkwell::FloatPredicate::UNE,
+                                _ => unreachable!(),
+                            };
+                            self.builder
+                                .build_float_compare(
+                                    predicate,
+                                    lhs.into_float_value(),
+                                    rhs.into_float_value(),
+                                    "cmptmp",
+                                )
+                                .map_err(|e| anyhow::anyhow!("Failed to build comparison: {}", e))
+                                .map(|v| self.with_fast_math(v).into())
                         } else {
-                            bail!("Equality operation only supports integer values for now");
+                            bail!("Equality operation only supports numeric values for now");
                         }
                     }
                     // Comparison operators
@@ -443,7 +1312,11 @@ impl<'ctx> CodeGen<'ctx> {
                     | ast::BinOp::GreaterThan
                     | ast::BinOp::GreaterThanOrEqual => {
                         if lhs.get_type() != rhs.get_type() {
-                            bail!("Type mismatch in comparison operation");
+                            bail!(
+                                "Type mismatch in comparison operation: {} and {}",
+                                self.type_name(lhs.get_type()),
+                                self.type_name(rhs.get_type())
+                            );
                         }
 
                         if lhs.is_int_value() && rhs.is_int_value() {
@@ -460,12 +1333,29 @@ impl<'ctx> CodeGen<'ctx> {
                                 .build_int_compare(predicate, lhs_int, rhs_int, "cmptmp")
                                 .map_err(|e| anyhow::anyhow!("Failed to build comparison: {}", e))
                                 .map(|v| v.into())
+                        } else if lhs.is_float_value() && rhs.is_float_value() {
+                            let predicate = match op {
+                                ast::BinOp::LessThan => inkwell::FloatPredicate::OLT,
+                                ast::BinOp::LessThanOrEqual => inkwell::FloatPredicate::OLE,
+                                ast::BinOp::GreaterThan => inkwell::FloatPredicate::OGT,
+                                ast::BinOp::GreaterThanOrEqual => inkwell::FloatPredicate::OGE,
+                                _ => unreachable!(),
+                            };
+                            self.builder
+                                .build_float_compare(
+                                    predicate,
+                                    lhs.into_float_value(),
+                                    rhs.into_float_value(),
+                                    "cmptmp",
+                                )
+                                .map_err(|e| anyhow::anyhow!("Failed to build comparison: {}", e))
+                                .map(|v| self.with_fast_math(v).into())
                         } else {
-                            bail!("Comparison operation only supports integer values for now");
+                            bail!("Comparison operation only supports numeric values for now");
                         }
                     }
                     // Logical operators
-                    ast::BinOp::And | ast::BinOp::Or => {
+                    ast::BinOp::And | ast::BinOp::Or | ast::BinOp::Xor => {
                         if !lhs.is_int_value() || !rhs.is_int_value() {
                             bail!("Logical operation only supports boolean values");
                         }
@@ -485,17 +1375,34 @@ impl<'ctx> CodeGen<'ctx> {
                                 .build_or(lhs_int, rhs_int, "ortmp")
                                 .map_err(|e| anyhow::anyhow!("Failed to build OR: {}", e))
                                 .map(|v| v.into()),
+                            ast::BinOp::Xor => self
+                                .builder
+                                .build_xor(lhs_int, rhs_int, "xortmp")
+                                .map_err(|e| anyhow::anyhow!("Failed to build XOR: {}", e))
+                                .map(|v| v.into()),
                             _ => unreachable!(),
                         }
                     }
                     // Arithmetic operators
                     _ => {
                         if lhs.get_type() != rhs.get_type() {
-                            bail!("Type mismatch in binary operation");
+                            bail!(
+                                "Type mismatch in binary operation: {} and {}",
+                                self.type_name(lhs.get_type()),
+                                self.type_name(rhs.get_type())
+                            );
+                        }
+
+                        if lhs.is_float_value() && rhs.is_float_value() {
+                            return self.gen_float_arithmetic(
+                                *op,
+                                lhs.into_float_value(),
+                                rhs.into_float_value(),
+                            );
                         }
 
                         if !lhs.is_int_value() || !rhs.is_int_value() {
-                            bail!("Binary operation only supports integer values");
+                            bail!("Binary operation only supports numeric values");
                         }
 
                         let lhs = lhs.into_int_value();
@@ -517,6 +1424,9 @@ impl<'ctx> CodeGen<'ctx> {
                                 .build_int_mul(lhs, rhs, "multmp")
                                 .map_err(|e| anyhow::anyhow!("Failed to build mul: {}", e))
                                 .map(|v| v.into()),
+                            ast::BinOp::Div if self.floor_div => {
+                                self.gen_floor_div(lhs, rhs).map(|v| v.into())
+                            }
                             ast::BinOp::Div => self
                                 .builder
                                 .build_int_signed_div(lhs, rhs, "divtmp")
@@ -527,17 +1437,33 @@ impl<'ctx> CodeGen<'ctx> {
                     }
                 }
             }
-            ast::Expr::UnaryOp { op, expr } => {
+            ast::ExprKind::UnaryOp { op, expr } => {
+                // A negated literal such as `-2147483648` is typed by its value
+                if let (ast::UnaryOp::Neg, ast::ExprKind::IntLit(value)) = (op, &expr.kind) {
+                    return Ok(self.gen_int_literal(-i128::from(*value)));
+                }
                 let value = self.gen_expr(expr)?;
 
                 match op {
                     ast::UnaryOp::Neg => {
+                        if value.is_float_value() {
+                            return self
+                                .builder
+                                .build_float_neg(value.into_float_value(), "negtmp")
+                                .map_err(|e| anyhow::anyhow!("Failed to build negation: {}", e))
+                                .map(|v| self.with_fast_math(v).into());
+                        }
                         if !value.is_int_value() {
-                            bail!("Unary negation only supports integer values");
+                            bail!("Unary negation only supports numeric values");
                         }
                         let value = value.into_int_value();
+                        if self.checked_arith {
+                            return self.gen_checked_neg(value).map(|v| v.into());
+                        }
 
-                        let zero = self.context.i32_type().const_int(0, false);
+                        // A plain `sub` (no `nsw`) wraps, so `-i32::MIN` is `i32::MIN` rather
+                        // than poison, the same as the constant folder's `wrapping_neg`
+                        let zero = value.get_type().const_zero();
                         self.builder
                             .build_int_sub(zero, value, "negtmp")
                             .map_err(|e| anyhow::anyhow!("Failed to build negation: {}", e))
@@ -554,30 +1480,28 @@ impl<'ctx> CodeGen<'ctx> {
                             .map_err(|e| anyhow::anyhow!("Failed to build logical NOT: {}", e))
                             .map(|v| v.into())
                     }
+                    ast::UnaryOp::BitNot => {
+                        if !value.is_int_value() {
+                            bail!("Bitwise NOT only supports integer values");
+                        }
+                        // `not` flips every bit, so `~0` is -1
+                        self.builder
+                            .build_not(value.into_int_value(), "bitnottmp")
+                            .map_err(|e| anyhow::anyhow!("Failed to build bitwise NOT: {}", e))
+                            .map(|v| v.into())
+                    }
                 }
             }
-            ast::Expr::FnCall { name, args } => {
-                // Look up the function by name
-                let function = self
-                    .module
-                    .get_function(name)
-                    .ok_or_else(|| anyhow::anyhow!("Function '{}' not found", name))?;
-                // Generate code for each argument
-                let mut arg_values = Vec::new();
-                for arg in args {
-                    arg_values.push(self.gen_expr(arg)?);
-                }
-                // Build the call
-                let call_site = self.builder.build_call(
-                    function,
-                    &arg_values.iter().map(|v| (*v).into()).collect::<Vec<_>>(),
-                    "calltmp",
-                )?;
-                // Assume all functions return i32 for now
-                let ret_val = call_site.try_as_basic_value().left().unwrap();
-                Ok(ret_val)
+            ast::ExprKind::FnCall {
+                name,
+                args,
+                arg_names,
+            } => {
+                // Void functions don't produce a value that could be used in an expression
+                self.gen_call(name, args, arg_names, &expr.span, false)?
+                    .ok_or_else(|| anyhow::anyhow!("Function '{}' does not return a value", name))
             }
-            ast::Expr::VarRef { name } => {
+            ast::ExprKind::VarRef { name } => {
                 // Look up the variable by name
                 let var_info = self
                     .env
@@ -588,29 +1512,1411 @@ impl<'ctx> CodeGen<'ctx> {
                     .build_load(var_info.ty, var_info.ptr, name) // Use stored type
                     .map_err(|e| anyhow::anyhow!("Failed to load variable '{}': {}", name, e))
             }
+            ast::ExprKind::StructLit { name, fields } => {
+                let struct_info = self
+                    .structs
+                    .get(name)
+                    .ok_or_else(|| anyhow::anyhow!("Struct '{}' not found", name))?;
+
+                if fields.len() != struct_info.fields.len() {
+                    bail!(
+                        "Struct '{}' has {} fields, but {} were given",
+                        name,
+                        struct_info.fields.len(),
+                        fields.len()
+                    );
+                }
+                for declared in &struct_info.fields {
+                    if !fields.iter().any(|field| field.name == *declared) {
+                        bail!("Missing field '{}' in struct literal '{}'", declared, name);
+                    }
+                }
+
+                // Build the struct value by inserting each field into an undef aggregate
+                let struct_type = struct_info.ty;
+                let mut struct_value = struct_type.get_undef();
+                for field in fields {
+                    let (index, field_type) = self.resolve_field(struct_type, field.name)?;
+                    let value = self.gen_expr(&field.value)?;
+                    if value.get_type() != field_type {
+                        bail!(
+                            "Type mismatch for field '{}' in struct literal '{}': expected {}, found {}",
+                            field.name,
+                            name,
+                            self.type_name(field_type),
+                            self.type_name(value.get_type())
+                        );
+                    }
+                    struct_value = self
+                        .builder
+                        .build_insert_value(struct_value, value, index, "structtmp")
+                        .map_err(|e| anyhow::anyhow!("Failed to build struct literal: {}", e))?
+                        .into_struct_value();
+                }
+                Ok(struct_value.into())
+            }
+            ast::ExprKind::FieldAccess { expr, field } => {
+                // Access the field in place when the struct lives in a variable
+                if let ast::ExprKind::VarRef { name } = &expr.kind {
+                    let var_info = self.env.resolve_var(name)?;
+                    if var_info.ty.is_struct_type() {
+                        let struct_type = var_info.ty.into_struct_type();
+                        let (index, field_type) = self.resolve_field(struct_type, field)?;
+                        let ptr = self
+                            .builder
+                            .build_struct_gep(struct_type, var_info.ptr, index, field)
+                            .map_err(|e| anyhow::anyhow!("Failed to build field access: {}", e))?;
+                        return self
+                            .builder
+                            .build_load(field_type, ptr, field)
+                            .map_err(|e| {
+                                anyhow::anyhow!("Failed to load field '{}': {}", field, e)
+                            });
+                    }
+                }
+
+                let value = self.gen_expr(expr)?;
+                if !value.is_struct_value() {
+                    bail!("Field access on a non-struct value");
+                }
+                let struct_value = value.into_struct_value();
+                let (index, _) = self.resolve_field(struct_value.get_type(), field)?;
+                self.builder
+                    .build_extract_value(struct_value, index, field)
+                    .map_err(|e| anyhow::anyhow!("Failed to build field access: {}", e))
+            }
+            ast::ExprKind::EnumVariant { name, variant } => {
+                let enum_info = self
+                    .enums
+                    .get(name)
+                    .ok_or_else(|| anyhow::anyhow!("Enum '{}' not found", name))?;
+                let value = enum_info.variants.get(variant).ok_or_else(|| {
+                    anyhow::anyhow!("Enum '{}' has no variant '{}'", name, variant)
+                })?;
+                Ok(self
+                    .context
+                    .i32_type()
+                    .const_int(*value as u64, true)
+                    .into())
+            }
+            ast::ExprKind::Cast { expr, r#type } => {
+                let value = self.gen_expr(expr)?;
+                let target_type = self.map_ast_type_to_llvm(*r#type)?;
+                self.gen_cast(value, target_type)
+            }
+            ast::ExprKind::SizeOf { r#type } => {
+                let size = self
+                    .map_ast_type_to_llvm(*r#type)?
+                    .size_of()
+                    .ok_or_else(|| anyhow::anyhow!("Cannot take the size of {}", r#type))?;
+
+                // LLVM measures sizes in i64, but like integer literals `sizeof` is an i32
+                self.builder
+                    .build_int_truncate(size, self.context.i32_type(), "sizetmp")
+                    .map_err(|e| anyhow::anyhow!("Failed to build size: {}", e))
+                    .map(|v| v.into())
+            }
+            ast::ExprKind::Block { label, body } => {
+                let function = self
+                    .builder
+                    .get_insert_block()
+                    .unwrap()
+                    .get_parent()
+                    .unwrap();
+                let merge_block = self.context.append_basic_block(function, "blockcont");
+                self.labeled_blocks.push(BlockTarget {
+                    label,
+                    merge_block,
+                    incoming: Vec::new(),
+                });
+                let index = self.labeled_blocks.len() - 1;
+
+                // The trailing value leaves the block like a `break` to its own label
+                let Some((ast::Stmt::Expr { expr: value }, stmts)) = body.split_last() else {
+                    bail!("Labeled block '{} must end with a value", label);
+                };
+                self.env.push_scope();
+                self.gen_stmts(stmts, false)?;
+                if !self.is_terminated() {
+                    let value = self.gen_expr(value)?;
+                    self.yield_from_block(index, value);
+                    self.builder
+                        .build_unconditional_branch(merge_block)
+                        .map_err(|e| {
+                            anyhow::anyhow!("Failed to build unconditional branch: {}", e)
+                        })?;
+                }
+                self.env.pop_scope();
+                let target = self.labeled_blocks.pop().unwrap();
+
+                // Join the values of every exit with a phi node
+                self.builder.position_at_end(merge_block);
+                let Some((first_value, _)) = target.incoming.first() else {
+                    bail!("Labeled block '{} never yields a value", label);
+                };
+                let phi = self
+                    .builder
+                    .build_phi(first_value.get_type(), "blocktmp")
+                    .map_err(|e| anyhow::anyhow!("Failed to build phi: {}", e))?;
+                for (value, block) in &target.incoming {
+                    if value.get_type() != first_value.get_type() {
+                        bail!(
+                            "Labeled block '{} yields different types: expected {}, found {}",
+                            label,
+                            self.type_name(first_value.get_type()),
+                            self.type_name(value.get_type())
+                        );
+                    }
+                    phi.add_incoming(&[(value, *block)]);
+                }
+                Ok(phi.as_basic_value())
+            }
+            ast::ExprKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => self.gen_if_expr(condition, then_branch, else_branch),
+            ast::ExprKind::Sequence { exprs } => {
+                let Some((last, rest)) = exprs.split_last() else {
+                    bail!("Empty comma expression");
+                };
+                // Only the value of the last expression is used, so the others may be void calls
+                for expr in rest {
+                    self.gen_optional_expr(expr)?;
+                }
+                self.gen_expr(last)
+            }
+            ast::ExprKind::IncDec { op, name, postfix } => {
+                let var_info = self.env.resolve_var(name)?;
+                if !var_info.is_mutable {
+                    bail!("Cannot assign to immutable variable '{}'", name);
+                }
+                let (ptr, ty) = (var_info.ptr, var_info.ty);
+                // Booleans are i1 in LLVM, but can't be incremented
+                if !matches!(ty, BasicTypeEnum::IntType(int_type) if int_type.get_bit_width() > 1) {
+                    bail!(
+                        "Operator {:?} is not supported for {}",
+                        op,
+                        self.type_name(ty)
+                    );
+                }
+
+                let old_value = self
+                    .builder
+                    .build_load(ty, ptr, name)
+                    .map_err(|e| anyhow::anyhow!("Failed to load variable '{}': {}", name, e))?
+                    .into_int_value();
+                let one = ty.into_int_type().const_int(1, false);
+                let new_value = match op {
+                    ast::IncDecOp::Increment => {
+                        self.builder.build_int_add(old_value, one, "inctmp")
+                    }
+                    ast::IncDecOp::Decrement => {
+                        self.builder.build_int_sub(old_value, one, "dectmp")
+                    }
+                }
+                .map_err(|e| anyhow::anyhow!("Failed to build {:?}: {}", op, e))?;
+                self.builder.build_store(ptr, new_value)?;
+
+                // The old value was loaded before the store, so it serves as the postfix result
+                Ok(if *postfix { old_value } else { new_value }.into())
+            }
+            ast::ExprKind::Match { scrutinee, arms } => {
+                let value = self.gen_expr(scrutinee)?;
+                if !value.is_int_value() {
+                    bail!("Match scrutinee must be an integer value");
+                }
+                let value = value.into_int_value();
+
+                let function = self
+                    .builder
+                    .get_insert_block()
+                    .unwrap()
+                    .get_parent()
+                    .unwrap();
+                let merge_block = self.context.append_basic_block(function, "matchcont");
+
+                // Only the first arm for each value (and the first wildcard) is reachable
+                let mut cases = Vec::new();
+                let mut default_block = None;
+                let mut arm_blocks = Vec::new();
+                for arm in arms {
+                    let block = self.context.append_basic_block(function, "matcharm");
+                    match arm.pattern {
+                        ast::Pattern::IntLit(pattern) => {
+                            let case = value.get_type().const_int(pattern as u64, true);
+                            if default_block.is_none() && !cases.iter().any(|(c, _)| *c == case) {
+                                cases.push((case, block));
+                            }
+                        }
+                        ast::Pattern::Wildcard => {
+                            default_block.get_or_insert(block);
+                        }
+                    }
+                    arm_blocks.push(block);
+                }
+                let default_block = default_block
+                    .ok_or_else(|| anyhow::anyhow!("Match on integers requires a `_` arm"))?;
+
+                self.builder
+                    .build_switch(value, default_block, &cases)
+                    .map_err(|e| anyhow::anyhow!("Failed to build switch: {}", e))?;
+
+                // Generate each arm and join their values with a phi node
+                let mut incoming = Vec::new();
+                for (arm, block) in arms.iter().zip(arm_blocks) {
+                    self.builder.position_at_end(block);
+                    let arm_value = self.gen_expr(&arm.value)?;
+                    self.builder
+                        .build_unconditional_branch(merge_block)
+                        .map_err(|e| {
+                            anyhow::anyhow!("Failed to build unconditional branch: {}", e)
+                        })?;
+                    incoming.push((arm_value, self.builder.get_insert_block().unwrap()));
+                }
+
+                self.builder.position_at_end(merge_block);
+                let phi = self
+                    .builder
+                    .build_phi(incoming[0].0.get_type(), "matchtmp")
+                    .map_err(|e| anyhow::anyhow!("Failed to build phi: {}", e))?;
+                for (arm_value, block) in &incoming {
+                    if arm_value.get_type() != phi.as_basic_value().get_type() {
+                        bail!("Match arms must all have the same type");
+                    }
+                    phi.add_incoming(&[(arm_value, *block)]);
+                }
+                Ok(phi.as_basic_value())
+            }
+        }
+    }
+
+    /// Generate an integer constant, which is an i32 if the value fits and an i64 otherwise
+    fn gen_int_literal(&self, value: i128) -> BasicValueEnum<'ctx> {
+        let int_type = match ast::int_literal_type(value) {
+            ast::Type::I32 => self.context.i32_type(),
+            _ => self.context.i64_type(),
+        };
+        int_type.const_int(value as u64, true).into()
+    }
+
+    /// Generate LLVM IR for an arithmetic operation on two floats
+    fn gen_float_arithmetic(
+        &self,
+        op: ast::BinOp,
+        lhs: FloatValue<'ctx>,
+        rhs: FloatValue<'ctx>,
+    ) -> Result<BasicValueEnum<'ctx>> {
+        let value = match op {
+            ast::BinOp::Add => self.builder.build_float_add(lhs, rhs, "addtmp"),
+            ast::BinOp::Sub => self.builder.build_float_sub(lhs, rhs, "subtmp"),
+            ast::BinOp::Mul => self.builder.build_float_mul(lhs, rhs, "multmp"),
+            ast::BinOp::Div => self.builder.build_float_div(lhs, rhs, "divtmp"),
+            _ => bail!("Operator {:?} is not an arithmetic operator", op),
+        };
+        value
+            .map_err(|e| anyhow::anyhow!("Failed to build {:?}: {}", op, e))
+            .map(|v| self.with_fast_math(v).into())
+    }
+
+    /// Set every fast-math flag on a float instruction if `--ffast-math` is enabled.
+    /// Values that the builder folded to constants have no instruction to flag
+    fn with_fast_math<V: BasicValue<'ctx>>(&self, value: V) -> V {
+        if self.fast_math {
+            if let Some(instruction) = value.as_instruction_value() {
+                instruction.set_fast_math_flags(FAST_MATH_ALL);
+            }
+        }
+        value
+    }
+
+    /// Generate LLVM IR converting a numeric value to another numeric type
+    fn gen_cast(
+        &self,
+        value: BasicValueEnum<'ctx>,
+        target_type: BasicTypeEnum<'ctx>,
+    ) -> Result<BasicValueEnum<'ctx>> {
+        let cast: Result<BasicValueEnum<'ctx>, _> = match (value, target_type) {
+            (BasicValueEnum::IntValue(value), BasicTypeEnum::IntType(target_type)) => {
+                // Booleans are zero-extended, wider integers are sign-extended or truncated
+                let is_signed = value.get_type().get_bit_width() > 1;
+                self.builder
+                    .build_int_cast_sign_flag(value, target_type, is_signed, "casttmp")
+                    .map(|v| v.into())
+            }
+            (BasicValueEnum::IntValue(value), BasicTypeEnum::FloatType(target_type)) => {
+                if value.get_type().get_bit_width() == 1 {
+                    self.builder
+                        .build_unsigned_int_to_float(value, target_type, "casttmp")
+                        .map(|v| v.into())
+                } else {
+                    self.builder
+                        .build_signed_int_to_float(value, target_type, "casttmp")
+                        .map(|v| v.into())
+                }
+            }
+            (BasicValueEnum::FloatValue(value), BasicTypeEnum::IntType(target_type)) => self
+                .builder
+                .build_float_to_signed_int(value, target_type, "casttmp")
+                .map(|v| v.into()),
+            (BasicValueEnum::FloatValue(value), BasicTypeEnum::FloatType(target_type)) => self
+                .builder
+                .build_float_cast(value, target_type, "casttmp")
+                .map(|v| v.into()),
+            _ => bail!("Casts are only supported between numeric types"),
+        };
+        cast.map_err(|e| anyhow::anyhow!("Failed to build cast: {}", e))
+    }
+
+    /// Register an enum type, numbering implicit discriminants from the previous variant
+    fn declare_enum(
+        &mut self,
+        name: &'ctx str,
+        variants: &'ctx [ast::EnumVariant],
+        hoisted: bool,
+    ) -> Result<()> {
+        if self.enums.contains_key(name) {
+            bail!("Enum '{}' already declared", name);
+        }
+
+        let mut next = 0;
+        let mut discriminants = HashMap::new();
+        for variant in variants {
+            let value = variant.value.unwrap_or(next);
+            discriminants.insert(variant.name, value);
+            next = value + 1;
+        }
+
+        self.enums.insert(
+            name,
+            EnumInfo {
+                variants: discriminants,
+                hoisted,
+            },
+        );
+        Ok(())
+    }
+
+    /// Register a struct type
+    fn declare_struct(
+        &mut self,
+        name: &'ctx str,
+        fields: &'ctx [ast::StructField],
+        hoisted: bool,
+    ) -> Result<()> {
+        if self.structs.contains_key(name) {
+            bail!("Struct '{}' already declared", name);
+        }
+
+        let field_types = fields
+            .iter()
+            .map(|field| self.map_ast_type_to_llvm(field.r#type))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let struct_type = self.context.opaque_struct_type(name);
+        struct_type.set_body(&field_types, false);
+
+        self.structs.insert(
+            name,
+            StructInfo {
+                ty: struct_type,
+                fields: fields.iter().map(|field| field.name).collect(),
+                hoisted,
+            },
+        );
+        Ok(())
+    }
+
+    /// Add a function prototype to the module
+    fn declare_function(
+        &mut self,
+        name: &str,
+        params: &'ctx [ast::FunctionParameter<'ctx>],
+        return_type: ast::Type,
+    ) -> Result<FunctionValue<'ctx>> {
+        let ptr_type = self.context.ptr_type(AddressSpace::default());
+        let return_llvm_type = match self.map_ast_type_to_llvm(return_type) {
+            Ok(ty) => Some(ty),
+            Err(_) if return_type == ast::Type::Void => None,
+            Err(e) => return Err(e),
+        };
+
+        // Large structs go through pointers, with the return slot as the first parameter
+        let mut abi = FnAbi::default();
+        let mut param_types: Vec<BasicMetadataTypeEnum> = Vec::new();
+        if let Some(ty) = return_llvm_type.filter(|ty| self.is_passed_indirectly(*ty)) {
+            abi.sret = Some(ty);
+            param_types.push(ptr_type.into());
+        }
+        for param in params {
+            let ty = self.map_ast_type_to_llvm(param.r#type)?;
+            if self.is_passed_indirectly(ty) {
+                abi.byval.push(Some(ty));
+                param_types.push(ptr_type.into());
+            } else {
+                abi.byval.push(None);
+                param_types.push(ty.into());
+            }
+        }
+
+        // Create function type
+        let fn_type = match return_llvm_type {
+            Some(ty) if abi.sret.is_none() => ty.fn_type(&param_types, false),
+            _ => self.context.void_type().fn_type(&param_types, false),
+        };
+
+        let function = self.module.add_function(name, fn_type, None);
+        for (index, attribute) in self.abi_attributes(&abi) {
+            function.add_attribute(AttributeLoc::Param(index), attribute);
+        }
+        self.param_names
+            .insert(function, params.iter().map(|param| param.name).collect());
+        self.abis.insert(function, abi);
+        Ok(function)
+    }
+
+    /// Whether values of `ty` are too large to pass or return directly
+    fn is_passed_indirectly(&self, ty: BasicTypeEnum<'ctx>) -> bool {
+        if !ty.is_struct_type() {
+            return false;
+        }
+        // Without a target machine the layout is unknown, so keep passing the struct directly
+        self.target_data
+            .as_ref()
+            .is_some_and(|target_data| target_data.get_abi_size(&ty) > MAX_DIRECT_AGGREGATE_SIZE)
+    }
+
+    /// The `sret` and `byval` attributes of each pointer parameter in `abi`, by parameter index
+    fn abi_attributes(&self, abi: &FnAbi<'ctx>) -> Vec<(u32, Attribute)> {
+        let type_attribute = |name, ty: BasicTypeEnum<'ctx>| {
+            let kind = Attribute::get_named_enum_kind_id(name);
+            self.context
+                .create_type_attribute(kind, ty.as_any_type_enum())
+        };
+        let offset = u32::from(abi.sret.is_some());
+        let sret = abi.sret.map(|ty| (0, type_attribute("sret", ty)));
+        // On AArch64 the callee gets the pointer to the caller's copy as is
+        let triple = self.target_triple.as_str().to_string_lossy();
+        if triple.starts_with("aarch64") || triple.starts_with("arm64") {
+            return sret.into_iter().collect();
+        }
+        let byval =
+            abi.byval.iter().enumerate().filter_map(|(i, ty)| {
+                ty.map(|ty| (offset + i as u32, type_attribute("byval", ty)))
+            });
+        sret.into_iter().chain(byval).collect()
+    }
+
+    /// Add a value branching from the current block to the end of the labeled block at `index`
+    fn yield_from_block(&mut self, index: usize, value: BasicValueEnum<'ctx>) {
+        let current_block = self.builder.get_insert_block().unwrap();
+        self.labeled_blocks[index]
+            .incoming
+            .push((value, current_block));
+    }
+
+    /// Branch to `target` and continue emitting into a fresh unreachable block,
+    /// so statements following a `break`/`continue` still produce valid IR
+    fn build_jump(&mut self, target: BasicBlock<'ctx>) -> Result<()> {
+        self.builder
+            .build_unconditional_branch(target)
+            .map_err(|e| anyhow::anyhow!("Failed to build unconditional branch: {}", e))?;
+
+        let function = self
+            .builder
+            .get_insert_block()
+            .unwrap()
+            .get_parent()
+            .unwrap();
+        let unreachable_block = self.context.append_basic_block(function, "afterjump");
+        self.builder.position_at_end(unreachable_block);
+        Ok(())
+    }
+
+    /// Return from the current function, with no value for void functions
+    fn build_return(&self, value: Option<BasicValueEnum<'ctx>>) -> Result<()> {
+        let result = match value {
+            Some(value) => {
+                let mut value = self.coerce_return_value(value)?;
+                // The top-level statements make up the body of main
+                if self.wrap_exit_code && self.fn_path.is_empty() && value.is_int_value() {
+                    let value_int = value.into_int_value();
+                    let mask = value_int.get_type().const_int(0xFF, false);
+                    value = self
+                        .builder
+                        .build_and(value_int, mask, "exitcode")
+                        .map_err(|e| anyhow::anyhow!("Failed to build AND: {}", e))?
+                        .into();
+                }
+                match self.sret_pointer() {
+                    // Large structs are written to the caller's return slot instead
+                    Some(sret) => {
+                        self.builder
+                            .build_store(sret, value)
+                            .map_err(|e| anyhow::anyhow!("Failed to build store: {}", e))?;
+                        self.builder.build_return(None)
+                    }
+                    None => self.builder.build_return(Some(&value)),
+                }
+            }
+            None => self.builder.build_return(None),
+        };
+        result.map_err(|e| anyhow::anyhow!("Failed to build return: {}", e))?;
+        Ok(())
+    }
+
+    /// The return slot of the current function, if it returns a large struct
+    fn sret_pointer(&self) -> Option<PointerValue<'ctx>> {
+        let function = self.builder.get_insert_block()?.get_parent()?;
+        self.abis.get(&function)?.sret?;
+        Some(function.get_first_param()?.into_pointer_value())
+    }
+
+    /// Divide integers rounding toward negative infinity
+    ///
+    /// The truncated quotient is one too large when there is a remainder whose
+    /// sign differs from the divisor's, so one is subtracted in that case.
+    fn gen_floor_div(&self, lhs: IntValue<'ctx>, rhs: IntValue<'ctx>) -> Result<IntValue<'ctx>> {
+        let build_err = |e| anyhow::anyhow!("Failed to build floor division: {}", e);
+        let quotient = self
+            .builder
+            .build_int_signed_div(lhs, rhs, "divtmp")
+            .map_err(build_err)?;
+        let remainder = self
+            .builder
+            .build_int_signed_rem(lhs, rhs, "remtmp")
+            .map_err(build_err)?;
+        let zero = lhs.get_type().const_zero();
+        let inexact = self
+            .builder
+            .build_int_compare(inkwell::IntPredicate::NE, remainder, zero, "inexact")
+            .map_err(build_err)?;
+        let signs = self
+            .builder
+            .build_xor(remainder, rhs, "signs")
+            .map_err(build_err)?;
+        let signs_differ = self
+            .builder
+            .build_int_compare(inkwell::IntPredicate::SLT, signs, zero, "signs_differ")
+            .map_err(build_err)?;
+        let adjust = self
+            .builder
+            .build_and(inexact, signs_differ, "adjust")
+            .map_err(build_err)?;
+        let adjust = self
+            .builder
+            .build_int_z_extend(adjust, lhs.get_type(), "adjust")
+            .map_err(build_err)?;
+        self.builder
+            .build_int_sub(quotient, adjust, "floordiv")
+            .map_err(build_err)
+    }
+
+    /// Generate a branch condition as an i1 value
+    ///
+    /// With C truthiness enabled, an integer condition is compared against zero.
+    fn gen_condition(&mut self, condition: &'ctx ast::Expr) -> Result<IntValue<'ctx>> {
+        let value = self.gen_expr(condition)?;
+        let value = match value {
+            BasicValueEnum::IntValue(value) => value,
+            _ => bail!(
+                "Condition must be a boolean value, found {}",
+                self.type_name(value.get_type())
+            ),
+        };
+        if value.get_type().get_bit_width() == 1 {
+            return Ok(value);
+        }
+        if !self.c_truthiness {
+            bail!(
+                "Condition must be a boolean value, found {}",
+                self.type_name(value.get_type().into())
+            );
+        }
+        let zero = value.get_type().const_zero();
+        self.builder
+            .build_int_compare(inkwell::IntPredicate::NE, value, zero, "truthy")
+            .map_err(|e| anyhow::anyhow!("Failed to build comparison: {}", e))
+    }
+
+    /// Lower an if/else whose arms are both side-effect free expressions
+    /// to a `select` of the two values, then return the result
+    fn gen_select(
+        &mut self,
+        condition: &'ctx ast::Expr,
+        then_expr: &'ctx ast::Expr,
+        else_expr: &'ctx ast::Expr,
+        is_last_stmt: bool,
+    ) -> Result<()> {
+        let condition_value = self.gen_condition(condition)?;
+
+        // Widen both arms the same way each would be widened when returned on its own
+        let then_value = self.gen_expr(then_expr)?;
+        let then_value = self.coerce_return_value(then_value)?;
+        let else_value = self.gen_expr(else_expr)?;
+        let else_value = self.coerce_return_value(else_value)?;
+        if then_value.get_type() != else_value.get_type() {
+            bail!(
+                "Type mismatch in if branches: {} and {}",
+                self.type_name(then_value.get_type()),
+                self.type_name(else_value.get_type())
+            );
+        }
+
+        let value = self
+            .builder
+            .build_select(condition_value, then_value, else_value, "selecttmp")
+            .map_err(|e| anyhow::anyhow!("Failed to build select: {}", e))?;
+        self.build_return(Some(value))?;
+
+        // Like a branchy if whose arms both return, anything after this is unreachable
+        if !is_last_stmt {
+            let function = self
+                .builder
+                .get_insert_block()
+                .unwrap()
+                .get_parent()
+                .unwrap();
+            let merge_block = self.context.append_basic_block(function, "ifcont");
+            self.builder.position_at_end(merge_block);
+        }
+        Ok(())
+    }
+
+    /// Generate an if expression, selecting between its values when both branches are
+    /// a side-effect free expression and branching to them otherwise
+    fn gen_if_expr(
+        &mut self,
+        condition: &'ctx ast::Expr,
+        then_branch: &'ctx [ast::Stmt],
+        else_branch: &'ctx [ast::Stmt],
+    ) -> Result<BasicValueEnum<'ctx>> {
+        let condition_value = self.gen_condition(condition)?;
+        if let Some((then_expr, else_expr)) = select_arms(then_branch, Some(else_branch)) {
+            let then_value = self.gen_expr(then_expr)?;
+            let else_value = self.gen_expr(else_expr)?;
+            self.check_if_types(then_value, else_value)?;
+            return self
+                .builder
+                .build_select(condition_value, then_value, else_value, "selecttmp")
+                .map_err(|e| anyhow::anyhow!("Failed to build select: {}", e));
+        }
+
+        let function = self
+            .builder
+            .get_insert_block()
+            .unwrap()
+            .get_parent()
+            .unwrap();
+        let then_block = self.context.append_basic_block(function, "then");
+        let else_block = self.context.append_basic_block(function, "else");
+        let merge_block = self.context.append_basic_block(function, "ifcont");
+        self.builder
+            .build_conditional_branch(condition_value, then_block, else_block)
+            .map_err(|e| anyhow::anyhow!("Failed to build conditional branch: {}", e))?;
+
+        // Each branch that doesn't return ends with its value and a jump to the merge block
+        let mut incoming = Vec::new();
+        for (block, branch) in [(then_block, then_branch), (else_block, else_branch)] {
+            self.builder.position_at_end(block);
+            let Some((ast::Stmt::Expr { expr: value }, stmts)) = branch.split_last() else {
+                bail!("If expression branches must end with a value");
+            };
+            self.env.push_scope();
+            self.gen_stmts(stmts, false)?;
+            if !self.is_terminated() {
+                let value = self.gen_expr(value)?;
+                incoming.push((value, self.builder.get_insert_block().unwrap()));
+                self.builder
+                    .build_unconditional_branch(merge_block)
+                    .map_err(|e| anyhow::anyhow!("Failed to build unconditional branch: {}", e))?;
+            }
+            self.env.pop_scope();
+        }
+
+        self.builder.position_at_end(merge_block);
+        let Some(&(first_value, _)) = incoming.first() else {
+            bail!("If expression never yields a value");
+        };
+        if let Some(&(last_value, _)) = incoming.get(1) {
+            self.check_if_types(first_value, last_value)?;
+        }
+        let phi = self
+            .builder
+            .build_phi(first_value.get_type(), "iftmp")
+            .map_err(|e| anyhow::anyhow!("Failed to build phi: {}", e))?;
+        for (value, block) in &incoming {
+            phi.add_incoming(&[(value, *block)]);
+        }
+        Ok(phi.as_basic_value())
+    }
+
+    /// Make sure both branches of an if expression have the same type
+    fn check_if_types(
+        &self,
+        then_value: BasicValueEnum<'ctx>,
+        else_value: BasicValueEnum<'ctx>,
+    ) -> Result<()> {
+        if then_value.get_type() != else_value.get_type() {
+            bail!(
+                "if and else have incompatible types: {} vs {}",
+                self.type_name(then_value.get_type()),
+                self.type_name(else_value.get_type())
+            );
+        }
+        Ok(())
+    }
+
+    /// Zero-extend a boolean (i1) return value when the enclosing function returns a wider integer
+    fn coerce_return_value(&self, value: BasicValueEnum<'ctx>) -> Result<BasicValueEnum<'ctx>> {
+        let function = self
+            .builder
+            .get_insert_block()
+            .and_then(|block| block.get_parent())
+            .ok_or_else(|| anyhow::anyhow!("Return outside of a function"))?;
+
+        match function.get_type().get_return_type() {
+            Some(BasicTypeEnum::IntType(return_type))
+                if value.is_int_value()
+                    && value.into_int_value().get_type().get_bit_width() == 1
+                    && return_type.get_bit_width() > 1 =>
+            {
+                self.builder
+                    .build_int_z_extend(value.into_int_value(), return_type, "zexttmp")
+                    .map_err(|e| anyhow::anyhow!("Failed to build zero extension: {}", e))
+                    .map(|v| v.into())
+            }
+            _ => Ok(value),
+        }
+    }
+
+    /// Generate LLVM IR for an expression whose value may be absent,
+    /// i.e. a call to a void function
+    fn gen_optional_expr(&mut self, expr: &'ctx ast::Expr) -> Result<Option<BasicValueEnum<'ctx>>> {
+        match &expr.kind {
+            ast::ExprKind::FnCall {
+                name,
+                args,
+                arg_names,
+            } => self.gen_call(name, args, arg_names, &expr.span, false),
+            _ => self.gen_expr(expr).map(Some),
+        }
+    }
+
+    /// Generate LLVM IR for the operand of a return, which puts a call in tail position
+    fn gen_returned_expr(&mut self, expr: &'ctx ast::Expr) -> Result<Option<BasicValueEnum<'ctx>>> {
+        match &expr.kind {
+            ast::ExprKind::FnCall {
+                name,
+                args,
+                arg_names,
+            } => self.gen_call(name, args, arg_names, &expr.span, true),
+            _ => {
+                let return_type = self
+                    .builder
+                    .get_insert_block()
+                    .and_then(|block| block.get_parent())
+                    .and_then(|function| function.get_type().get_return_type());
+                match return_type {
+                    Some(ty) => self.gen_expr_as(expr, ty).map(Some),
+                    None => self.gen_optional_expr(expr),
+                }
+            }
+        }
+    }
+
+    /// Generate LLVM IR for an expression whose context expects `expected`, so that
+    /// a bare integer literal takes that type instead of defaulting to i32
+    fn gen_expr_as(
+        &mut self,
+        expr: &'ctx ast::Expr,
+        expected: BasicTypeEnum<'ctx>,
+    ) -> Result<BasicValueEnum<'ctx>> {
+        match ast::int_literal_value(expr) {
+            // Booleans are also LLVM integers, but literals never default to them
+            Some(value)
+                if expected.is_int_type() && expected.into_int_type().get_bit_width() > 1 =>
+            {
+                Ok(expected
+                    .into_int_type()
+                    .const_int(value as u64, true)
+                    .into())
+            }
+            _ => self.gen_expr(expr),
+        }
+    }
+
+    /// Generate LLVM IR for a function call.
+    /// Returns `None` if the function returns void.
+    ///
+    /// A recursive call in tail position is marked as a tail call, so LLVM can
+    /// reuse the caller's stack frame instead of growing the stack.
+    fn gen_call(
+        &mut self,
+        name: &str,
+        args: &'ctx [ast::Expr],
+        arg_names: &[&str],
+        span: &ast::Span,
+        is_tail: bool,
+    ) -> Result<Option<BasicValueEnum<'ctx>>> {
+        // Look up the function by name, falling back to the builtins
+        let function = self
+            .env
+            .resolve_fn(name)
+            .or_else(|| self.module.get_function(name));
+        let Some(function) = function else {
+            // The only builtin without a value
+            if name == "assert" {
+                self.gen_assert(args, arg_names, span)?;
+                return Ok(None);
+            }
+            if let Some(value) = self.gen_builtin_call(name, args, arg_names)? {
+                return Ok(Some(value));
+            }
+            bail!("Function '{}' not found", name);
+        };
+        let abi = self.abis.get(&function).cloned().unwrap_or_default();
+        let mut arg_values: Vec<BasicMetadataValueEnum> = Vec::new();
+        // A large struct result is written to a slot allocated by the caller
+        let sret = match abi.sret {
+            Some(ty) => {
+                let slot = self.builder.build_alloca(ty, "sret")?;
+                arg_values.push(slot.into());
+                Some((slot, ty))
+            }
+            None => None,
+        };
+        // Generate code for each argument, in the order of the parameters
+        for (i, arg) in self
+            .order_args(function, name, args, arg_names)?
+            .into_iter()
+            .enumerate()
+        {
+            let param_type = function
+                .get_nth_param((usize::from(sret.is_some()) + i) as u32)
+                .map(|param| param.get_type());
+            let value = match param_type {
+                Some(ty) => self.gen_expr_as(arg, ty)?,
+                None => self.gen_expr(arg)?,
+            };
+            match abi.byval.get(i).copied().flatten() {
+                // Large structs are passed as a pointer the callee copies from
+                Some(ty) => {
+                    let copy = self.builder.build_alloca(ty, "byval")?;
+                    self.builder.build_store(copy, value)?;
+                    arg_values.push(copy.into());
+                }
+                None => arg_values.push(value.into()),
+            }
+        }
+        // Build the call
+        let call_site = self.builder.build_call(function, &arg_values, "calltmp")?;
+        for (index, attribute) in self.abi_attributes(&abi) {
+            call_site.add_attribute(AttributeLoc::Param(index), attribute);
+        }
+        let caller = self
+            .builder
+            .get_insert_block()
+            .and_then(|block| block.get_parent());
+        // The callee may not access the caller's stack in a tail call
+        if is_tail && caller == Some(function) && abi.is_direct() {
+            call_site.set_tail_call(true);
+        }
+        match sret {
+            Some((slot, ty)) => Ok(Some(self.builder.build_load(ty, slot, "calltmp")?)),
+            None => Ok(call_site.try_as_basic_value().left()),
+        }
+    }
+
+    /// Put the arguments of a call in the order of the callee's parameters,
+    /// matching the trailing named arguments by name
+    fn order_args(
+        &self,
+        function: FunctionValue<'ctx>,
+        name: &str,
+        args: &'ctx [ast::Expr<'ctx>],
+        arg_names: &[&str],
+    ) -> Result<Vec<&'ctx ast::Expr<'ctx>>> {
+        let positional = args.len() - arg_names.len();
+        let mut ordered: Vec<_> = args[..positional].iter().map(Some).collect();
+        if let Some(param_names) = self.param_names.get(&function) {
+            ordered.resize(param_names.len().max(positional), None);
+            for (arg, arg_name) in args[positional..].iter().zip(arg_names) {
+                let Some(index) = param_names.iter().position(|param| param == arg_name) else {
+                    bail!("Function '{}' has no parameter named '{}'", name, arg_name);
+                };
+                if ordered[index].replace(arg).is_some() {
+                    bail!(
+                        "Argument '{}' to '{}' is given more than once",
+                        arg_name,
+                        name
+                    );
+                }
+            }
+        }
+        ordered
+            .into_iter()
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| anyhow::anyhow!("Missing arguments in call to '{}'", name))
+    }
+
+    /// Generate LLVM IR for a call to a builtin function.
+    /// Returns `None` if `name` is not a builtin.
+    fn gen_builtin_call(
+        &mut self,
+        name: &str,
+        args: &'ctx [ast::Expr],
+        arg_names: &[&str],
+    ) -> Result<Option<BasicValueEnum<'ctx>>> {
+        match name {
+            "abs" | "sqrt" | "pow" | "min" | "max" if !arg_names.is_empty() => {
+                bail!("Builtin '{}' does not take named arguments", name);
+            }
+            "abs" | "sqrt" => {
+                let [arg] = args else {
+                    bail!(
+                        "Builtin '{}' expects 1 argument, but {} were given",
+                        name,
+                        args.len()
+                    );
+                };
+                let arg = self.gen_expr(arg)?;
+                self.gen_abs_sqrt(name, arg).map(Some)
+            }
+            "pow" => {
+                let [base, exp] = args else {
+                    bail!(
+                        "Builtin 'pow' expects 2 arguments, but {} were given",
+                        args.len()
+                    );
+                };
+                let base = self.gen_expr(base)?;
+                let exp = self.gen_expr(exp)?;
+                self.gen_pow(base, exp).map(Some)
+            }
+            "min" | "max" => {
+                let [lhs, rhs] = args else {
+                    bail!(
+                        "Builtin '{}' expects 2 arguments, but {} were given",
+                        name,
+                        args.len()
+                    );
+                };
+                let lhs = self.gen_expr(lhs)?;
+                let rhs = self.gen_expr(rhs)?;
+                self.gen_min_max(name, lhs, rhs).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Generate LLVM IR for `assert(condition)`, which prints where it is and
+    /// aborts the program when the condition is false
+    fn gen_assert(
+        &mut self,
+        args: &'ctx [ast::Expr],
+        arg_names: &[&str],
+        span: &ast::Span,
+    ) -> Result<()> {
+        if !arg_names.is_empty() {
+            bail!("Builtin 'assert' does not take named arguments");
+        }
+        let [condition] = args else {
+            bail!(
+                "Builtin 'assert' expects 1 argument, but {} were given",
+                args.len()
+            );
+        };
+        let condition = self.gen_condition(condition)?;
+
+        let function = self
+            .builder
+            .get_insert_block()
+            .unwrap()
+            .get_parent()
+            .unwrap();
+        let fail_block = self.context.append_basic_block(function, "assertfail");
+        let cont_block = self.context.append_basic_block(function, "assertcont");
+        self.builder
+            .build_conditional_branch(condition, cont_block, fail_block)?;
+
+        // write(2, message, length) needs nothing but libc, unlike stderr
+        self.builder.position_at_end(fail_block);
+        let message = format!("Assertion failed at {}\n", self.location(span));
+        let text = self
+            .builder
+            .build_global_string_ptr(&message, "assertmsg")?;
+        let target_data = self
+            .target_data
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Failed to create target machine"))?;
+        let size_type = self.context.ptr_sized_int_type(target_data, None);
+        let ptr_type = self.context.ptr_type(AddressSpace::default());
+        let write_type = size_type.fn_type(
+            &[
+                self.context.i32_type().into(),
+                ptr_type.into(),
+                size_type.into(),
+            ],
+            false,
+        );
+        let write = self.libc_function("assert", "write", write_type)?;
+        self.builder.build_call(
+            write,
+            &[
+                self.context.i32_type().const_int(2, false).into(),
+                text.as_pointer_value().into(),
+                size_type.const_int(message.len() as u64, false).into(),
+            ],
+            "",
+        )?;
+        let abort_type = self.context.void_type().fn_type(&[], false);
+        let abort = self.libc_function("assert", "abort", abort_type)?;
+        if abort.get_first_basic_block().is_none() {
+            let kind = Attribute::get_named_enum_kind_id("noreturn");
+            abort.add_attribute(
+                AttributeLoc::Function,
+                self.context.create_enum_attribute(kind, 0),
+            );
+        }
+        self.builder.build_call(abort, &[], "")?;
+        self.builder.build_unreachable()?;
+
+        self.builder.position_at_end(cont_block);
+        Ok(())
+    }
+
+    /// The C function `name` that the builtin `builtin` calls, declared on first use.
+    /// A program function of the same name is only reused when its type matches
+    fn libc_function(
+        &self,
+        builtin: &str,
+        name: &str,
+        fn_type: FunctionType<'ctx>,
+    ) -> Result<FunctionValue<'ctx>> {
+        match self.module.get_function(name) {
+            Some(function) if function.get_type() == fn_type => Ok(function),
+            Some(_) => bail!(
+                "Builtin '{builtin}' calls the C function '{name}', but the program declares it with a different signature"
+            ),
+            None => Ok(self.module.add_function(name, fn_type, None)),
+        }
+    }
+
+    /// `file:line` of a span, for messages printed at run time
+    fn location(&self, span: &ast::Span) -> String {
+        let line = self
+            .source
+            .get(..span.start)
+            .map_or(1, |before| before.matches('\n').count() + 1);
+        format!("{}:{}", self.source_name, line)
+    }
+
+    /// Generate LLVM IR for `abs(value)` or `sqrt(value)`
+    fn gen_abs_sqrt(
+        &self,
+        name: &str,
+        value: BasicValueEnum<'ctx>,
+    ) -> Result<BasicValueEnum<'ctx>> {
+        match (name, value) {
+            ("abs", BasicValueEnum::IntValue(int)) if int.get_type().get_bit_width() > 1 => {
+                // Not poison for the minimum value, whose absolute value wraps to itself
+                let int_min_is_poison = self.context.bool_type().const_zero();
+                self.call_intrinsic(
+                    "llvm.abs",
+                    &[value.get_type()],
+                    &[value.into(), int_min_is_poison.into()],
+                    "abstmp",
+                )
+            }
+            ("abs", BasicValueEnum::FloatValue(_)) => {
+                self.call_intrinsic("llvm.fabs", &[value.get_type()], &[value.into()], "abstmp")
+            }
+            ("sqrt", BasicValueEnum::FloatValue(_)) => {
+                self.call_intrinsic("llvm.sqrt", &[value.get_type()], &[value.into()], "sqrttmp")
+            }
+            _ => bail!(
+                "Builtin '{}' does not support arguments of type {}",
+                name,
+                self.type_name(value.get_type())
+            ),
+        }
+    }
+
+    /// Generate LLVM IR for `min(lhs, rhs)` or `max(lhs, rhs)`
+    fn gen_min_max(
+        &self,
+        name: &str,
+        lhs: BasicValueEnum<'ctx>,
+        rhs: BasicValueEnum<'ctx>,
+    ) -> Result<BasicValueEnum<'ctx>> {
+        if lhs.get_type() != rhs.get_type() {
+            bail!(
+                "Type mismatch in '{}' arguments: {} and {}",
+                name,
+                self.type_name(lhs.get_type()),
+                self.type_name(rhs.get_type())
+            );
+        }
+        let intrinsic = match (name, lhs) {
+            ("min", BasicValueEnum::IntValue(value)) if value.get_type().get_bit_width() > 1 => {
+                "llvm.smin"
+            }
+            ("max", BasicValueEnum::IntValue(value)) if value.get_type().get_bit_width() > 1 => {
+                "llvm.smax"
+            }
+            ("min", BasicValueEnum::FloatValue(_)) => "llvm.minnum",
+            ("max", BasicValueEnum::FloatValue(_)) => "llvm.maxnum",
+            _ => bail!(
+                "Builtin '{}' does not support arguments of type {}",
+                name,
+                self.type_name(lhs.get_type())
+            ),
+        };
+        self.call_intrinsic(
+            intrinsic,
+            &[lhs.get_type()],
+            &[lhs.into(), rhs.into()],
+            &format!("{name}tmp"),
+        )
+    }
+
+    /// Generate LLVM IR for `pow(base, exp)`
+    fn gen_pow(
+        &self,
+        base: BasicValueEnum<'ctx>,
+        exp: BasicValueEnum<'ctx>,
+    ) -> Result<BasicValueEnum<'ctx>> {
+        match (base, exp) {
+            (BasicValueEnum::FloatValue(base), BasicValueEnum::FloatValue(exp)) => {
+                if base.get_type() != exp.get_type() {
+                    bail!(
+                        "Type mismatch in 'pow' arguments: {} and {}",
+                        self.type_name(base.get_type().into()),
+                        self.type_name(exp.get_type().into())
+                    );
+                }
+                self.call_intrinsic(
+                    "llvm.pow",
+                    &[base.get_type().into()],
+                    &[base.into(), exp.into()],
+                    "powtmp",
+                )
+            }
+            (BasicValueEnum::FloatValue(base), BasicValueEnum::IntValue(exp)) => {
+                // llvm.powi takes an i32 exponent
+                if exp.get_type().get_bit_width() != 32 {
+                    bail!("Builtin 'pow' expects an i32 exponent for a float base");
+                }
+                self.call_intrinsic(
+                    "llvm.powi",
+                    &[base.get_type().into(), exp.get_type().into()],
+                    &[base.into(), exp.into()],
+                    "powtmp",
+                )
+            }
+            (BasicValueEnum::IntValue(base), BasicValueEnum::IntValue(exp)) => {
+                let int_type = base.get_type();
+                if exp.get_type() != int_type || int_type.get_bit_width() == 1 {
+                    bail!("Builtin 'pow' expects two integers of the same type");
+                }
+
+                // There is no integer power intrinsic, so multiply in a loop.
+                // A negative exponent runs the loop zero times and yields 1.
+                let entry_block = self.builder.get_insert_block().unwrap();
+                let function = entry_block.get_parent().unwrap();
+                let cond_block = self.context.append_basic_block(function, "powcond");
+                let body_block = self.context.append_basic_block(function, "powbody");
+                let end_block = self.context.append_basic_block(function, "powend");
+
+                self.builder.build_unconditional_branch(cond_block)?;
+
+                self.builder.position_at_end(cond_block);
+                let result = self.builder.build_phi(int_type, "powresult")?;
+                let counter = self.builder.build_phi(int_type, "powcounter")?;
+                let keep_going = self.builder.build_int_compare(
+                    inkwell::IntPredicate::SLT,
+                    counter.as_basic_value().into_int_value(),
+                    exp,
+                    "powcmp",
+                )?;
+                self.builder
+                    .build_conditional_branch(keep_going, body_block, end_block)?;
+
+                self.builder.position_at_end(body_block);
+                let next_result = self.builder.build_int_mul(
+                    result.as_basic_value().into_int_value(),
+                    base,
+                    "powmul",
+                )?;
+                let next_counter = self.builder.build_int_add(
+                    counter.as_basic_value().into_int_value(),
+                    int_type.const_int(1, false),
+                    "powinc",
+                )?;
+                self.builder.build_unconditional_branch(cond_block)?;
+
+                result.add_incoming(&[
+                    (&int_type.const_int(1, false), entry_block),
+                    (&next_result, body_block),
+                ]);
+                counter.add_incoming(&[
+                    (&int_type.const_zero(), entry_block),
+                    (&next_counter, body_block),
+                ]);
+
+                self.builder.position_at_end(end_block);
+                Ok(result.as_basic_value())
+            }
+            _ => bail!("Builtin 'pow' expects numeric arguments"),
+        }
+    }
+
+    /// Negate an integer with `llvm.ssub.with.overflow`, trapping when `0 - value`
+    /// overflows instead of wrapping around
+    fn gen_checked_neg(&mut self, value: IntValue<'ctx>) -> Result<IntValue<'ctx>> {
+        let zero = value.get_type().const_zero();
+        let result = self
+            .call_intrinsic(
+                "llvm.ssub.with.overflow",
+                &[value.get_type().into()],
+                &[zero.into(), value.into()],
+                "negtmp",
+            )?
+            .into_struct_value();
+        let negated = self
+            .builder
+            .build_extract_value(result, 0, "negated")?
+            .into_int_value();
+        let overflow = self
+            .builder
+            .build_extract_value(result, 1, "overflow")?
+            .into_int_value();
+
+        let function = self
+            .builder
+            .get_insert_block()
+            .unwrap()
+            .get_parent()
+            .unwrap();
+        let trap_block = self.context.append_basic_block(function, "negoverflow");
+        let cont_block = self.context.append_basic_block(function, "negcont");
+        self.builder
+            .build_conditional_branch(overflow, trap_block, cont_block)?;
+
+        self.builder.position_at_end(trap_block);
+        let trap = Intrinsic::find("llvm.trap")
+            .and_then(|intrinsic| intrinsic.get_declaration(&self.module, &[]))
+            .ok_or_else(|| anyhow::anyhow!("Failed to declare intrinsic 'llvm.trap'"))?;
+        self.builder.build_call(trap, &[], "")?;
+        self.builder.build_unreachable()?;
+
+        self.builder.position_at_end(cont_block);
+        Ok(negated)
+    }
+
+    /// Call an LLVM intrinsic, declaring it in the module on first use
+    fn call_intrinsic(
+        &self,
+        name: &str,
+        types: &[BasicTypeEnum<'ctx>],
+        args: &[BasicMetadataValueEnum<'ctx>],
+        value_name: &str,
+    ) -> Result<BasicValueEnum<'ctx>> {
+        let function = Intrinsic::find(name)
+            .and_then(|intrinsic| intrinsic.get_declaration(&self.module, types))
+            .ok_or_else(|| anyhow::anyhow!("Failed to declare intrinsic '{}'", name))?;
+        self.builder
+            .build_call(function, args, value_name)?
+            .try_as_basic_value()
+            .left()
+            .ok_or_else(|| anyhow::anyhow!("Intrinsic '{}' returned no value", name))
+    }
+
+    /// Name an LLVM type the way it is written in the source, e.g. `i32`
+    fn type_name(&self, ty: BasicTypeEnum<'ctx>) -> String {
+        let ast_type = match ty {
+            BasicTypeEnum::IntType(int_type) => match int_type.get_bit_width() {
+                1 => Some(ast::Type::Bool),
+                32 => Some(ast::Type::I32),
+                64 => Some(ast::Type::I64),
+                _ => None,
+            },
+            BasicTypeEnum::FloatType(float_type) if float_type == self.context.f32_type() => {
+                Some(ast::Type::F32)
+            }
+            BasicTypeEnum::FloatType(float_type) if float_type == self.context.f64_type() => {
+                Some(ast::Type::F64)
+            }
+            BasicTypeEnum::StructType(struct_type) => self
+                .structs
+                .iter()
+                .find(|(_, struct_info)| struct_info.ty == struct_type)
+                .map(|(name, _)| ast::Type::Struct(*name)),
+            BasicTypeEnum::PointerType(_) => Some(ast::Type::String),
+            _ => None,
+        };
+        match ast_type {
+            Some(ast_type) => ast_type.to_string(),
+            None => ty.print_to_string().to_string(),
         }
     }
 
+    /// Resolve a field of a struct type to its index and LLVM type
+    fn resolve_field(
+        &self,
+        struct_type: StructType<'ctx>,
+        field: &str,
+    ) -> Result<(u32, BasicTypeEnum<'ctx>)> {
+        let (name, struct_info) = self
+            .structs
+            .iter()
+            .find(|(_, struct_info)| struct_info.ty == struct_type)
+            .ok_or_else(|| anyhow::anyhow!("Unknown struct type"))?;
+        let index = struct_info
+            .fields
+            .iter()
+            .position(|declared| *declared == field)
+            .ok_or_else(|| anyhow::anyhow!("Struct '{}' has no field '{}'", name, field))?
+            as u32;
+        let field_type = struct_type
+            .get_field_type_at_index(index)
+            .ok_or_else(|| anyhow::anyhow!("Invalid field index {} in '{}'", index, name))?;
+        Ok((index, field_type))
+    }
+
     /// Map AST type to LLVM type
     fn map_ast_type_to_llvm(&self, ty: ast::Type) -> Result<BasicTypeEnum<'ctx>> {
         match ty {
+            ast::Type::Bool => Ok(self.context.bool_type().into()),
             ast::Type::I32 => Ok(self.context.i32_type().into()),
             ast::Type::I64 => Ok(self.context.i64_type().into()),
             ast::Type::F32 => Ok(self.context.f32_type().into()),
             ast::Type::F64 => Ok(self.context.f64_type().into()),
             ast::Type::Void => bail!("Void type cannot be used directly as a variable type"),
-            ast::Type::String => bail!("String type not implemented"),
+            ast::Type::String => Ok(self.context.ptr_type(AddressSpace::default()).into()),
+            // Enums are represented by their i32 discriminant
+            ast::Type::Enum(_) => Ok(self.context.i32_type().into()),
+            ast::Type::Struct(name) if self.enums.contains_key(name) => {
+                Ok(self.context.i32_type().into())
+            }
+            ast::Type::Struct(name) => self
+                .structs
+                .get(name)
+                .map(|struct_info| struct_info.ty.into())
+                .ok_or_else(|| anyhow::anyhow!("Struct '{}' not found", name)),
         }
     }
 
     /// Get default value for a given AST type
     fn get_default_value(&self, ty: ast::Type) -> Result<BasicValueEnum<'ctx>> {
         match ty {
+            ast::Type::Bool => Ok(self.context.bool_type().const_zero().into()),
             ast::Type::I32 => Ok(self.context.i32_type().const_zero().into()),
             ast::Type::I64 => Ok(self.context.i64_type().const_zero().into()),
             ast::Type::F32 => Ok(self.context.f32_type().const_zero().into()),
             ast::Type::F64 => Ok(self.context.f64_type().const_zero().into()),
-            _ => bail!("Unsupported type for default value: {:?}", ty),
+            _ => bail!("Unsupported type for default value: {}", ty),
         }
     }
 
@@ -619,37 +2925,679 @@ impl<'ctx> CodeGen<'ctx> {
         self.module.print_to_string().to_string()
     }
 
+    /// Output the LLVM IR of a single function as a string
+    pub fn print_function(&self, name: &str) -> Result<String> {
+        let Some(function) = self.module.get_function(name) else {
+            bail!("Function '{}' not found", name);
+        };
+        Ok(function.print_to_string().to_string())
+    }
+
+    /// Render the control-flow graph of a single function as a Graphviz DOT digraph,
+    /// with a vertex per basic block and an edge per branch target
+    pub fn cfg_to_dot(&self, name: &str) -> Result<String> {
+        let Some(function) = self.module.get_function(name) else {
+            bail!("Function '{}' not found", name);
+        };
+        let blocks = function.get_basic_blocks();
+
+        let mut out = String::new();
+        writeln!(out, "digraph \"{}\" {{", name).unwrap();
+        out.push_str("    node [shape=box];\n");
+        for (id, block) in blocks.iter().enumerate() {
+            writeln!(
+                out,
+                "    n{id} [label=\"{}\"];",
+                block.get_name().to_string_lossy()
+            )
+            .unwrap();
+        }
+        for (from, block) in blocks.iter().enumerate() {
+            let Some(terminator) = block.get_terminator() else {
+                continue;
+            };
+            let is_conditional = terminator.get_opcode() == InstructionOpcode::Br
+                && terminator.get_num_operands() == 3;
+            for index in 0..terminator.get_num_operands() {
+                let Some(target) = terminator.get_operand(index).and_then(|op| op.right()) else {
+                    continue;
+                };
+                let to = blocks.iter().position(|block| *block == target).unwrap();
+                // A conditional branch lists its false target before its true target
+                match (is_conditional, index) {
+                    (true, 1) => writeln!(out, "    n{from} -> n{to} [label=\"false\"];"),
+                    (true, _) => writeln!(out, "    n{from} -> n{to} [label=\"true\"];"),
+                    (false, _) => writeln!(out, "    n{from} -> n{to};"),
+                }
+                .unwrap();
+            }
+        }
+        out.push_str("}\n");
+        Ok(out)
+    }
+
+    /// The functions in the module, in declaration order
+    pub fn symbols(&self) -> Vec<Symbol> {
+        self.module
+            .get_functions()
+            .map(|function| Symbol {
+                name: function.get_name().to_string_lossy().into_owned(),
+                signature: function
+                    .get_type()
+                    .print_to_string()
+                    .to_string_lossy()
+                    .into_owned(),
+                defined: !function.as_global_value().is_declaration(),
+            })
+            .collect()
+    }
+
+    /// List the functions in the module, one per line, as
+    /// `define <name>: <signature>` or `declare <name>: <signature>` for external declarations
+    pub fn print_symbols(&self) -> String {
+        self.symbols()
+            .iter()
+            .map(|symbol| {
+                let kind = if symbol.defined { "define" } else { "declare" };
+                format!("{kind} {}: {}\n", symbol.name, symbol.signature)
+            })
+            .collect()
+    }
+
+    /// The target triple the module is compiled for
+    pub fn target_triple(&self) -> String {
+        self.target_triple.as_str().to_string_lossy().into_owned()
+    }
+
+    /// Run a custom sequence of LLVM passes over the module
+    pub fn run_passes(&self, passes: &[String]) -> Result<()> {
+        for pass in passes {
+            if !SUPPORTED_PASSES.contains(&pass.as_str()) {
+                bail!(
+                    "Unknown pass '{}' (supported passes: {})",
+                    pass,
+                    SUPPORTED_PASSES.join(", ")
+                );
+            }
+        }
+
+        let target_machine = self.create_target_machine()?;
+        self.module
+            .run_passes(
+                &passes.join(","),
+                &target_machine,
+                PassBuilderOptions::create(),
+            )
+            .map_err(|e| anyhow::anyhow!("Failed to run passes: {}", e))
+    }
+
     /// Compile to a native executable file
-    pub fn compile_to_file(&self, filename: &str) -> Result<()> {
+    pub fn compile_to_file(&self, path: &Path) -> Result<()> {
+        let target_machine = self.create_target_machine()?;
+
+        // Instrumentation-based PGO: count the blocks, or annotate them with the counts
+        if self.profile_generate {
+            self.module
+                .run_passes(
+                    "pgo-instr-gen,instrprof",
+                    &target_machine,
+                    PassBuilderOptions::create(),
+                )
+                .map_err(|e| anyhow::anyhow!("Failed to instrument for profiling: {}", e))?;
+        }
+        // The counts only guide the passes after them, so the regular -O2 pipeline follows
+        if let Some(profile) = &self.profile_use {
+            set_pgo_profile_file(profile)?;
+            self.module
+                .run_passes(
+                    "pgo-instr-use,default<O2>",
+                    &target_machine,
+                    PassBuilderOptions::create(),
+                )
+                .map_err(|e| anyhow::anyhow!("Failed to apply profile: {}", e))?;
+        }
+
+        // The attribute only marks functions, the `asan` pass adds the checks
+        if self.sanitize_address {
+            self.module
+                .run_passes("asan", &target_machine, PassBuilderOptions::create())
+                .map_err(|e| anyhow::anyhow!("Failed to run AddressSanitizer: {}", e))?;
+        }
+
+        // Emit object file
+        target_machine
+            .write_to_file(&self.module, inkwell::targets::FileType::Object, path)
+            .map_err(|e| {
+                anyhow::anyhow!("Failed to write object file '{}': {}", path.display(), e)
+            })?;
+
+        Ok(())
+    }
+
+    /// Create a target machine for the target triple
+    fn create_target_machine(&self) -> Result<TargetMachine> {
         // Initialize the target
         Target::initialize_all(&InitializationConfig::default());
 
-        // Get the host target triple
-        let triple = TargetMachine::get_default_triple();
-        let target = Target::from_triple(&triple)
-            .map_err(|e| anyhow::anyhow!("Failed to get target from triple: {}", e))?;
+        let triple = &self.target_triple;
+        let target = Target::from_triple(triple).map_err(|e| {
+            anyhow::anyhow!(
+                "Failed to get target from triple '{}': {}",
+                triple.as_str().to_string_lossy(),
+                e
+            )
+        })?;
+
+        // Only tune for the host CPU when compiling for the host
+        let (cpu, features) = if triple.as_str() == TargetMachine::get_default_triple().as_str() {
+            (
+                TargetMachine::get_host_cpu_name().to_string(),
+                TargetMachine::get_host_cpu_features().to_string(),
+            )
+        } else {
+            ("generic".to_string(), String::new())
+        };
 
         // Create a target machine
         let target_machine = target
             .create_target_machine(
-                &triple,
-                &TargetMachine::get_host_cpu_name().to_string(),
-                &TargetMachine::get_host_cpu_features().to_string(),
-                OptimizationLevel::Default,
-                RelocMode::Default,
-                CodeModel::Default,
+                triple,
+                &cpu,
+                &features,
+                OPT_LEVEL,
+                self.reloc_mode,
+                self.code_model,
             )
             .ok_or_else(|| anyhow::anyhow!("Failed to create target machine"))?;
 
-        // Emit object file
-        target_machine
-            .write_to_file(
-                &self.module,
-                inkwell::targets::FileType::Object,
-                filename.as_ref(),
-            )
-            .map_err(|e| anyhow::anyhow!("Failed to write object file: {}", e))?;
+        Ok(target_machine)
+    }
+}
 
-        Ok(())
+/// The return type of a function declaration, which sema infers when it is omitted
+fn return_type<'a>(name: &str, r#type: Option<ast::Type<'a>>) -> Result<ast::Type<'a>> {
+    r#type.ok_or_else(|| anyhow::anyhow!("Return type of '{}' was not inferred", name))
+}
+
+/// Collect `stmts` and the statements nested in their bodies in source order, each with
+/// the names of the functions enclosing it. Statements inside expressions, such as the
+/// body of a labeled block, are left out
+fn nested_stmts<'a, 'src>(
+    stmts: &'a [ast::Stmt<'src>],
+    fn_path: &[&'a str],
+    out: &mut Vec<(Vec<&'a str>, &'a ast::Stmt<'src>)>,
+) {
+    for stmt in stmts {
+        out.push((fn_path.to_vec(), stmt));
+        match stmt {
+            ast::Stmt::FnDecl { name, body, .. } => {
+                nested_stmts(body, &[fn_path, &[*name]].concat(), out);
+            }
+            ast::Stmt::If {
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                nested_stmts(then_branch, fn_path, out);
+                nested_stmts(else_branch.as_deref().unwrap_or_default(), fn_path, out);
+            }
+            ast::Stmt::While { body, .. }
+            | ast::Stmt::DoWhile { body, .. }
+            | ast::Stmt::Block { body } => nested_stmts(body, fn_path, out),
+            _ => {}
+        }
+    }
+}
+
+/// The two arm expressions of an if/else that can be lowered to a `select`
+fn select_arms<'a, 'src>(
+    then_branch: &'a [ast::Stmt<'src>],
+    else_branch: Option<&'a [ast::Stmt<'src>]>,
+) -> Option<(&'a ast::Expr<'src>, &'a ast::Expr<'src>)> {
+    match (then_branch, else_branch) {
+        ([ast::Stmt::Expr { expr: then_expr }], Some([ast::Stmt::Expr { expr: else_expr }]))
+            if is_speculatable(then_expr) && is_speculatable(else_expr) =>
+        {
+            Some((then_expr.as_ref(), else_expr.as_ref()))
+        }
+        _ => None,
+    }
+}
+
+/// Whether an expression can be evaluated even when its value is not used,
+/// i.e. it has no side effects and cannot trap
+fn is_speculatable(expr: &ast::Expr) -> bool {
+    match &expr.kind {
+        ast::ExprKind::IntLit(_)
+        | ast::ExprKind::FloatLit(_)
+        | ast::ExprKind::BoolLit(_)
+        | ast::ExprKind::StrLit(_)
+        | ast::ExprKind::VarRef { .. }
+        | ast::ExprKind::EnumVariant { .. }
+        | ast::ExprKind::SizeOf { .. } => true,
+        // Integer division by zero is undefined behavior
+        ast::ExprKind::BinOp {
+            op: ast::BinOp::Div,
+            ..
+        } => false,
+        ast::ExprKind::BinOp { lhs, rhs, .. } => is_speculatable(lhs) && is_speculatable(rhs),
+        ast::ExprKind::UnaryOp { expr, .. }
+        | ast::ExprKind::FieldAccess { expr, .. }
+        | ast::ExprKind::Cast { expr, .. } => is_speculatable(expr),
+        ast::ExprKind::FnCall { .. }
+        | ast::ExprKind::StructLit { .. }
+        | ast::ExprKind::Block { .. }
+        | ast::ExprKind::If { .. }
+        | ast::ExprKind::Match { .. }
+        | ast::ExprKind::IncDec { .. } => false,
+        ast::ExprKind::Sequence { exprs } => exprs.iter().all(is_speculatable),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use indoc::indoc;
+
+    fn compile_error(input: &str) -> String {
+        let program = parse(input).into_result().unwrap();
+        let context = Context::create();
+        let mut codegen = CodeGen::new(&context, "test");
+        codegen.compile(&program).unwrap_err().to_string()
+    }
+
+    fn compile_ir(input: &str) -> String {
+        let program = parse(input).into_result().unwrap();
+        let context = Context::create();
+        let mut codegen = CodeGen::new(&context, "test");
+        codegen.compile(&program).unwrap();
+        codegen.print_ir()
+    }
+
+    #[test]
+    fn test_functions_in_source_order() {
+        let input = indoc! {"
+            fn first() -> i32 {
+                fn helper() -> i32 { 1 }
+                helper()
+            }
+            if true {
+                fn in_block() -> i32 { 2 }
+                in_block();
+            }
+            fn second() -> i32 {
+                struct Pair { a: i32, b: i32 }
+                fn helper(pair: Pair) -> i32 { pair.a + pair.b }
+                helper(Pair { a: 1, b: 2 })
+            }
+            first() + second()
+        "};
+        let ir = compile_ir(input);
+        let defined: Vec<_> = ir
+            .lines()
+            .filter(|line| line.starts_with("define "))
+            .filter_map(|line| line.split_once('@')?.1.split_once('('))
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(
+            defined,
+            [
+                "main",
+                "first",
+                "first$helper",
+                "in_block",
+                "second",
+                "second$helper"
+            ]
+        );
+
+        // The same input always gives the same module
+        assert_eq!(compile_ir(input), ir);
+    }
+
+    #[test]
+    fn test_assert() {
+        let input = "let x = 1;\nassert(x == 1);\nx";
+        let program = parse(input).into_result().unwrap();
+        let context = Context::create();
+        let mut codegen = CodeGen::new(&context, "test");
+        codegen.set_source("main.aic", input);
+        codegen.compile(&program).unwrap();
+        let ir = codegen.print_ir();
+        assert!(ir.contains("Assertion failed at main.aic:2\\0A"), "{ir}");
+        assert!(
+            ir.contains("br i1 %cmptmp, label %assertcont, label %assertfail"),
+            "{ir}"
+        );
+        assert!(ir.contains("call void @abort()"), "{ir}");
+        assert!(ir.contains("unreachable"), "{ir}");
+    }
+
+    #[test]
+    fn test_assert_rejects_mismatched_write() {
+        let input = "fn write(x: i32) -> i32 { x }\nassert(write(1) == 1);\n0";
+        let program = parse(input).into_result().unwrap();
+        let context = Context::create();
+        let mut codegen = CodeGen::new(&context, "test");
+        let error = codegen.compile(&program).unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "Builtin 'assert' calls the C function 'write', but the program declares it with a different signature"
+        );
+    }
+
+    #[test]
+    fn test_fast_math_flags() {
+        let input = "fn f(x: f64, y: f64) -> bool { -(x * y) < x }\nlet _ = f(1.0, 2.0);\n0";
+        let program = parse(input).into_result().unwrap();
+        let context = Context::create();
+        let mut codegen = CodeGen::new(&context, "test");
+        codegen.set_fast_math(true);
+        codegen.compile(&program).unwrap();
+        let ir = codegen.print_ir();
+        assert!(ir.contains("fmul fast double"), "{ir}");
+        assert!(ir.contains("fneg fast double"), "{ir}");
+        assert!(ir.contains("fcmp fast olt double"), "{ir}");
+
+        let ir = compile_ir(input);
+        assert!(!ir.contains(" fast "), "{ir}");
+    }
+
+    #[test]
+    fn test_function_attributes() {
+        let ir = compile_ir(
+            "#[inline]\nfn one() -> i32 { 1 }\n#[noinline]\nfn two() -> i32 { 2 }\none() + two()",
+        );
+        assert!(ir.contains("define i32 @one() #0"), "{ir}");
+        assert!(ir.contains("define i32 @two() #1"), "{ir}");
+        assert!(ir.contains("attributes #0 = { alwaysinline }"), "{ir}");
+        assert!(ir.contains("attributes #1 = { noinline }"), "{ir}");
+    }
+
+    #[test]
+    fn test_sanitize_address() {
+        let input = "extern fn abs(x: i32) -> i32;\nfn f() -> i32 { abs(-1) }\nf()";
+        let program = parse(input).into_result().unwrap();
+        let context = Context::create();
+        let mut codegen = CodeGen::new(&context, "test");
+        codegen.set_sanitize_address(true);
+        codegen.compile(&program).unwrap();
+        let ir = codegen.print_ir();
+        assert!(ir.contains("define i32 @main() #0"), "{ir}");
+        assert!(ir.contains("define i32 @f() #0"), "{ir}");
+        assert!(ir.contains("declare i32 @abs(i32)\n"), "{ir}");
+        assert!(ir.contains("attributes #0 = { sanitize_address }"), "{ir}");
+
+        let ir = compile_ir(input);
+        assert!(!ir.contains("sanitize_address"), "{ir}");
+    }
+
+    #[test]
+    fn test_no_std_main() {
+        let input = "extern fn halt() -> void;\nfn _start() { halt(); }";
+        let program = parse(input).into_result().unwrap();
+        let context = Context::create();
+        let mut codegen = CodeGen::new(&context, "test");
+        codegen.set_no_std_main(true);
+        codegen.compile(&program).unwrap();
+        let ir = codegen.print_ir();
+        assert!(!ir.contains("@main"), "{ir}");
+        assert!(ir.contains("define void @_start() #0"), "{ir}");
+        assert!(
+            ir.contains("attributes #0 = { nounwind \"stackrealign\" }"),
+            "{ir}"
+        );
+
+        let program = parse("fn _start() {}\n_start();").into_result().unwrap();
+        let mut codegen = CodeGen::new(&context, "test");
+        codegen.set_no_std_main(true);
+        let error = codegen.compile(&program).unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "Only declarations are allowed at the top level without a synthetic main"
+        );
+    }
+
+    #[test]
+    fn test_negation_wraps() {
+        let ir = compile_ir("let min = -2147483647 - 1;\n-min");
+        assert!(ir.contains("%negtmp = sub i32 0, %"), "{ir}");
+        assert!(!ir.contains("nsw"), "{ir}");
+    }
+
+    #[test]
+    fn test_checked_negation_traps() {
+        let input = "let min = -2147483647 - 1;\n-min";
+        let program = parse(input).into_result().unwrap();
+        let context = Context::create();
+        let mut codegen = CodeGen::new(&context, "test");
+        codegen.set_checked_arith(true);
+        codegen.compile(&program).unwrap();
+        let ir = codegen.print_ir();
+        assert!(
+            ir.contains("call { i32, i1 } @llvm.ssub.with.overflow.i32(i32 0, i32 %"),
+            "{ir}"
+        );
+        assert!(
+            ir.contains("br i1 %overflow, label %negoverflow, label %negcont"),
+            "{ir}"
+        );
+        assert!(ir.contains("call void @llvm.trap()"), "{ir}");
+    }
+
+    #[test]
+    fn test_entry_realigns_stack() {
+        let program = parse("0").into_result().unwrap();
+        let context = Context::create();
+        let mut codegen = CodeGen::new(&context, "test");
+        codegen.set_entry("_start");
+        codegen.compile(&program).unwrap();
+        let ir = codegen.print_ir();
+        assert!(ir.contains("define i32 @_start() #0"), "{ir}");
+        assert!(ir.contains("attributes #0 = { \"stackrealign\" }"), "{ir}");
+
+        let ir = compile_ir("0");
+        assert!(!ir.contains("stackrealign"), "{ir}");
+    }
+
+    #[test]
+    fn test_int_literal_takes_operand_type() {
+        let ir = compile_ir("var n: i64 = 0;\nn = 1;\nn = 2 * n + 3;\n(n > 4) as i32");
+        assert!(ir.contains("store i64 1, ptr @n"), "{ir}");
+        assert!(ir.contains("mul i64 2, %"), "{ir}");
+        assert!(ir.contains("add i64 %multmp, 3"), "{ir}");
+        assert!(ir.contains("icmp sgt i64 %"), "{ir}");
+    }
+
+    #[test]
+    fn test_pgo_profile_file_is_set_once() {
+        set_pgo_profile_file(Path::new("first.profdata")).unwrap();
+        set_pgo_profile_file(Path::new("first.profdata")).unwrap();
+        let error = set_pgo_profile_file(Path::new("second.profdata")).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Cannot use profile 'second.profdata', only one profile can be used per process and 'first.profdata' already is"
+        );
+    }
+
+    #[test]
+    fn test_discarded_final_expression_returns_zero() {
+        let ir = compile_ir("let x = 5;\nx + 1;");
+        assert!(ir.contains("ret i32 0"), "{ir}");
+
+        let ir = compile_ir("var x = 5;\nif x > 1 { x = 1; }");
+        assert!(ir.contains("ret i32 0"), "{ir}");
+    }
+
+    #[test]
+    fn test_cfg_to_dot() {
+        let program = parse("fn pick(x: i32) -> i32 { if x > 0 { return 1; } 2 }\npick(1)")
+            .into_result()
+            .unwrap();
+        let context = Context::create();
+        let mut codegen = CodeGen::new(&context, "test");
+        codegen.compile(&program).unwrap();
+
+        let dot = codegen.cfg_to_dot("pick").unwrap();
+        assert!(dot.starts_with("digraph \"pick\" {"), "{dot}");
+        let (edges, nodes): (Vec<_>, Vec<_>) = dot
+            .lines()
+            .filter(|line| line.contains("[label="))
+            .partition(|line| line.contains(" -> "));
+        assert!(nodes.len() >= 3, "{dot}");
+        assert!(edges.len() >= 2, "{dot}");
+        assert!(dot.contains("[label=\"true\"]"), "{dot}");
+        assert!(dot.contains("[label=\"false\"]"), "{dot}");
+
+        assert_eq!(
+            codegen.cfg_to_dot("missing").unwrap_err().to_string(),
+            "Function 'missing' not found"
+        );
+    }
+
+    #[test]
+    fn test_clean_ir() {
+        let ir = indoc! {r#"
+            ; ModuleID = 'test'
+            source_filename = "test"
+            target triple = "x86_64-unknown-linux-gnu"
+
+            @str = private unnamed_addr constant [9 x i8] c"a, align\00", align 1
+
+            ; Function Attrs: noinline
+            define i32 @f() #0 {
+            entry:
+              %x = alloca i32, align 4
+              store i32 1, ptr %x, align 4
+              ret i32 1
+            }
+
+            attributes #0 = { noinline }
+        "#};
+        assert_eq!(
+            clean_ir(ir),
+            indoc! {r#"
+                @str = private unnamed_addr constant [9 x i8] c"a, align\00"
+
+                define i32 @f() {
+                entry:
+                  %x = alloca i32
+                  store i32 1, ptr %x
+                  ret i32 1
+                }
+            "#}
+        );
+    }
+
+    #[test]
+    fn test_if_expression() {
+        // Side-effect free branches are selected between, others are branched to
+        let ir = compile_ir("let x = 3;\nlet y = if x > 2 { 10 } else { 20 };\ny");
+        assert!(ir.contains("select i1"), "{ir}");
+
+        let ir = compile_ir("let x = 3;\nlet y = if x > 2 { let z = x * 2; z } else { 20 };\ny");
+        assert!(ir.contains("phi i32"), "{ir}");
+
+        assert_eq!(
+            compile_error("let y = if true { 1 } else { 2.5 };\ny"),
+            "if and else have incompatible types: i32 vs f64"
+        );
+    }
+
+    #[test]
+    fn test_global_variables() {
+        let ir = compile_ir(indoc! {"
+            var counter = 0;
+            var start = counter + 1;
+            fn bump() { counter = counter + 1; }
+            bump();
+            counter + start
+        "});
+        // A constant initializes the global, anything else is stored by `main`
+        assert!(ir.contains("@counter = internal global i32 0"), "{ir}");
+        assert!(ir.contains("@start = internal global i32 0"), "{ir}");
+        assert!(ir.contains(", ptr @start"), "{ir}");
+        assert!(ir.contains("load i32, ptr @counter"), "{ir}");
+        assert!(!ir.contains("alloca"), "{ir}");
+    }
+
+    #[test]
+    fn test_struct_abi() {
+        let ir = compile_ir(indoc! {"
+            struct Pair { a: i32, b: i32 }
+            struct Row { a: i32, b: i32, c: i32, d: i32, e: i32 }
+            fn swap(p: Pair) -> Pair { Pair { a: p.b, b: p.a } }
+            fn widen(p: Pair) -> Row { Row { a: p.a, b: p.b, c: 0, d: 0, e: 0 } }
+            fn total(r: Row) -> i32 { r.a + r.b + r.c + r.d + r.e }
+            total(widen(swap(Pair { a: 1, b: 2 })))
+        "});
+        // Small structs stay in registers, large ones go through pointers
+        assert!(ir.contains("define %Pair @swap(%Pair"), "{ir}");
+        assert!(ir.contains("define void @widen(ptr sret(%Row)"), "{ir}");
+        assert!(ir.contains("define i32 @total(ptr byval(%Row)"), "{ir}");
+        assert!(ir.contains("call void @widen(ptr sret(%Row)"), "{ir}");
+        assert!(ir.contains("call i32 @total(ptr byval(%Row)"), "{ir}");
+    }
+
+    #[test]
+    fn test_struct_abi_aarch64() {
+        let program = parse(indoc! {"
+            struct Row { a: i32, b: i32, c: i32, d: i32, e: i32 }
+            fn total(r: Row) -> i32 { r.a + r.b + r.c + r.d + r.e }
+            total(Row { a: 1, b: 2, c: 3, d: 4, e: 5 })
+        "})
+        .into_result()
+        .unwrap();
+        let context = Context::create();
+        let mut codegen = CodeGen::new(&context, "test");
+        codegen.set_target("aarch64-unknown-linux-gnu").unwrap();
+        codegen.compile(&program).unwrap();
+        let ir = codegen.print_ir();
+        // The callee gets a pointer to the caller's copy, not a copy on the stack
+        assert!(ir.contains("define i32 @total(ptr "), "{ir}");
+        assert!(ir.contains("call i32 @total(ptr %byval)"), "{ir}");
+        assert!(!ir.contains("byval("), "{ir}");
+    }
+
+    #[test]
+    fn test_large_literals_are_i64() {
+        let ir = compile_ir("let big: i64 = 9223372036854775807;\nlet min: i32 = -2147483648;\n0");
+        assert!(ir.contains("store i64 9223372036854775807"), "{ir}");
+        assert!(ir.contains("store i32 -2147483648"), "{ir}");
+    }
+
+    #[test]
+    fn test_type_mismatch_uses_source_type_names() {
+        assert_eq!(
+            compile_error("let y = 1;\nlet x: i64 = y;\nx"),
+            "Type mismatch in let declaration: expected i64, found i32"
+        );
+        assert_eq!(
+            compile_error("let x = 1.5;\nx == true"),
+            "Type mismatch in equality operation: f64 and bool"
+        );
+    }
+
+    #[test]
+    fn test_self_tail_calls_are_marked() {
+        let ir = compile_ir(indoc! {"
+            fn count(n: i32, acc: i32) -> i32 {
+                if n == 0 {
+                    return acc;
+                }
+                return count(n - 1, acc + 1);
+            }
+            fn twice(n: i32) -> i32 {
+                return count(n, 0) + count(n, 0);
+            }
+            fn wrap(n: i32) -> i32 {
+                return count(n, 0);
+            }
+            twice(1) + wrap(1)
+        "});
+        // Only the recursive call in `count` is both a self call and in tail position
+        assert_eq!(ir.matches("tail call").count(), 1, "ir was: {ir}");
+        assert!(ir.contains("tail call i32 @count"), "ir was: {ir}");
     }
 }
@@ -0,0 +1,123 @@
+use ariadne::{Color, Config, IndexType, Label, Report, ReportKind, Source};
+
+use crate::ast::Span;
+
+/// How severe a diagnostic is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// Compilation fails
+    Error,
+    /// Compilation continues unless warnings are treated as errors
+    Warning,
+}
+
+/// A compile error or warning attached to a location in the source code
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    /// How severe the diagnostic is
+    pub severity: Severity,
+    /// The error message
+    pub message: String,
+    /// The source location the error points at
+    pub span: Span,
+    /// Additional locations related to the error
+    pub labels: Vec<(Span, String)>,
+}
+
+impl Diagnostic {
+    /// Create a new error diagnostic
+    pub fn error(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            severity: Severity::Error,
+            message: message.into(),
+            span,
+            labels: Vec::new(),
+        }
+    }
+
+    /// Create a new warning diagnostic
+    pub fn warning(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(message, span)
+        }
+    }
+
+    /// Whether the diagnostic fails compilation
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// Attach a secondary label pointing at a related location
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push((span, message.into()));
+        self
+    }
+
+    /// Print the diagnostic as an ariadne report to stderr
+    pub fn eprint(&self, src: &str) {
+        let (kind, color) = match self.severity {
+            Severity::Error => (ReportKind::Error, Color::Red),
+            Severity::Warning => (ReportKind::Warning, Color::Yellow),
+        };
+        let mut report = Report::build(kind, ((), self.span.clone()))
+            .with_config(Config::new().with_index_type(IndexType::Byte))
+            .with_message(&self.message)
+            .with_label(
+                Label::new(((), self.span.clone()))
+                    .with_message(&self.message)
+                    .with_color(color),
+            );
+        for (span, message) in &self.labels {
+            report = report.with_label(
+                Label::new(((), span.clone()))
+                    .with_message(message)
+                    .with_color(Color::Blue),
+            );
+        }
+        report.finish().eprint(Source::from(src)).unwrap();
+    }
+}
+
+/// Print diagnostics to stderr and return whether any of them is an error.
+/// With `werror`, warnings are promoted to errors first.
+/// At most `max_errors` diagnostics are printed (0 means no limit).
+pub fn report(diagnostics: &mut [Diagnostic], src: &str, werror: bool, max_errors: usize) -> bool {
+    if werror {
+        for diagnostic in diagnostics.iter_mut() {
+            diagnostic.severity = Severity::Error;
+        }
+    }
+    let shown = shown_count(diagnostics.len(), max_errors);
+    for diagnostic in &diagnostics[..shown] {
+        diagnostic.eprint(src);
+    }
+    eprint_omitted(diagnostics.len() - shown);
+    diagnostics.iter().any(Diagnostic::is_error)
+}
+
+/// How many of `total` diagnostics to print when capped at `max_errors` (0 means no limit)
+pub fn shown_count(total: usize, max_errors: usize) -> usize {
+    if max_errors == 0 {
+        total
+    } else {
+        total.min(max_errors)
+    }
+}
+
+/// Tell the user how many diagnostics were left out by the cap
+pub fn eprint_omitted(omitted: usize) {
+    match omitted {
+        0 => {}
+        1 => eprintln!("...and 1 more error."),
+        _ => eprintln!("...and {omitted} more errors."),
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for Diagnostic {}
@@ -0,0 +1,364 @@
+use std::fmt::Write;
+
+use crate::ast;
+
+/// Render a program as a Graphviz DOT digraph
+pub fn program_to_dot(program: &ast::Program) -> String {
+    let mut dot = DotWriter::default();
+    dot.out.push_str("digraph ast {\n");
+    dot.out.push_str("    node [shape=box];\n");
+
+    let root = dot.node("Program");
+    for stmt in &program.statements {
+        let child = dot.stmt(stmt);
+        dot.edge(root, child, None);
+    }
+
+    dot.out.push_str("}\n");
+    dot.out
+}
+
+#[derive(Default)]
+struct DotWriter {
+    out: String,
+    next_id: usize,
+}
+
+impl DotWriter {
+    /// Emit a vertex and return its id
+    fn node(&mut self, label: &str) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        writeln!(self.out, "    n{} [label=\"{}\"];", id, escape(label)).unwrap();
+        id
+    }
+
+    /// Emit an edge between two vertices
+    fn edge(&mut self, from: usize, to: usize, label: Option<&str>) {
+        match label {
+            Some(label) => writeln!(
+                self.out,
+                "    n{from} -> n{to} [label=\"{}\"];",
+                escape(label)
+            )
+            .unwrap(),
+            None => writeln!(self.out, "    n{from} -> n{to};").unwrap(),
+        }
+    }
+
+    /// Emit a vertex for a list of statements with edges to each statement
+    fn block(&mut self, parent: usize, label: &str, stmts: &[ast::Stmt]) {
+        let block = self.node("Block");
+        self.edge(parent, block, Some(label));
+        for stmt in stmts {
+            let child = self.stmt(stmt);
+            self.edge(block, child, None);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &ast::Stmt) -> usize {
+        match stmt {
+            ast::Stmt::FnDecl {
+                name,
+                params,
+                r#type,
+                body,
+                ..
+            } => {
+                let params = params
+                    .iter()
+                    .map(|param| format!("{}: {:?}", param.name, param.r#type))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let id = match r#type {
+                    Some(ty) => self.node(&format!("FnDecl {name}({params}) -> {ty:?}")),
+                    None => self.node(&format!("FnDecl {name}({params})")),
+                };
+                self.block(id, "body", body);
+                id
+            }
+            ast::Stmt::ExternDecl {
+                name,
+                params,
+                r#type,
+                ..
+            } => {
+                let params = params
+                    .iter()
+                    .map(|param| format!("{}: {:?}", param.name, param.r#type))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                self.node(&format!("ExternDecl {name}({params}) -> {:?}", r#type))
+            }
+            ast::Stmt::StructDecl { name, fields, .. } => {
+                let id = self.node(&format!("StructDecl {name}"));
+                for field in fields {
+                    let child = self.node(&format!("{}: {:?}", field.name, field.r#type));
+                    self.edge(id, child, None);
+                }
+                id
+            }
+            ast::Stmt::EnumDecl { name, variants, .. } => {
+                let id = self.node(&format!("EnumDecl {name}"));
+                for variant in variants {
+                    let label = match variant.value {
+                        Some(value) => format!("{} = {value}", variant.name),
+                        None => variant.name.to_string(),
+                    };
+                    let child = self.node(&label);
+                    self.edge(id, child, None);
+                }
+                id
+            }
+            ast::Stmt::LetDecl {
+                is_const: true,
+                name,
+                r#type,
+                value,
+                ..
+            } => self.decl("ConstDecl", name, r#type, value),
+            ast::Stmt::LetDecl {
+                name,
+                r#type,
+                value,
+                ..
+            } => self.decl("LetDecl", name, r#type, value),
+            ast::Stmt::VarDecl {
+                name,
+                r#type,
+                value,
+                ..
+            } => self.decl("VarDecl", name, r#type, value),
+            ast::Stmt::Assign { name, value, .. } => {
+                let id = self.node(&format!("Assign {name}"));
+                let child = self.expr(value);
+                self.edge(id, child, None);
+                id
+            }
+            ast::Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                let id = self.node("If");
+                let child = self.expr(condition);
+                self.edge(id, child, Some("condition"));
+                self.block(id, "then", then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.block(id, "else", else_branch);
+                }
+                id
+            }
+            ast::Stmt::While { condition, body } => {
+                let id = self.node("While");
+                let child = self.expr(condition);
+                self.edge(id, child, Some("condition"));
+                self.block(id, "body", body);
+                id
+            }
+            ast::Stmt::DoWhile { body, condition } => {
+                let id = self.node("DoWhile");
+                self.block(id, "body", body);
+                let child = self.expr(condition);
+                self.edge(id, child, Some("condition"));
+                id
+            }
+            ast::Stmt::Block { body } => {
+                let id = self.node("Block");
+                self.block(id, "body", body);
+                id
+            }
+            ast::Stmt::Break {
+                label: Some(label),
+                value,
+                ..
+            } => {
+                let id = self.node(&format!("Break '{label}"));
+                if let Some(value) = value {
+                    let child = self.expr(value);
+                    self.edge(id, child, None);
+                }
+                id
+            }
+            ast::Stmt::Break { .. } => self.node("Break"),
+            ast::Stmt::Continue { .. } => self.node("Continue"),
+            ast::Stmt::Return { expr, .. } => {
+                let id = self.node("Return");
+                if let Some(expr) = expr {
+                    let child = self.expr(expr);
+                    self.edge(id, child, None);
+                }
+                id
+            }
+            ast::Stmt::ExprStmt { expr } => {
+                let id = self.node("ExprStmt");
+                let child = self.expr(expr);
+                self.edge(id, child, None);
+                id
+            }
+            ast::Stmt::Expr { expr } => {
+                let id = self.node("Expr");
+                let child = self.expr(expr);
+                self.edge(id, child, None);
+                id
+            }
+        }
+    }
+
+    fn decl(
+        &mut self,
+        kind: &str,
+        name: &str,
+        r#type: &Option<ast::Type>,
+        value: &Option<ast::Expr>,
+    ) -> usize {
+        let label = match r#type {
+            Some(ty) => format!("{kind} {name}: {ty:?}"),
+            None => format!("{kind} {name}"),
+        };
+        let id = self.node(&label);
+        if let Some(value) = value {
+            let child = self.expr(value);
+            self.edge(id, child, None);
+        }
+        id
+    }
+
+    fn expr(&mut self, expr: &ast::Expr) -> usize {
+        match &expr.kind {
+            ast::ExprKind::IntLit(value) => self.node(&format!("IntLit {value}")),
+            ast::ExprKind::FloatLit(value) => self.node(&format!("FloatLit {value}")),
+            ast::ExprKind::BoolLit(value) => self.node(&format!("BoolLit {value}")),
+            ast::ExprKind::StrLit(value) => self.node(&format!("StrLit {value:?}")),
+            ast::ExprKind::BinOp { lhs, op, rhs } => {
+                let id = self.node(&format!("BinOp {op:?}"));
+                let lhs = self.expr(lhs);
+                self.edge(id, lhs, Some("lhs"));
+                let rhs = self.expr(rhs);
+                self.edge(id, rhs, Some("rhs"));
+                id
+            }
+            ast::ExprKind::UnaryOp { op, expr } => {
+                let id = self.node(&format!("UnaryOp {op:?}"));
+                let child = self.expr(expr);
+                self.edge(id, child, None);
+                id
+            }
+            ast::ExprKind::FnCall {
+                name,
+                args,
+                arg_names,
+            } => {
+                let id = self.node(&format!("FnCall {name}"));
+                let positional = args.len() - arg_names.len();
+                for (i, arg) in args.iter().enumerate() {
+                    let child = self.expr(arg);
+                    let label = match i.checked_sub(positional) {
+                        Some(named) => arg_names[named].to_string(),
+                        None => format!("arg{i}"),
+                    };
+                    self.edge(id, child, Some(&label));
+                }
+                id
+            }
+            ast::ExprKind::VarRef { name } => self.node(&format!("VarRef {name}")),
+            ast::ExprKind::StructLit { name, fields } => {
+                let id = self.node(&format!("StructLit {name}"));
+                for field in fields {
+                    let child = self.expr(&field.value);
+                    self.edge(id, child, Some(field.name));
+                }
+                id
+            }
+            ast::ExprKind::FieldAccess { expr, field } => {
+                let id = self.node(&format!("FieldAccess .{field}"));
+                let child = self.expr(expr);
+                self.edge(id, child, None);
+                id
+            }
+            ast::ExprKind::EnumVariant { name, variant } => {
+                self.node(&format!("EnumVariant {name}::{variant}"))
+            }
+            ast::ExprKind::Cast { expr, r#type } => {
+                let id = self.node(&format!("Cast {:?}", r#type));
+                let child = self.expr(expr);
+                self.edge(id, child, None);
+                id
+            }
+            ast::ExprKind::Block { label, body } => {
+                let id = self.node(&format!("Block '{label}"));
+                self.block(id, "body", body);
+                id
+            }
+            ast::ExprKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                let id = self.node("If");
+                let child = self.expr(condition);
+                self.edge(id, child, Some("condition"));
+                self.block(id, "then", then_branch);
+                self.block(id, "else", else_branch);
+                id
+            }
+            ast::ExprKind::SizeOf { r#type } => self.node(&format!("SizeOf {:?}", r#type)),
+            ast::ExprKind::IncDec { op, name, postfix } => {
+                let position = if *postfix { "postfix" } else { "prefix" };
+                self.node(&format!("IncDec {op:?} {name} ({position})"))
+            }
+            ast::ExprKind::Match { scrutinee, arms } => {
+                let id = self.node("Match");
+                let child = self.expr(scrutinee);
+                self.edge(id, child, Some("scrutinee"));
+                for arm in arms {
+                    let label = match arm.pattern {
+                        ast::Pattern::IntLit(value) => value.to_string(),
+                        ast::Pattern::Wildcard => "_".to_string(),
+                    };
+                    let child = self.expr(&arm.value);
+                    self.edge(id, child, Some(&label));
+                }
+                id
+            }
+            ast::ExprKind::Sequence { exprs } => {
+                let id = self.node("Sequence");
+                for (i, expr) in exprs.iter().enumerate() {
+                    let child = self.expr(expr);
+                    self.edge(id, child, Some(&i.to_string()));
+                }
+                id
+            }
+        }
+    }
+}
+
+/// Escape a string for use inside a quoted DOT label
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use indoc::indoc;
+
+    #[test]
+    fn test_program_to_dot() {
+        let input = indoc! {"
+            fn add(a: i32, b: i32) -> i32 {
+                a + b
+            }
+            if add(1, 2) == 3 { 1 } else { 0 }
+        "};
+        let program = parse(input).into_result().unwrap();
+        let dot = program_to_dot(&program);
+
+        assert!(dot.starts_with("digraph ast {"));
+        assert_eq!(dot.matches('{').count(), dot.matches('}').count());
+        assert!(dot.contains("label=\"FnDecl add(a: I32, b: I32) -> I32\""));
+        assert!(dot.contains("label=\"BinOp Add\""));
+        assert!(dot.contains("label=\"else\""));
+    }
+}
@@ -0,0 +1,659 @@
+//! Constant folding over the AST
+
+use std::collections::HashMap;
+
+use crate::{
+    ast,
+    diagnostic::Diagnostic,
+    visit::{self, Visitor, VisitorMut},
+};
+
+/// Replace constant integer arithmetic, comparisons and logical operations with their result
+///
+/// Only literals that fit in 32 bits are folded, so folding wraps exactly like
+/// the generated code would. Operations whose result is undefined at runtime
+/// (division by zero, `i32::MIN / -1`) are left untouched.
+pub fn fold_program(program: &mut ast::Program) -> Vec<Diagnostic> {
+    fold_program_with(program, Options::default())
+}
+
+/// Language options that change the value of constant expressions
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Options {
+    /// Round integer division toward negative infinity instead of toward zero
+    pub floor_div: bool,
+    /// Trap on overflowing integer negation, which is then left to run at runtime
+    pub checked_arith: bool,
+}
+
+/// Replace constant expressions with their result, as computed under `options`.
+/// Calls to a top-level `const fn` whose arguments are constant are evaluated too,
+/// and the initializer of a `const` item is replaced with its value. An initializer
+/// that can't be computed at compile time is reported as an error
+pub fn fold_program_with(program: &mut ast::Program, options: Options) -> Vec<Diagnostic> {
+    let const_fns = collect_const_fns(program);
+    let mut folder = ConstantFolder {
+        const_fns: &const_fns,
+        options,
+    };
+    // The values of the const items declared so far, which later initializers can use
+    let mut consts = Vars::new();
+    let mut diagnostics = Vec::new();
+    for stmt in &mut program.statements {
+        folder.visit_stmt_mut(stmt);
+        match stmt {
+            ast::Stmt::LetDecl {
+                is_const: true,
+                name,
+                value: Some(value),
+                ..
+            } => match Interpreter::new(&const_fns, options).eval(value, &consts) {
+                Some(kind) => {
+                    value.kind = kind.clone();
+                    consts.insert(*name, kind);
+                }
+                None => diagnostics.push(Diagnostic::error(
+                    format!("The value of const '{name}' can't be computed at compile time"),
+                    value.span.clone(),
+                )),
+            },
+            // A variable shadows the const of the same name
+            ast::Stmt::LetDecl { name, .. } | ast::Stmt::VarDecl { name, .. } => {
+                consts.remove(*name);
+            }
+            _ => {}
+        }
+    }
+    diagnostics
+}
+
+struct ConstantFolder<'f, 'a> {
+    const_fns: &'f HashMap<&'a str, ConstFn<'a>>,
+    options: Options,
+}
+
+impl<'a> VisitorMut<'a> for ConstantFolder<'_, 'a> {
+    fn visit_expr_mut(&mut self, expr: &mut ast::Expr<'a>) {
+        // Fold the children first so nested constants collapse bottom-up
+        visit::walk_expr_mut(self, expr);
+        let value = fold_expr(&expr.kind, self.options).or_else(|| match &expr.kind {
+            ast::ExprKind::FnCall { .. } => {
+                Interpreter::new(self.const_fns, self.options).eval(expr, &HashMap::new())
+            }
+            _ => None,
+        });
+        if let Some(value) = value {
+            expr.kind = value;
+        }
+    }
+}
+
+/// Evaluate an expression whose operands are integer or boolean literals
+fn fold_expr<'a>(kind: &ast::ExprKind, options: Options) -> Option<ast::ExprKind<'a>> {
+    match kind {
+        ast::ExprKind::BinOp { lhs, op, rhs } => fold_binop(&lhs.kind, *op, &rhs.kind, options),
+        ast::ExprKind::UnaryOp { op, expr } => fold_unop(*op, &expr.kind, options),
+        _ => None,
+    }
+}
+
+/// Evaluate a binary operation on two integer or boolean literals
+fn fold_binop<'a>(
+    lhs: &ast::ExprKind,
+    op: ast::BinOp,
+    rhs: &ast::ExprKind,
+    options: Options,
+) -> Option<ast::ExprKind<'a>> {
+    if let (Some(lhs), Some(rhs)) = (int_value(lhs), int_value(rhs)) {
+        return fold_int_binop(lhs, op, rhs, options);
+    }
+    let (lhs, rhs) = (bool_value(lhs)?, bool_value(rhs)?);
+    let value = match op {
+        ast::BinOp::Equal => lhs == rhs,
+        ast::BinOp::NotEqual => lhs != rhs,
+        ast::BinOp::And => lhs && rhs,
+        ast::BinOp::Or => lhs || rhs,
+        ast::BinOp::Xor => lhs != rhs,
+        _ => return None,
+    };
+    Some(ast::ExprKind::BoolLit(value))
+}
+
+/// Evaluate a unary operation on an integer or boolean literal
+fn fold_unop<'a>(
+    op: ast::UnaryOp,
+    operand: &ast::ExprKind,
+    options: Options,
+) -> Option<ast::ExprKind<'a>> {
+    match op {
+        ast::UnaryOp::Neg if options.checked_arith => Some(ast::ExprKind::IntLit(
+            int_value(operand)?.checked_neg()?.into(),
+        )),
+        ast::UnaryOp::Neg => Some(ast::ExprKind::IntLit(
+            int_value(operand)?.wrapping_neg().into(),
+        )),
+        ast::UnaryOp::Not => Some(ast::ExprKind::BoolLit(!bool_value(operand)?)),
+        ast::UnaryOp::BitNot => Some(ast::ExprKind::IntLit((!int_value(operand)?).into())),
+    }
+}
+
+/// Evaluate a binary operation on two integer literals
+fn fold_int_binop<'a>(
+    lhs: i32,
+    op: ast::BinOp,
+    rhs: i32,
+    options: Options,
+) -> Option<ast::ExprKind<'a>> {
+    let value = match op {
+        ast::BinOp::Add => lhs.wrapping_add(rhs),
+        ast::BinOp::Sub => lhs.wrapping_sub(rhs),
+        ast::BinOp::Mul => lhs.wrapping_mul(rhs),
+        ast::BinOp::Div if options.floor_div => floor_div(lhs, rhs)?,
+        ast::BinOp::Div => lhs.checked_div(rhs)?,
+        ast::BinOp::Equal => return Some(ast::ExprKind::BoolLit(lhs == rhs)),
+        ast::BinOp::NotEqual => return Some(ast::ExprKind::BoolLit(lhs != rhs)),
+        ast::BinOp::LessThan => return Some(ast::ExprKind::BoolLit(lhs < rhs)),
+        ast::BinOp::LessThanOrEqual => return Some(ast::ExprKind::BoolLit(lhs <= rhs)),
+        ast::BinOp::GreaterThan => return Some(ast::ExprKind::BoolLit(lhs > rhs)),
+        ast::BinOp::GreaterThanOrEqual => return Some(ast::ExprKind::BoolLit(lhs >= rhs)),
+        // Logical operators on integers are type errors, left for the checker to report
+        ast::BinOp::And | ast::BinOp::Or | ast::BinOp::Xor => return None,
+    };
+    Some(ast::ExprKind::IntLit(value.into()))
+}
+
+/// Divide rounding toward negative infinity, i.e. one less than the truncated
+/// quotient when the signs differ and there is a remainder
+fn floor_div(lhs: i32, rhs: i32) -> Option<i32> {
+    let quotient = lhs.checked_div(rhs)?;
+    if lhs % rhs != 0 && (lhs < 0) != (rhs < 0) {
+        Some(quotient - 1)
+    } else {
+        Some(quotient)
+    }
+}
+
+fn int_value(kind: &ast::ExprKind) -> Option<i32> {
+    match *kind {
+        ast::ExprKind::IntLit(value) => i32::try_from(value).ok(),
+        _ => None,
+    }
+}
+
+fn bool_value(kind: &ast::ExprKind) -> Option<bool> {
+    match *kind {
+        ast::ExprKind::BoolLit(value) => Some(value),
+        _ => None,
+    }
+}
+
+/// A `const fn` whose calls with constant arguments are evaluated at compile time
+struct ConstFn<'a> {
+    /// The parameter names
+    params: Vec<&'a str>,
+    /// The body, as written before folding
+    body: Vec<ast::Stmt<'a>>,
+}
+
+/// Collect the top-level const fns. A name that is declared more than once, e.g. by a
+/// nested function shadowing it, is left out, since calls to it may mean either function
+fn collect_const_fns<'a>(program: &ast::Program<'a>) -> HashMap<&'a str, ConstFn<'a>> {
+    let mut names = FnNames::default();
+    names.visit_program(program);
+    program
+        .statements
+        .iter()
+        .filter_map(|stmt| match stmt {
+            ast::Stmt::FnDecl {
+                is_const: true,
+                name,
+                params,
+                body,
+                ..
+            } if names.counts[name] == 1 => Some((
+                *name,
+                ConstFn {
+                    params: params.iter().map(|param| param.name).collect(),
+                    body: body.clone(),
+                },
+            )),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Counts the declarations of each function name, at any depth
+#[derive(Default)]
+struct FnNames<'a> {
+    counts: HashMap<&'a str, usize>,
+}
+
+impl<'a> Visitor<'a> for FnNames<'a> {
+    fn visit_stmt(&mut self, stmt: &ast::Stmt<'a>) {
+        if let ast::Stmt::FnDecl { name, .. } | ast::Stmt::ExternDecl { name, .. } = stmt {
+            *self.counts.entry(name).or_default() += 1;
+        }
+        visit::walk_stmt(self, stmt);
+    }
+}
+
+/// Calls nested deeper than this are left to run at runtime
+const MAX_CALL_DEPTH: usize = 128;
+
+/// The number of expressions a call may evaluate before it is left to run at runtime
+const MAX_STEPS: usize = 1_000_000;
+
+/// The values of the variables in scope, as literals
+type Vars<'a> = HashMap<&'a str, ast::ExprKind<'a>>;
+
+/// How a list of statements finished
+enum Flow<'a> {
+    /// By running off its end
+    Continue,
+    /// By returning a value from the function
+    Return(ast::ExprKind<'a>),
+}
+
+/// Evaluates calls to const fns over the AST.
+///
+/// Every method returns `None` when the value can't be computed at compile time,
+/// e.g. because an operation is undefined or the call recurses too deeply, in
+/// which case the call is left to run at runtime.
+struct Interpreter<'f, 'a> {
+    const_fns: &'f HashMap<&'a str, ConstFn<'a>>,
+    options: Options,
+    depth: usize,
+    steps: usize,
+}
+
+impl<'f, 'a> Interpreter<'f, 'a> {
+    fn new(const_fns: &'f HashMap<&'a str, ConstFn<'a>>, options: Options) -> Self {
+        Self {
+            const_fns,
+            options,
+            depth: 0,
+            steps: 0,
+        }
+    }
+
+    fn eval(&mut self, expr: &ast::Expr<'a>, vars: &Vars<'a>) -> Option<ast::ExprKind<'a>> {
+        self.steps += 1;
+        if self.steps > MAX_STEPS {
+            return None;
+        }
+        match &expr.kind {
+            ast::ExprKind::IntLit(_) | ast::ExprKind::BoolLit(_) => Some(expr.kind.clone()),
+            ast::ExprKind::VarRef { name } => vars.get(name).cloned(),
+            ast::ExprKind::BinOp {
+                lhs,
+                op: op @ (ast::BinOp::And | ast::BinOp::Or),
+                rhs,
+            } => {
+                // The right-hand side only runs if it decides the result
+                let lhs = bool_value(&self.eval(lhs, vars)?)?;
+                if lhs == (*op == ast::BinOp::Or) {
+                    return Some(ast::ExprKind::BoolLit(lhs));
+                }
+                let rhs = self.eval(rhs, vars)?;
+                bool_value(&rhs).map(ast::ExprKind::BoolLit)
+            }
+            ast::ExprKind::BinOp { lhs, op, rhs } => {
+                let lhs = self.eval(lhs, vars)?;
+                let rhs = self.eval(rhs, vars)?;
+                fold_binop(&lhs, *op, &rhs, self.options)
+            }
+            ast::ExprKind::UnaryOp { op, expr } => {
+                fold_unop(*op, &self.eval(expr, vars)?, self.options)
+            }
+            ast::ExprKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                let branch = if bool_value(&self.eval(condition, vars)?)? {
+                    then_branch
+                } else {
+                    else_branch
+                };
+                let (ast::Stmt::Expr { expr: value }, stmts) = branch.split_last()? else {
+                    return None;
+                };
+                let mut vars = vars.clone();
+                // A `return` from inside an if expression isn't evaluated
+                match self.exec(stmts, &mut vars)? {
+                    Flow::Continue => self.eval(value, &vars),
+                    Flow::Return(_) => None,
+                }
+            }
+            ast::ExprKind::FnCall {
+                name,
+                args,
+                arg_names,
+            } if arg_names.is_empty() => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.eval(arg, vars))
+                    .collect::<Option<Vec<_>>>()?;
+                self.call(name, args)
+            }
+            _ => None,
+        }
+    }
+
+    fn call(&mut self, name: &str, args: Vec<ast::ExprKind<'a>>) -> Option<ast::ExprKind<'a>> {
+        let const_fns = self.const_fns;
+        let function = const_fns.get(name)?;
+        if function.params.len() != args.len() || self.depth == MAX_CALL_DEPTH {
+            return None;
+        }
+        let mut vars = function.params.iter().copied().zip(args).collect();
+        self.depth += 1;
+        let flow = self.exec(&function.body, &mut vars);
+        self.depth -= 1;
+        match flow? {
+            Flow::Return(value) => Some(value),
+            // A function that runs off its end returns void
+            Flow::Continue => None,
+        }
+    }
+
+    fn exec(&mut self, stmts: &[ast::Stmt<'a>], vars: &mut Vars<'a>) -> Option<Flow<'a>> {
+        for stmt in stmts {
+            match stmt {
+                ast::Stmt::LetDecl {
+                    name,
+                    value: Some(value),
+                    ..
+                } => {
+                    let value = self.eval(value, vars)?;
+                    vars.insert(name, value);
+                }
+                ast::Stmt::ExprStmt { expr } => {
+                    self.eval(expr, vars)?;
+                }
+                ast::Stmt::Return {
+                    expr: Some(expr), ..
+                }
+                | ast::Stmt::Expr { expr } => return Some(Flow::Return(self.eval(expr, vars)?)),
+                ast::Stmt::If {
+                    condition,
+                    then_branch,
+                    else_branch,
+                } => {
+                    let branch = if bool_value(&self.eval(condition, vars)?)? {
+                        then_branch
+                    } else {
+                        match else_branch {
+                            Some(else_branch) => else_branch,
+                            None => continue,
+                        }
+                    };
+                    // Variables declared in the branch go out of scope after it
+                    if let Flow::Return(value) = self.exec(branch, &mut vars.clone())? {
+                        return Some(Flow::Return(value));
+                    }
+                }
+                ast::Stmt::Block { body } => {
+                    if let Flow::Return(value) = self.exec(body, &mut vars.clone())? {
+                        return Some(Flow::Return(value));
+                    }
+                }
+                _ => return None,
+            }
+        }
+        Some(Flow::Continue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use indoc::indoc;
+
+    fn fold_source(input: &str) -> ast::Program {
+        let mut program = parse(input).into_result().unwrap();
+        fold_program(&mut program);
+        program
+    }
+
+    fn value_of(program: &ast::Program) -> &ast::ExprKind {
+        match program.statements.last() {
+            Some(ast::Stmt::Expr { expr }) => &expr.kind,
+            stmt => panic!("expected a trailing expression, found {stmt:?}"),
+        }
+    }
+
+    #[test]
+    fn test_fold_arithmetic() {
+        let program = fold_source("-(1 + 2 * 3) / 2");
+        assert_eq!(value_of(&program), &ast::ExprKind::IntLit(-3));
+    }
+
+    #[test]
+    fn test_fold_nested_in_statements() {
+        let program = fold_source("fn f(x: i32) -> i32 { return x + 2 * 5; }\nf(4 - 1)");
+        let ast::Stmt::FnDecl { body, .. } = &program.statements[0] else {
+            panic!("expected a function declaration");
+        };
+        let Some(ast::Stmt::Return {
+            expr: Some(expr), ..
+        }) = body.first()
+        else {
+            panic!("expected a return statement");
+        };
+        let ast::ExprKind::BinOp { rhs, .. } = &expr.kind else {
+            panic!("expected x + 10");
+        };
+        assert_eq!(rhs.kind, ast::ExprKind::IntLit(10));
+
+        let ast::ExprKind::FnCall { args, .. } = value_of(&program) else {
+            panic!("expected a call");
+        };
+        assert_eq!(args[0].kind, ast::ExprKind::IntLit(3));
+    }
+
+    #[test]
+    fn test_fold_wraps_like_i32() {
+        let program = fold_source("2147483647 + 1");
+        assert_eq!(value_of(&program), &ast::ExprKind::IntLit(i32::MIN.into()));
+    }
+
+    #[test]
+    fn test_fold_floor_division() {
+        for (input, truncated, floored) in [
+            ("-7 / 2", -3, -4),
+            ("7 / -2", -3, -4),
+            ("-7 / -2", 3, 3),
+            ("-8 / 2", -4, -4),
+            ("7 / 2", 3, 3),
+        ] {
+            let program = fold_source(input);
+            assert_eq!(
+                value_of(&program),
+                &ast::ExprKind::IntLit(truncated),
+                "{input}"
+            );
+
+            let mut program = parse(input).into_result().unwrap();
+            fold_program_with(
+                &mut program,
+                Options {
+                    floor_div: true,
+                    ..Options::default()
+                },
+            );
+            assert_eq!(
+                value_of(&program),
+                &ast::ExprKind::IntLit(floored),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_fold_checked_negation() {
+        let input = "-(-2147483647 - 1)";
+        let program = fold_source(input);
+        assert_eq!(value_of(&program), &ast::ExprKind::IntLit(-2147483648));
+
+        // The overflow is left to trap at runtime
+        let mut program = parse(input).into_result().unwrap();
+        fold_program_with(
+            &mut program,
+            Options {
+                checked_arith: true,
+                ..Options::default()
+            },
+        );
+        assert!(
+            matches!(
+                value_of(&program),
+                ast::ExprKind::UnaryOp {
+                    op: ast::UnaryOp::Neg,
+                    ..
+                }
+            ),
+            "{program:?}"
+        );
+    }
+
+    #[test]
+    fn test_fold_comparisons() {
+        for (input, expected) in [
+            ("3 == 3", true),
+            ("3 == 5", false),
+            ("3 != 5", true),
+            ("3 != 3", false),
+            ("3 < 5", true),
+            ("5 < 3", false),
+            ("3 <= 3", true),
+            ("5 <= 3", false),
+            ("5 > 3", true),
+            ("3 > 5", false),
+            ("3 >= 3", true),
+            ("3 >= 5", false),
+            ("-1 < 0", true),
+            ("1 + 1 == 2", true),
+        ] {
+            let program = fold_source(input);
+            assert_eq!(
+                value_of(&program),
+                &ast::ExprKind::BoolLit(expected),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_fold_logical_operations() {
+        for (input, expected) in [
+            ("true && true", true),
+            ("true && false", false),
+            ("false || true", true),
+            ("false || false", false),
+            ("!false", true),
+            ("!(1 < 2)", false),
+            ("true == false", false),
+            ("true != false", true),
+            ("1 < 2 && 3 < 4", true),
+            ("true ^^ false", true),
+            ("true ^^ true", false),
+            ("false ^^ false", false),
+        ] {
+            let program = fold_source(input);
+            assert_eq!(
+                value_of(&program),
+                &ast::ExprKind::BoolLit(expected),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_fold_if_condition() {
+        let program = fold_source("if 1 < 2 { 1 } else { 2 }");
+        let Some(ast::Stmt::If { condition, .. }) = program.statements.last() else {
+            panic!("expected an if statement");
+        };
+        assert_eq!(condition.kind, ast::ExprKind::BoolLit(true));
+    }
+
+    #[test]
+    fn test_fold_const_fn_call() {
+        let program = fold_source("const fn square(x: i32) -> i32 { x * x }\nsquare(4)");
+        assert_eq!(value_of(&program), &ast::ExprKind::IntLit(16));
+
+        let program = fold_source(indoc! {"
+            const fn fib(n: i32) -> i32 {
+                if n < 2 {
+                    return n;
+                }
+                let a = fib(n - 1);
+                a + fib(n - 2)
+            }
+            const fn is_even(n: i32) -> bool { if n < 0 { is_even(-n) } else { n / 2 * 2 == n } }
+            fib(10) == 55 && is_even(-4)
+        "});
+        assert_eq!(value_of(&program), &ast::ExprKind::BoolLit(true));
+    }
+
+    #[test]
+    fn test_fold_const_fn_keeps_runtime_calls() {
+        for input in [
+            // Not a const fn
+            "fn square(x: i32) -> i32 { x * x }\nsquare(4)",
+            // The argument isn't constant
+            "const fn square(x: i32) -> i32 { x * x }\nfn f(y: i32) -> i32 { square(y) }\nf(4)",
+            // Undefined at runtime
+            "const fn inverse(x: i32) -> i32 { 1 / x }\ninverse(0)",
+            // Never returns
+            "const fn forever(x: i32) -> i32 { forever(x) }\nforever(1)",
+        ] {
+            let program = fold_source(input);
+            assert!(
+                matches!(value_of(&program), ast::ExprKind::FnCall { .. }),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_fold_const_item() {
+        let mut program = parse(indoc! {"
+            const fn square(x: i32) -> i32 { x * x }
+            const N: i32 = square(4);
+            const M: i32 = N + 1;
+            let x = 3;
+            const BAD: i32 = square(x);
+            M
+        "})
+        .into_result()
+        .unwrap();
+        let diagnostics = fold_program(&mut program);
+        let ast::Stmt::LetDecl {
+            value: Some(value), ..
+        } = &program.statements[2]
+        else {
+            panic!("expected a const item");
+        };
+        assert_eq!(value.kind, ast::ExprKind::IntLit(17));
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(|diagnostic| diagnostic.message.as_str())
+                .collect::<Vec<_>>(),
+            ["The value of const 'BAD' can't be computed at compile time"]
+        );
+    }
+
+    #[test]
+    fn test_fold_keeps_division_by_zero() {
+        let program = fold_source("1 / (2 - 2)");
+        let ast::ExprKind::BinOp { op, rhs, .. } = value_of(&program) else {
+            panic!("expected the division to be kept");
+        };
+        assert_eq!(*op, ast::BinOp::Div);
+        assert_eq!(rhs.kind, ast::ExprKind::IntLit(0));
+    }
+}
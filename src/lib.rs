@@ -3,8 +3,53 @@
 //!
 //! This library provides functionality for compiling a simple
 //! integer expression language to executable code.
+//!
+//! # Embedding
+//!
+//! Tools such as linters or transpilers can drive the same pipeline as the `aic` binary:
+//!
+//! 1. [`parse`] the source into an [`ast::Program`]
+//! 2. type-check it with [`sema::check`], which returns the [`diagnostic::Diagnostic`]s found,
+//!    or with [`sema::infer_with`], which also fills in omitted return types for codegen
+//! 3. walk it with a [`visit::Visitor`], or rewrite it in place with a [`visit::VisitorMut`]
+//!    such as [`fold::fold_program`]
+//! 4. lower it to LLVM IR with [`codegen::CodeGen`]
+//!
+//! ```
+//! use aic::{ast, parse, visit::{self, Visitor}};
+//!
+//! let program = parse("let x = 1 + 2;\nx * 2").into_result().expect("valid program");
+//! println!("{program:#?}");
+//!
+//! // Count the integer literals in the program
+//! struct Literals(usize);
+//!
+//! impl<'a> Visitor<'a> for Literals {
+//!     fn visit_expr(&mut self, expr: &ast::Expr<'a>) {
+//!         if let ast::ExprKind::IntLit(_) = expr.kind {
+//!             self.0 += 1;
+//!         }
+//!         visit::walk_expr(self, expr);
+//!     }
+//! }
+//!
+//! let mut literals = Literals(0);
+//! literals.visit_program(&program);
+//! assert_eq!(literals.0, 3);
+//! ```
 
 pub mod ast;
+pub mod backend_c;
 pub mod codegen;
+pub mod diagnostic;
+pub mod dot;
+pub mod fold;
+pub mod metadata;
 pub mod parser;
+pub mod preprocess;
+pub mod prune;
+pub mod sema;
 pub mod token;
+pub mod visit;
+
+pub use parser::parse;
@@ -1,21 +1,38 @@
 mod ast;
+mod backend_c;
 mod codegen;
+mod diagnostic;
+mod dot;
+mod fold;
+mod metadata;
 mod parser;
+mod preprocess;
+mod prune;
+mod sema;
 mod token;
+mod visit;
 
-use anyhow::Result;
+use anyhow::{Context as _, Result};
 use ariadne::{Report, ReportKind};
-use clap::Parser;
-use inkwell::context::Context;
-use std::{fs, path::PathBuf};
+use clap::{Parser, ValueEnum};
+use inkwell::{
+    context::Context,
+    targets::{CodeModel, RelocMode},
+};
+use std::{
+    fs,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
 
 /// A simple integer-only compiler
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
     /// Input file to compile
-    #[arg(short, long)]
-    input: PathBuf,
+    #[arg(short, long, required_unless_present = "list_targets")]
+    input: Option<PathBuf>,
 
     /// Output file
     #[arg(short, long)]
@@ -24,19 +41,298 @@ struct Args {
     /// Emit LLVM IR instead of an object file
     #[arg(long)]
     emit_llvm: bool,
+
+    /// Kind of output to emit
+    #[arg(long, value_enum)]
+    emit: Option<Emit>,
+
+    /// Strip attributes, metadata and alignments from the emitted LLVM IR to make it easier to read
+    #[arg(long)]
+    clean: bool,
+
+    /// Emit the AST in a serialization format instead of compiling
+    #[arg(long, value_enum, value_name = "FORMAT", conflicts_with_all = ["emit", "emit_llvm"])]
+    emit_ast: Option<AstFormat>,
+
+    /// Print the time spent in each compilation phase to stderr
+    #[arg(long)]
+    timings: bool,
+
+    /// Only parse and type-check the input, without generating code
+    #[arg(long)]
+    check: bool,
+
+    /// Print the type inferred for each expression to stderr, one per line
+    #[arg(long)]
+    dump_types: bool,
+
+    /// Treat warnings as errors
+    #[arg(long)]
+    werror: bool,
+
+    /// Warn when a `let` or `var` shadows a variable of an enclosing scope
+    #[arg(long)]
+    warn_shadow: bool,
+
+    /// Define a flag for `#if FLAG` sections (can be repeated)
+    #[arg(short = 'D', long = "define", value_name = "FLAG")]
+    defines: Vec<String>,
+
+    /// Accept integer conditions, which are true when non-zero (as in C)
+    #[arg(long)]
+    c_truthiness: bool,
+
+    /// Rewrite chained comparisons such as `a < b < c` to `a < b && b < c` instead of rejecting them
+    #[arg(long)]
+    sugar: bool,
+
+    /// Round integer division toward negative infinity instead of toward zero
+    #[arg(long)]
+    floor_div: bool,
+
+    /// Return main's value as `value & 0xFF`, so negative values give the same exit code everywhere
+    #[arg(long)]
+    wrap_exit_code: bool,
+
+    /// Trap when negating an integer overflows, as for `-i32::MIN`, instead of wrapping around
+    #[arg(long)]
+    checked_arith: bool,
+
+    /// Let float operations assume no NaNs or infinities and be reordered, which may change results
+    #[arg(long = "ffast-math", overrides_with = "no_fast_math")]
+    fast_math: bool,
+
+    /// Keep float operations IEEE-exact, undoing an earlier --ffast-math (the default)
+    #[arg(long = "fno-fast-math", overrides_with = "fast_math")]
+    no_fast_math: bool,
+
+    /// Instrument the generated code with a sanitizer, whose runtime must be linked in
+    #[arg(long, value_enum, value_name = "SANITIZER")]
+    sanitize: Option<Sanitizer>,
+
+    /// Instrument the object file to write an execution profile when run (link with -fprofile-generate)
+    #[arg(long, conflicts_with = "profile_use")]
+    profile_generate: bool,
+
+    /// Optimize the object file with a profile merged by llvm-profdata from a --profile-generate build
+    #[arg(long, value_name = "FILE")]
+    profile_use: Option<PathBuf>,
+
+    /// Name of the entry function, e.g. `_start` for freestanding code (the linker must be told it)
+    #[arg(long, value_name = "NAME", default_value = "main")]
+    entry: String,
+
+    /// Leave out the synthetic main for freestanding code, which must define its own entry such as `_start`
+    #[arg(long, conflicts_with = "entry")]
+    no_std_main: bool,
+
+    /// Target triple to compile for (e.g. wasm32-unknown-unknown), defaults to the host
+    #[arg(long)]
+    target: Option<String>,
+
+    /// Relocation model of the generated code
+    #[arg(long, value_enum, default_value_t = Reloc::Default)]
+    reloc: Reloc,
+
+    /// Generate position-independent code, e.g. for shared libraries (same as --reloc pic)
+    #[arg(long, conflicts_with = "reloc")]
+    pic: bool,
+
+    /// Code model of the generated code
+    #[arg(long, value_enum, default_value_t = Model::Default)]
+    code_model: Model,
+
+    /// Print the functions in the generated module and their signatures to stderr
+    #[arg(long)]
+    print_symbols: bool,
+
+    /// Print the LLVM IR of a single function to stderr
+    #[arg(long, value_name = "NAME")]
+    dump_fn: Option<String>,
+
+    /// Write the control-flow graph of a single function as Graphviz DOT to --output
+    #[arg(
+        long,
+        value_name = "NAME",
+        requires = "output",
+        conflicts_with_all = ["emit", "emit_llvm", "emit_ast"]
+    )]
+    dump_cfg: Option<String>,
+
+    /// Write a JSON summary of the compilation (functions, statements, target) to a file
+    #[arg(long, value_name = "PATH")]
+    emit_metadata: Option<PathBuf>,
+
+    /// Maximum number of errors to report (0 for no limit)
+    #[arg(long, default_value_t = 20)]
+    max_errors: usize,
+
+    /// Directory to cache object files in, so recompiling unchanged input is skipped
+    #[arg(long)]
+    cache_dir: Option<PathBuf>,
+
+    /// Print the targets supported by the linked LLVM and exit
+    #[arg(long)]
+    list_targets: bool,
+
+    /// Comma-separated list of LLVM passes to run before emission (e.g. mem2reg,instcombine,gvn)
+    #[arg(long, value_delimiter = ',')]
+    passes: Vec<String>,
+}
+
+/// Kind of output to emit
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum Emit {
+    /// Native object file
+    Obj,
+    /// LLVM IR
+    LlvmIr,
+    /// Graphviz DOT digraph of the AST
+    AstDot,
+    /// C99 source, as an alternative to LLVM
+    C,
+}
+
+/// Serialization format of `--emit-ast`
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum AstFormat {
+    /// JSON
+    Json,
+    /// YAML
+    Yaml,
+    /// Rusty Object Notation
+    Ron,
+}
+
+/// Sanitizer of `--sanitize`
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Hash)]
+enum Sanitizer {
+    /// AddressSanitizer, linked with `-fsanitize=address`
+    Address,
+}
+
+/// Relocation model of the generated code
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Hash)]
+enum Reloc {
+    /// The target's default
+    Default,
+    /// Non-relocatable code
+    Static,
+    /// Position-independent code
+    Pic,
+    /// Relocatable code that isn't position-independent
+    DynamicNoPic,
+}
+
+impl From<Reloc> for RelocMode {
+    fn from(reloc: Reloc) -> Self {
+        match reloc {
+            Reloc::Default => RelocMode::Default,
+            Reloc::Static => RelocMode::Static,
+            Reloc::Pic => RelocMode::PIC,
+            Reloc::DynamicNoPic => RelocMode::DynamicNoPic,
+        }
+    }
+}
+
+/// Code model of the generated code
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Hash)]
+enum Model {
+    /// The target's default
+    Default,
+    /// Code and data within the lower 2GB of the address space
+    Small,
+    /// Code and data within the upper 2GB of the address space
+    Kernel,
+    /// Code within the lower 2GB, data anywhere
+    Medium,
+    /// Code and data anywhere
+    Large,
+}
+
+impl From<Model> for CodeModel {
+    fn from(model: Model) -> Self {
+        match model {
+            Model::Default => CodeModel::Default,
+            Model::Small => CodeModel::Small,
+            Model::Kernel => CodeModel::Kernel,
+            Model::Medium => CodeModel::Medium,
+            Model::Large => CodeModel::Large,
+        }
+    }
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
 
+    if args.list_targets {
+        print!("{}", codegen::list_targets());
+        return Ok(());
+    }
+
     // Read the input file
-    let input = fs::read_to_string(&args.input)?;
+    let input_path = args.input.clone().expect("clap requires --input");
+    let source = fs::read_to_string(&input_path)
+        .with_context(|| format!("Failed to read input file '{}'", input_path.display()))?;
+
+    let mut timings = Vec::new();
+
+    let emit = match args.emit {
+        Some(emit) => emit,
+        None if args.emit_llvm => Emit::LlvmIr,
+        None => Emit::Obj,
+    };
+    if args.clean && emit != Emit::LlvmIr {
+        return Err(anyhow::anyhow!(
+            "--clean requires --emit-llvm or --emit llvm-ir"
+        ));
+    }
+
+    // Object files are cached by everything that goes into them. Anything that
+    // should print more than the output path always runs the whole pipeline
+    let cache_file = match &args.cache_dir {
+        Some(dir)
+            if emit == Emit::Obj
+                && !args.check
+                && !args.dump_types
+                && !args.print_symbols
+                && args.dump_fn.is_none()
+                && args.dump_cfg.is_none()
+                && args.emit_metadata.is_none()
+                && args.emit_ast.is_none() =>
+        {
+            Some(dir.join(format!("{:016x}.o", cache_key(&args, &input_path, &source))))
+        }
+        _ => None,
+    };
+    if let Some(cache_file) = cache_file.as_ref().filter(|file| file.exists()) {
+        let output = args
+            .output
+            .unwrap_or_else(|| input_path.with_extension("o"));
+        fs::copy(cache_file, &output)?;
+        println!("Compiled to {} (cached)", output.display());
+        return Ok(());
+    }
+
+    // Strip the `#if` sections whose flag isn't defined
+    let start = Instant::now();
+    let input = match preprocess::preprocess(&source, &args.defines) {
+        Ok(input) => input,
+        Err(diagnostic) => {
+            diagnostic.eprint(&source);
+            return Err(anyhow::anyhow!("Failed to preprocess input"));
+        }
+    };
+    timings.push(("preprocess", start.elapsed()));
 
     // Parse the input
-    let program = match parser::parse(&input).into_result() {
+    let start = Instant::now();
+    let options = parser::Options { sugar: args.sugar };
+    let mut program = match parser::parse_with(&input, options).into_result() {
         Ok(program) => program,
         Err(errors) => {
-            for err in errors {
+            let shown = diagnostic::shown_count(errors.len(), args.max_errors);
+            for err in &errors[..shown] {
                 Report::build(ReportKind::Error, ((), err.span().into_range()))
                     .with_config(ariadne::Config::new().with_index_type(ariadne::IndexType::Byte))
                     .with_code(3)
@@ -50,36 +346,304 @@ fn main() -> Result<()> {
                     .eprint(ariadne::Source::from(&input))
                     .unwrap();
             }
+            diagnostic::eprint_omitted(errors.len() - shown);
             return Err(anyhow::anyhow!("Failed to parse input"));
         }
     };
+    timings.push(("parse", start.elapsed()));
+
+    // Neither the serialized AST nor its visualization need LLVM at all
+    if let Some(format) = args.emit_ast {
+        let text = serialize_ast(&program, format)?;
+        match args.output {
+            Some(output) => write_output(&output, text)?,
+            None => print!("{}", text),
+        }
+        if args.timings {
+            print_timings(&timings);
+        }
+        return Ok(());
+    }
+    if emit == Emit::AstDot {
+        let dot = dot::program_to_dot(&program);
+        match args.output {
+            Some(output) => write_output(&output, dot)?,
+            None => print!("{}", dot),
+        }
+        if args.timings {
+            print_timings(&timings);
+        }
+        return Ok(());
+    }
+
+    // Type-check the program
+    let start = Instant::now();
+    let options = sema::Options {
+        c_truthiness: args.c_truthiness,
+        warn_shadow: args.warn_shadow,
+    };
+    let (mut diagnostics, types) = sema::infer_with_types(&mut program, options);
+    timings.push(("check", start.elapsed()));
+    if diagnostic::report(&mut diagnostics, &input, args.werror, args.max_errors) {
+        return Err(anyhow::anyhow!("Failed to type-check input"));
+    }
+    if args.dump_types {
+        print_types(&types, &input);
+    }
+
+    if args.check {
+        if args.timings {
+            print_timings(&timings);
+        }
+        return Ok(());
+    }
+
+    // Fold constant expressions and drop the branches they rule out
+    let start = Instant::now();
+    let options = fold::Options {
+        floor_div: args.floor_div,
+        checked_arith: args.checked_arith,
+    };
+    let mut diagnostics = fold::fold_program_with(&mut program, options);
+    if diagnostic::report(&mut diagnostics, &input, args.werror, args.max_errors) {
+        return Err(anyhow::anyhow!("Failed to evaluate constants"));
+    }
+    prune::prune_program(&mut program);
+    timings.push(("fold", start.elapsed()));
+
+    // The C backend works on the AST directly, without LLVM
+    if emit == Emit::C {
+        if args.floor_div {
+            return Err(anyhow::anyhow!(
+                "--floor-div is not supported by the C backend"
+            ));
+        }
+        if args.wrap_exit_code {
+            return Err(anyhow::anyhow!(
+                "--wrap-exit-code is not supported by the C backend"
+            ));
+        }
+        if args.checked_arith {
+            return Err(anyhow::anyhow!(
+                "--checked-arith is not supported by the C backend"
+            ));
+        }
+        if args.fast_math {
+            return Err(anyhow::anyhow!(
+                "--ffast-math is not supported by the C backend"
+            ));
+        }
+        if args.sanitize.is_some() {
+            return Err(anyhow::anyhow!(
+                "--sanitize is not supported by the C backend"
+            ));
+        }
+        if args.profile_generate || args.profile_use.is_some() {
+            return Err(anyhow::anyhow!(
+                "--profile-generate and --profile-use are not supported by the C backend"
+            ));
+        }
+        if args.entry != "main" {
+            return Err(anyhow::anyhow!("--entry is not supported by the C backend"));
+        }
+        if args.no_std_main {
+            return Err(anyhow::anyhow!(
+                "--no-std-main is not supported by the C backend"
+            ));
+        }
+        let c = backend_c::program_to_c(&program)?;
+        match args.output {
+            Some(output) => write_output(&output, c)?,
+            None => print!("{}", c),
+        }
+        if args.timings {
+            print_timings(&timings);
+        }
+        return Ok(());
+    }
+
     println!("Parsed AST:\n {:#?}", program);
 
     // Generate code
     let context = Context::create();
-    let module_name = args
-        .input
+    let module_name = input_path
         .file_name()
-        .and_then(|name| name.to_str())
-        .unwrap_or("module");
+        .map_or("module".into(), |name| name.to_string_lossy());
+
+    let start = Instant::now();
+    let mut codegen = codegen::CodeGen::new(&context, &module_name);
+    if let Some(target) = &args.target {
+        codegen.set_target(target)?;
+    }
+    codegen.set_reloc_mode(if args.pic {
+        RelocMode::PIC
+    } else {
+        args.reloc.into()
+    });
+    codegen.set_code_model(args.code_model.into());
+    codegen.set_c_truthiness(args.c_truthiness);
+    codegen.set_floor_div(args.floor_div);
+    codegen.set_wrap_exit_code(args.wrap_exit_code);
+    codegen.set_checked_arith(args.checked_arith);
+    codegen.set_fast_math(args.fast_math);
+    codegen.set_sanitize_address(args.sanitize == Some(Sanitizer::Address));
+    codegen.set_entry(&args.entry);
+    codegen.set_no_std_main(args.no_std_main);
+    codegen.set_source(&input_path.display().to_string(), &input);
+    codegen.set_profile_generate(args.profile_generate);
+    if let Some(profile) = &args.profile_use {
+        // LLVM exits on its own if the profile is missing, so check it first
+        fs::metadata(profile)
+            .with_context(|| format!("Failed to read profile '{}'", profile.display()))?;
+    }
+    codegen.set_profile_use(args.profile_use.as_deref());
+    if let Err(err) = codegen.compile(&program) {
+        if let Some(diagnostic) = err.downcast_ref::<diagnostic::Diagnostic>() {
+            diagnostic.eprint(&input);
+            return Err(anyhow::anyhow!("Failed to compile input"));
+        }
+        return Err(err);
+    }
+    timings.push(("codegen", start.elapsed()));
 
-    let mut codegen = codegen::CodeGen::new(&context, module_name);
-    codegen.compile(&program)?;
+    if args.print_symbols {
+        eprint!("{}", codegen.print_symbols());
+    }
+
+    // Run the requested optimization passes
+    if !args.passes.is_empty() {
+        let start = Instant::now();
+        codegen.run_passes(&args.passes)?;
+        timings.push(("passes", start.elapsed()));
+    }
+
+    if let Some(name) = &args.dump_fn {
+        eprintln!("{}", codegen.print_function(name)?);
+    }
+
+    if let Some(path) = &args.emit_metadata {
+        let metadata = metadata::Metadata::new(&program, &codegen);
+        write_output(path, metadata.to_json())?;
+    }
+
+    // The CFG takes the place of the object file in --output
+    if let (Some(name), Some(output)) = (&args.dump_cfg, &args.output) {
+        write_output(output, codegen.cfg_to_dot(name)?)?;
+        if args.timings {
+            print_timings(&timings);
+        }
+        return Ok(());
+    }
 
     // Output
-    if args.emit_llvm {
+    if emit == Emit::LlvmIr {
         // Print LLVM IR
         println!("Generated LLVM IR:");
-        println!("{}", codegen.print_ir());
+        if args.clean {
+            println!("{}", codegen::clean_ir(&codegen.print_ir()));
+        } else {
+            println!("{}", codegen.print_ir());
+        }
     } else {
-        // Compile to an object file
+        // Compile to an object file, by default next to the input (`path/to/foo.aic` -> `path/to/foo.o`)
         let output = args
             .output
-            .unwrap_or_else(|| PathBuf::from(format!("{}.o", module_name)));
+            .unwrap_or_else(|| input_path.with_extension("o"));
 
-        codegen.compile_to_file(output.to_str().unwrap())?;
+        let start = Instant::now();
+        codegen.compile_to_file(&output)?;
+        timings.push(("emit", start.elapsed()));
         println!("Compiled to {}", output.display());
+
+        if let Some(cache_file) = &cache_file {
+            if let Some(dir) = cache_file.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::copy(&output, cache_file)?;
+        }
+    }
+
+    if args.timings {
+        print_timings(&timings);
     }
 
     Ok(())
 }
+
+/// Serialize the AST in the given format, ending with a newline
+fn serialize_ast(program: &ast::Program, format: AstFormat) -> Result<String> {
+    let mut text = match format {
+        AstFormat::Json => serde_json::to_string_pretty(program)?,
+        AstFormat::Yaml => serde_yaml::to_string(program)?,
+        AstFormat::Ron => ron::ser::to_string_pretty(program, ron::ser::PrettyConfig::default())?,
+    };
+    if !text.ends_with('\n') {
+        text.push('\n');
+    }
+    Ok(text)
+}
+
+/// Write an output file, naming it in the error if that fails
+fn write_output(output: &Path, contents: String) -> Result<()> {
+    fs::write(output, contents)
+        .with_context(|| format!("Failed to write output file '{}'", output.display()))
+}
+
+/// Hash the compiler version, the source and every option that affects the object file
+fn cache_key(args: &Args, input_path: &Path, source: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    env!("CARGO_PKG_VERSION").hash(&mut hasher);
+    source.hash(&mut hasher);
+    // The file name becomes the module's source file name
+    input_path.file_name().hash(&mut hasher);
+    args.defines.hash(&mut hasher);
+    args.c_truthiness.hash(&mut hasher);
+    args.sugar.hash(&mut hasher);
+    args.floor_div.hash(&mut hasher);
+    args.wrap_exit_code.hash(&mut hasher);
+    args.checked_arith.hash(&mut hasher);
+    args.fast_math.hash(&mut hasher);
+    args.sanitize.hash(&mut hasher);
+    args.entry.hash(&mut hasher);
+    args.no_std_main.hash(&mut hasher);
+    args.profile_generate.hash(&mut hasher);
+    // A new profile changes the object file even when it has the same path
+    let profile = args
+        .profile_use
+        .as_ref()
+        .and_then(|path| fs::read(path).ok());
+    profile.hash(&mut hasher);
+    args.werror.hash(&mut hasher);
+    args.warn_shadow.hash(&mut hasher);
+    args.target.hash(&mut hasher);
+    args.reloc.hash(&mut hasher);
+    args.pic.hash(&mut hasher);
+    args.code_model.hash(&mut hasher);
+    args.passes.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Print the type of each expression to stderr as `line:column: source: type`
+fn print_types(types: &[(ast::Span, ast::Type)], source: &str) {
+    for (span, ty) in types {
+        let before = &source[..span.start];
+        let line = before.matches('\n').count() + 1;
+        let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+        // Expressions spanning several lines are shown on one
+        let text = source[span.clone()]
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        eprintln!("{line}:{column}: {text}: {ty}");
+    }
+}
+
+/// Print the time spent in each phase as an aligned table to stderr
+fn print_timings(timings: &[(&str, Duration)]) {
+    let total: Duration = timings.iter().map(|(_, elapsed)| *elapsed).sum();
+    eprintln!("{:<10}{:>12}", "phase", "time");
+    for (phase, elapsed) in timings {
+        eprintln!("{:<10}{:>12}", phase, format!("{:.3?}", elapsed));
+    }
+    eprintln!("{:<10}{:>12}", "total", format!("{:.3?}", total));
+}
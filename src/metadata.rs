@@ -0,0 +1,74 @@
+//! A machine-readable summary of a compilation, for build systems
+
+use serde::Serialize;
+
+use crate::{
+    ast,
+    codegen::{self, CodeGen, Symbol},
+    visit::{self, Visitor},
+};
+
+/// The summary written by `--emit-metadata`
+#[derive(Debug, Serialize)]
+pub struct Metadata {
+    /// The functions in the generated module
+    pub functions: Vec<Symbol>,
+    /// The number of statements in the program, including those in function bodies
+    pub statements: usize,
+    /// The target triple the module is compiled for
+    pub target: String,
+    /// The optimization level of the target machine
+    pub opt_level: String,
+}
+
+impl Metadata {
+    /// Summarize a program and the module generated for it
+    pub fn new(program: &ast::Program, codegen: &CodeGen) -> Self {
+        let mut counter = StatementCounter(0);
+        counter.visit_program(program);
+        Self {
+            functions: codegen.symbols(),
+            statements: counter.0,
+            target: codegen.target_triple(),
+            opt_level: format!("{:?}", codegen::OPT_LEVEL).to_lowercase(),
+        }
+    }
+
+    /// Serialize the summary as pretty-printed JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("metadata is always serializable")
+    }
+}
+
+struct StatementCounter(usize);
+
+impl<'a> Visitor<'a> for StatementCounter {
+    fn visit_stmt(&mut self, stmt: &ast::Stmt<'a>) {
+        self.0 += 1;
+        visit::walk_stmt(self, stmt);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use inkwell::context::Context;
+
+    #[test]
+    fn test_metadata_counts_nested_statements() {
+        let program = parse("fn one() -> i32 { let x = 1; x }\none()")
+            .into_result()
+            .unwrap();
+        let context = Context::create();
+        let mut codegen = CodeGen::new(&context, "test");
+        codegen.compile(&program).unwrap();
+
+        let metadata = Metadata::new(&program, &codegen);
+        // The declaration, its two body statements and the trailing call
+        assert_eq!(metadata.statements, 4);
+        assert_eq!(metadata.opt_level, "default");
+        let names: Vec<_> = metadata.functions.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["main", "one"]);
+    }
+}
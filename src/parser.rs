@@ -3,7 +3,17 @@ use logos::Logos;
 
 use crate::{ast, token::Token};
 
-pub fn parser<'a, I>() -> impl Parser<'a, I, ast::Program<'a>, extra::Err<Rich<'a, Token<'a>>>>
+/// Options that change what the parser accepts
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Options {
+    /// Rewrite chained comparisons such as `a < b < c` to `a < b && b < c`
+    /// instead of reporting them. The middle operand is evaluated twice
+    pub sugar: bool,
+}
+
+pub fn parser<'a, I>(
+    options: Options,
+) -> impl Parser<'a, I, ast::Program<'a>, extra::Err<Rich<'a, Token<'a>>>>
 where
     I: ValueInput<'a, Token = Token<'a>, Span = SimpleSpan>,
 {
@@ -11,83 +21,369 @@ where
         Token::Identifier(value) => value
     };
 
+    let label = select! {
+        Token::Label(value) => value
+    };
+
     let r#type = select! {
+        Token::Identifier(value) if value == "bool" => ast::Type::Bool,
         Token::Identifier(value) if value == "i32" => ast::Type::I32,
         Token::Identifier(value) if value == "i64" => ast::Type::I64,
         Token::Identifier(value) if value == "f32" => ast::Type::F32,
         Token::Identifier(value) if value == "f64" => ast::Type::F64,
         Token::Identifier(value) if value == "void" => ast::Type::Void,
         Token::Identifier(value) if value == "string" => ast::Type::String,
+        Token::Identifier(value) => ast::Type::Struct(value),
     };
 
     // The operator precedence and associativity are designed to match C++ according to:
     // https://www.ibm.com/docs/en/i/7.3.0?topic=operators-operator-precedence-associativity
+    // Statements and expressions are mutually recursive through labeled blocks
+    let mut statements = Recursive::declare();
+
     let expr = recursive(|expr| {
-        let literal = select! {
-            Token::Integer(value) => ast::Expr::IntLit(value.parse().unwrap()),
-            Token::Identifier(ident) if ident == "true" => ast::Expr::BoolLit(true),
-            Token::Identifier(ident) if ident == "false" => ast::Expr::BoolLit(false),
-        };
+        let integer = select! { Token::Integer(value) => value }.validate(|value, e, emitter| {
+            let value = parse_int_literal(value).unwrap_or_else(|message| {
+                emitter.emit(Rich::custom(e.span(), message));
+                0
+            });
+            ast::ExprKind::IntLit(value)
+        });
 
-        // variable reference (identifier as expression)
-        let var_ref = identifier.map(|name| ast::Expr::VarRef { name });
+        // Escapes are processed here rather than in the lexer, so an invalid one is reported at its own span
+        let string = select! { Token::String(value) => value }.validate(|value, e, emitter| {
+            let span: SimpleSpan = e.span();
+            // Skip the opening quote
+            let offset = span.start + 1;
+            let value = unescape(value).unwrap_or_else(|(range, message)| {
+                let span = SimpleSpan::from(offset + range.start..offset + range.end);
+                emitter.emit(Rich::custom(span, message));
+                String::new()
+            });
+            ast::ExprKind::StrLit(value)
+        });
 
-        // "(" [ { expr "," } expr ] ")"
-        let call_args = expr
-            .clone()
+        let literal = integer.or(string).or(select! {
+            Token::Float(value) => ast::ExprKind::FloatLit(value.parse().unwrap()),
+            Token::RawString(value) => ast::ExprKind::StrLit(value.to_string()),
+            Token::Identifier(ident) if ident == "true" => ast::ExprKind::BoolLit(true),
+            Token::Identifier(ident) if ident == "false" => ast::ExprKind::BoolLit(false),
+        });
+
+        // [ identifier ":" ] expr
+        let call_arg = identifier
+            .then_ignore(just(Token::Colon))
+            .or_not()
+            .then(expr.clone());
+
+        // "(" [ { call_arg "," } call_arg ] ")"
+        // Named arguments may only follow the positional ones
+        let call_args = call_arg
             .separated_by(just(Token::Comma))
             .collect::<Vec<_>>()
-            .delimited_by(just(Token::LParen), just(Token::RParen));
+            .delimited_by(just(Token::LParen), just(Token::RParen))
+            .validate(|call_args, _, emitter| {
+                let mut args = Vec::new();
+                let mut arg_names = Vec::new();
+                for (arg_name, arg) in call_args {
+                    match arg_name {
+                        Some(arg_name) => arg_names.push(arg_name),
+                        None if !arg_names.is_empty() => emitter.emit(Rich::custom(
+                            SimpleSpan::from(arg.span.clone()),
+                            "positional argument after named arguments",
+                        )),
+                        None => {}
+                    }
+                    args.push(arg);
+                }
+                (args, arg_names)
+            });
+
+        // identifier [ call_args ]
+        // A call when followed by an argument list, a variable reference otherwise
+        let name_or_call = identifier
+            .then(call_args.or_not())
+            .map(|(name, args)| match args {
+                Some((args, arg_names)) => ast::ExprKind::FnCall {
+                    name,
+                    args,
+                    arg_names,
+                },
+                None => ast::ExprKind::VarRef { name },
+            });
+
+        // identifier ":" expr
+        let struct_lit_field = identifier
+            .then_ignore(just(Token::Colon))
+            .then(expr.clone())
+            .map(|(name, value)| ast::StructLitField { name, value });
+
+        // struct literal: identifier "{" { struct_lit_field "," } struct_lit_field [","] "}"
+        // At least one field is required so that `if flag {}` is not parsed as a struct literal
+        let struct_lit = identifier
+            .then(
+                struct_lit_field
+                    .separated_by(just(Token::Comma))
+                    .allow_trailing()
+                    .at_least(1)
+                    .collect::<Vec<_>>()
+                    .delimited_by(just(Token::LBrace), just(Token::RBrace)),
+            )
+            .map(|(name, fields)| ast::ExprKind::StructLit { name, fields });
+
+        // enum variant: identifier "::" identifier
+        let enum_variant = identifier
+            .then_ignore(just(Token::ColonColon))
+            .then(identifier)
+            .map(|(name, variant)| ast::ExprKind::EnumVariant { name, variant });
+
+        // "sizeof" "(" type ")"
+        let size_of = just(Token::SizeOf)
+            .ignore_then(r#type.delimited_by(just(Token::LParen), just(Token::RParen)))
+            .map(|r#type| ast::ExprKind::SizeOf { r#type });
+
+        // integer pattern: [ "-" ] integer
+        let int_pattern = just(Token::Sub)
+            .or_not()
+            .then(select! { Token::Integer(value) => value })
+            .validate(|(neg, value), e, emitter| {
+                let value = parse_int_literal(value).unwrap_or_else(|message| {
+                    emitter.emit(Rich::custom(e.span(), message));
+                    0
+                });
+                ast::Pattern::IntLit(if neg.is_some() { -value } else { value })
+            });
+
+        // pattern: int_pattern | "_"
+        let pattern = int_pattern.or(select! {
+            Token::Identifier(ident) if ident == "_" => ast::Pattern::Wildcard,
+        });
+
+        // "match" expr "{" { pattern "=>" expr "," } pattern "=>" expr [","] "}"
+        let match_expr = just(Token::Match)
+            .ignore_then(expr.clone())
+            .then(
+                pattern
+                    .map_with(|pattern, e| (pattern, e.span().into_range()))
+                    .then_ignore(just(Token::FatArrow))
+                    .then(expr.clone())
+                    .map(|((pattern, pattern_span), value)| ast::MatchArm {
+                        pattern,
+                        pattern_span,
+                        value,
+                    })
+                    .separated_by(just(Token::Comma))
+                    .allow_trailing()
+                    .at_least(1)
+                    .collect::<Vec<_>>()
+                    .delimited_by(just(Token::LBrace), just(Token::RBrace)),
+            )
+            .map(|(scrutinee, arms)| ast::ExprKind::Match {
+                scrutinee: Box::new(scrutinee),
+                arms,
+            });
 
-        // function call: identifier '(' [args] ')' (only in expression context)
-        let function_call = identifier
-            .then(call_args)
-            .map(|(name, args)| ast::Expr::FnCall { name, args });
+        // label ":" "{" statements "}"
+        let labeled_block = label
+            .then_ignore(just(Token::Colon))
+            .then(
+                statements
+                    .clone()
+                    .delimited_by(just(Token::LBrace), just(Token::RBrace)),
+            )
+            .map(|(label, body)| ast::ExprKind::Block { label, body });
+
+        // "if" expr "{" statements "}" "else" ( if_expr | "{" statements "}" )
+        // An `if` at the start of a statement is an if statement instead
+        let if_expr = recursive(|if_expr| {
+            let branch = statements
+                .clone()
+                .delimited_by(just(Token::LBrace), just(Token::RBrace));
+            just(Token::If)
+                .ignore_then(expr.clone())
+                .then(branch.clone())
+                .then(
+                    just(Token::Else)
+                        .ignore_then(
+                            if_expr
+                                .map_with(|kind, e| {
+                                    vec![ast::Stmt::Expr {
+                                        expr: Box::new(ast::Expr::new(kind, e.span().into_range())),
+                                    }]
+                                })
+                                .or(branch),
+                        )
+                        .or_not(),
+                )
+                // Without an `else` there is no value when the condition is false
+                .validate(|((condition, then_branch), else_branch), e, emitter| {
+                    let else_branch = else_branch.unwrap_or_else(|| {
+                        emitter.emit(Rich::custom(
+                            e.span(),
+                            "if expression without else cannot produce a value",
+                        ));
+                        Vec::new()
+                    });
+                    ast::ExprKind::If {
+                        condition: Box::new(condition),
+                        then_branch,
+                        else_branch,
+                    }
+                })
+        });
 
-        let primary = choice((
-            // function call
-            function_call,
+        let atom = choice((
+            // if expression
+            if_expr,
+            // labeled block
+            labeled_block,
+            // match expression
+            match_expr,
+            // sizeof
+            size_of,
+            // struct literal
+            struct_lit,
+            // enum variant
+            enum_variant,
             // literal
             literal,
-            // variable reference
-            var_ref,
-            // "(" expr ")"
-            expr.clone()
-                .delimited_by(just(Token::LParen), just(Token::RParen)),
+            // function call or variable reference
+            name_or_call,
+        ))
+        .map_with(|kind, e| ast::Expr::new(kind, e.span().into_range()))
+        // "(" expr { "," expr } ")"
+        // A comma expression when there is more than one expression, as in C
+        .or(expr
+            .clone()
+            .separated_by(just(Token::Comma))
+            .at_least(1)
+            .collect::<Vec<_>>()
+            .delimited_by(just(Token::LParen), just(Token::RParen))
+            .map_with(|mut exprs, e| {
+                if exprs.len() == 1 {
+                    exprs.pop().unwrap()
+                } else {
+                    ast::Expr::new(ast::ExprKind::Sequence { exprs }, e.span().into_range())
+                }
+            }));
+
+        // atom { "." identifier }
+        let primary = atom.foldl(
+            just(Token::Dot)
+                .ignore_then(identifier)
+                .map_with(|field, e| (field, e.span()))
+                .repeated(),
+            |expr, (field, span)| {
+                let span = expr.span.start..span.end;
+                ast::Expr::new(
+                    ast::ExprKind::FieldAccess {
+                        expr: Box::new(expr),
+                        field,
+                    },
+                    span,
+                )
+            },
+        );
+
+        // "++" | "--"
+        let inc_dec_op = choice((
+            just(Token::PlusPlus).to(ast::IncDecOp::Increment),
+            just(Token::MinusMinus).to(ast::IncDecOp::Decrement),
         ));
 
         let unary = choice((
+            // ("++" | "--") identifier
+            inc_dec_op
+                .clone()
+                .then(identifier)
+                .map_with(|(op, name), e| {
+                    ast::Expr::new(
+                        ast::ExprKind::IncDec {
+                            op,
+                            name,
+                            postfix: false,
+                        },
+                        e.span().into_range(),
+                    )
+                }),
+            // identifier ("++" | "--")
+            identifier.then(inc_dec_op).map_with(|(name, op), e| {
+                ast::Expr::new(
+                    ast::ExprKind::IncDec {
+                        op,
+                        name,
+                        postfix: true,
+                    },
+                    e.span().into_range(),
+                )
+            }),
             // "-" primary
             just(Token::Sub)
                 .ignore_then(primary.clone())
-                .map(|expr| ast::Expr::UnaryOp {
-                    op: ast::UnaryOp::Neg,
-                    expr: Box::new(expr),
+                .map_with(|expr, e| {
+                    ast::Expr::new(
+                        ast::ExprKind::UnaryOp {
+                            op: ast::UnaryOp::Neg,
+                            expr: Box::new(expr),
+                        },
+                        e.span().into_range(),
+                    )
                 }),
             // "!" primary
             just(Token::Not)
                 .ignore_then(primary.clone())
-                .map(|expr| ast::Expr::UnaryOp {
-                    op: ast::UnaryOp::Not,
-                    expr: Box::new(expr),
+                .map_with(|expr, e| {
+                    ast::Expr::new(
+                        ast::ExprKind::UnaryOp {
+                            op: ast::UnaryOp::Not,
+                            expr: Box::new(expr),
+                        },
+                        e.span().into_range(),
+                    )
+                }),
+            // "~" primary
+            just(Token::Tilde)
+                .ignore_then(primary.clone())
+                .map_with(|expr, e| {
+                    ast::Expr::new(
+                        ast::ExprKind::UnaryOp {
+                            op: ast::UnaryOp::BitNot,
+                            expr: Box::new(expr),
+                        },
+                        e.span().into_range(),
+                    )
                 }),
             // primary
             primary,
         ));
 
-        // unary { ("*" | "/") unary }
-        let multiplication = unary.clone().foldl(
+        // unary { "as" type }
+        let cast = unary.foldl(
+            just(Token::As)
+                .ignore_then(r#type)
+                .map_with(|ty, e| (ty, e.span()))
+                .repeated(),
+            |expr, (ty, span)| {
+                let span = expr.span.start..span.end;
+                ast::Expr::new(
+                    ast::ExprKind::Cast {
+                        expr: Box::new(expr),
+                        r#type: ty,
+                    },
+                    span,
+                )
+            },
+        );
+
+        // cast { ("*" | "/") cast }
+        let multiplication = cast.clone().foldl(
             choice((
                 just(Token::Mul).to(ast::BinOp::Mul),
                 just(Token::Div).to(ast::BinOp::Div),
             ))
-            .then(unary)
+            .then(cast)
             .repeated(),
-            |lhs, (op, rhs)| ast::Expr::BinOp {
-                lhs: Box::new(lhs),
-                op,
-                rhs: Box::new(rhs),
-            },
+            binary,
         );
 
         // multiplication { ("+" | "-") multiplication }
@@ -100,32 +396,75 @@ where
                 ))
                 .then(multiplication)
                 .repeated(),
-                |lhs, (op, rhs)| ast::Expr::BinOp {
-                    lhs: Box::new(lhs),
-                    op,
-                    rhs: Box::new(rhs),
-                },
+                binary,
             )
             .boxed();
 
         // addition { ("<" | "<=" | ">" | ">=") addition }
-        let comparison = addition.clone().foldl(
-            choice((
-                just(Token::Equal).to(ast::BinOp::Equal),
-                just(Token::NotEqual).to(ast::BinOp::NotEqual),
-                just(Token::LessThan).to(ast::BinOp::LessThan),
-                just(Token::LessThanOrEqual).to(ast::BinOp::LessThanOrEqual),
-                just(Token::GreaterThan).to(ast::BinOp::GreaterThan),
-                just(Token::GreaterThanOrEqual).to(ast::BinOp::GreaterThanOrEqual),
-            ))
-            .then(addition)
-            .repeated(),
-            |lhs, (op, rhs)| ast::Expr::BinOp {
-                lhs: Box::new(lhs),
-                op,
-                rhs: Box::new(rhs),
-            },
-        );
+        // A chain such as `a < b < c` is an error, or `a < b && b < c` with the sugar option.
+        // The rewrite evaluates `b` twice, so it is only done when `b` is a variable or a literal
+        let comparison = addition
+            .clone()
+            .then(
+                choice((
+                    just(Token::Equal).to(ast::BinOp::Equal),
+                    just(Token::NotEqual).to(ast::BinOp::NotEqual),
+                    just(Token::LessThan).to(ast::BinOp::LessThan),
+                    just(Token::LessThanOrEqual).to(ast::BinOp::LessThanOrEqual),
+                    just(Token::GreaterThan).to(ast::BinOp::GreaterThan),
+                    just(Token::GreaterThanOrEqual).to(ast::BinOp::GreaterThanOrEqual),
+                ))
+                .then(addition)
+                .repeated()
+                .collect::<Vec<_>>(),
+            )
+            .validate(move |(first, rest), _, emitter| {
+                let mut expr = first;
+                // The right operand of the previous operator, if it was an ordering
+                let mut middle: Option<ast::Expr> = None;
+                for (op, rhs) in rest {
+                    let ordering = matches!(
+                        op,
+                        ast::BinOp::LessThan
+                            | ast::BinOp::LessThanOrEqual
+                            | ast::BinOp::GreaterThan
+                            | ast::BinOp::GreaterThanOrEqual
+                    );
+                    expr = match middle.take().filter(|_| ordering) {
+                        Some(middle)
+                            if options.sugar
+                                && matches!(
+                                    middle.kind,
+                                    ast::ExprKind::VarRef { .. }
+                                        | ast::ExprKind::IntLit(_)
+                                        | ast::ExprKind::FloatLit(_)
+                                        | ast::ExprKind::BoolLit(_)
+                                        | ast::ExprKind::StrLit(_)
+                                ) =>
+                        {
+                            let comparison = binary(middle, (op, rhs.clone()));
+                            binary(expr, (ast::BinOp::And, comparison))
+                        }
+                        Some(_) => {
+                            let message = if options.sugar {
+                                "comparison operators cannot be chained around an operand other than a variable or a literal; write `a < b && b < c` instead"
+                            } else {
+                                "comparison operators cannot be chained; write `a < b && b < c` instead, or pass --sugar to rewrite it"
+                            };
+                            emitter.emit(Rich::custom(
+                                SimpleSpan::from(expr.span.start..rhs.span.end),
+                                message,
+                            ));
+                            binary(expr, (op, rhs.clone()))
+                        }
+                        None => binary(expr, (op, rhs.clone())),
+                    };
+                    if ordering {
+                        middle = Some(rhs);
+                    }
+                }
+                expr
+            });
 
         // comparison { ("==" | "!=") comparison }
         let equality = comparison
@@ -137,11 +476,7 @@ where
                 ))
                 .then(comparison)
                 .repeated(),
-                |lhs, (op, rhs)| ast::Expr::BinOp {
-                    lhs: Box::new(lhs),
-                    op,
-                    rhs: Box::new(rhs),
-                },
+                binary,
             )
             .boxed();
 
@@ -151,31 +486,32 @@ where
                 .to(ast::BinOp::And)
                 .then(equality)
                 .repeated(),
-            |lhs, (op, rhs)| ast::Expr::BinOp {
-                lhs: Box::new(lhs),
-                op,
-                rhs: Box::new(rhs),
-            },
+            binary,
         );
 
-        // logical_and { "||" logical_and }
+        // logical_and { "^^" logical_and }
+        let logical_xor = logical_and.clone().foldl(
+            just(Token::Xor)
+                .to(ast::BinOp::Xor)
+                .then(logical_and)
+                .repeated(),
+            binary,
+        );
+
+        // logical_xor { "||" logical_xor }
         #[allow(clippy::let_and_return)]
-        let logical_or = logical_and.clone().foldl(
+        let logical_or = logical_xor.clone().foldl(
             just(Token::Or)
                 .to(ast::BinOp::Or)
-                .then(logical_and)
+                .then(logical_xor)
                 .repeated(),
-            |lhs, (op, rhs)| ast::Expr::BinOp {
-                lhs: Box::new(lhs),
-                op,
-                rhs: Box::new(rhs),
-            },
+            binary,
         );
 
         logical_or
     });
 
-    let statements = recursive(|statements| {
+    let statement_list = {
         // expr ";"
         let expr_statement = expr
             .clone()
@@ -190,10 +526,40 @@ where
             .then(just(Token::Colon).ignore_then(r#type).or_not())
             .then(just(Token::Assign).ignore_then(expr.clone()).or_not())
             .then_ignore(just(Token::Semicolon))
-            .map(|((name, ty), value)| ast::Stmt::LetDecl {
-                name,
-                r#type: ty,
-                value,
+            .validate(|((name, ty), value), e, emitter| {
+                if let Some(value) = &value {
+                    if let Some(message) = ty.and_then(|ty| check_literal_range(ty, value)) {
+                        emitter.emit(Rich::custom(SimpleSpan::from(value.span.clone()), message));
+                    }
+                }
+                ast::Stmt::LetDecl {
+                    is_const: false,
+                    name,
+                    r#type: ty,
+                    value,
+                    span: e.span().into_range(),
+                }
+            });
+
+        // "const" identifier ":" type "=" expr ";"
+        let const_declaration = just(Token::Const)
+            .ignore_then(identifier)
+            .then_ignore(just(Token::Colon))
+            .then(r#type)
+            .then_ignore(just(Token::Assign))
+            .then(expr.clone())
+            .then_ignore(just(Token::Semicolon))
+            .validate(|((name, ty), value), e, emitter| {
+                if let Some(message) = check_literal_range(ty, &value) {
+                    emitter.emit(Rich::custom(SimpleSpan::from(value.span.clone()), message));
+                }
+                ast::Stmt::LetDecl {
+                    is_const: true,
+                    name,
+                    r#type: Some(ty),
+                    value: Some(value),
+                    span: e.span().into_range(),
+                }
             });
 
         // "var" identifier [":" type] ["=" expr] ";"
@@ -202,10 +568,18 @@ where
             .then(just(Token::Colon).ignore_then(r#type).or_not())
             .then(just(Token::Assign).ignore_then(expr.clone()).or_not())
             .then_ignore(just(Token::Semicolon))
-            .map(|((name, ty), value)| ast::Stmt::VarDecl {
-                name,
-                r#type: ty,
-                value,
+            .validate(|((name, ty), value), e, emitter| {
+                if let Some(value) = &value {
+                    if let Some(message) = ty.and_then(|ty| check_literal_range(ty, value)) {
+                        emitter.emit(Rich::custom(SimpleSpan::from(value.span.clone()), message));
+                    }
+                }
+                ast::Stmt::VarDecl {
+                    name,
+                    r#type: ty,
+                    value,
+                    span: e.span().into_range(),
+                }
             });
 
         // identifier "=" expr ";"
@@ -213,17 +587,88 @@ where
             .then_ignore(just(Token::Assign))
             .then(expr.clone())
             .then_ignore(just(Token::Semicolon))
-            .map(|(name, value)| ast::Stmt::Assign {
+            .map_with(|(name, value), e| ast::Stmt::Assign {
                 name,
                 value: Box::new(value),
+                span: e.span().into_range(),
             });
 
         // "return" [ expr ] ";"
         let return_statement = just(Token::Return)
             .ignore_then(expr.clone().or_not())
             .then_ignore(just(Token::Semicolon))
-            .map(|expr| ast::Stmt::Return {
+            .map_with(|expr, e| ast::Stmt::Return {
                 expr: expr.map(Box::new),
+                span: e.span().into_range(),
+            });
+
+        // identifier ":" type
+        let struct_field = identifier
+            .then_ignore(just(Token::Colon))
+            .then(r#type)
+            .map(|(name, ty)| ast::StructField { name, r#type: ty });
+
+        // "struct" identifier "{" [ { struct_field "," } struct_field [","] ] "}"
+        let struct_declaration = just(Token::Struct)
+            .ignore_then(identifier)
+            .then(
+                struct_field
+                    .separated_by(just(Token::Comma))
+                    .allow_trailing()
+                    .collect::<Vec<_>>()
+                    .delimited_by(just(Token::LBrace), just(Token::RBrace)),
+            )
+            .map_with(|(name, fields), e| ast::Stmt::StructDecl {
+                name,
+                fields,
+                span: e.span().into_range(),
+            });
+
+        // identifier [ "=" [ "-" ] integer ]
+        let enum_variant = identifier
+            .then(
+                just(Token::Assign)
+                    .ignore_then(just(Token::Sub).or_not())
+                    .then(select! { Token::Integer(value) => value })
+                    .map_with(|(neg, value), e| (neg.is_some(), value, e.span()))
+                    .or_not(),
+            )
+            .validate(|(name, value), _, emitter| {
+                let value = value.and_then(|(neg, value, span)| {
+                    let value = match parse_int_literal(value) {
+                        Ok(value) => value,
+                        Err(message) => {
+                            emitter.emit(Rich::custom(span, message));
+                            return None;
+                        }
+                    };
+                    let value = if neg { -value } else { value };
+                    if i32::try_from(value).is_err() {
+                        emitter.emit(Rich::custom(
+                            span,
+                            format!("discriminant of '{name}' out of range for i32"),
+                        ));
+                        return None;
+                    }
+                    Some(value)
+                });
+                ast::EnumVariant { name, value }
+            });
+
+        // "enum" identifier "{" [ { enum_variant "," } enum_variant [","] ] "}"
+        let enum_declaration = just(Token::Enum)
+            .ignore_then(identifier)
+            .then(
+                enum_variant
+                    .separated_by(just(Token::Comma))
+                    .allow_trailing()
+                    .collect::<Vec<_>>()
+                    .delimited_by(just(Token::LBrace), just(Token::RBrace)),
+            )
+            .map_with(|(name, variants), e| ast::Stmt::EnumDecl {
+                name,
+                variants,
+                span: e.span().into_range(),
             });
 
         // identifier ":" type
@@ -241,23 +686,84 @@ where
             )
             .then_ignore(just(Token::RParen));
 
+        // "{" { any token | braced_group } "}"
+        // Used to skip over code with syntax errors, keeping its braces balanced
+        let braced_group = recursive(|braced_group| {
+            none_of([Token::LBrace, Token::RBrace])
+                .ignored()
+                .or(braced_group)
+                .repeated()
+                .delimited_by(just(Token::LBrace), just(Token::RBrace))
+        });
+
         // "{" statements [ expr ] "}"
+        // A block that fails to parse is skipped up to its closing brace, so that the code after it is still checked
         let block = just(Token::LBrace)
             .ignore_then(statements.clone())
-            .then_ignore(just(Token::RBrace));
+            .then_ignore(just(Token::RBrace))
+            .recover_with(via_parser(braced_group.clone().map(|()| Vec::new())));
+
+        // "#" "[" identifier "]"
+        let attribute = just(Token::Hash)
+            .ignore_then(identifier.delimited_by(just(Token::LBracket), just(Token::RBracket)))
+            .validate(|name, e, emitter| match name {
+                "inline" => Some(ast::FnAttribute::Inline),
+                "noinline" => Some(ast::FnAttribute::NoInline),
+                _ => {
+                    emitter.emit(Rich::custom(
+                        e.span(),
+                        format!("unknown attribute `{name}`"),
+                    ));
+                    None
+                }
+            });
+
+        // { attribute } [ "const" ] "fn" identifier function_parameters [ "->" type ] function_body
+        let function_declaration = attribute
+            .repeated()
+            .collect::<Vec<_>>()
+            .then(just(Token::Const).or_not().map(|token| token.is_some()))
+            .then_ignore(just(Token::FunctionDeclaration))
+            .then(identifier)
+            .then(function_parameters.clone())
+            .then(just(Token::RightArrow).ignore_then(r#type).or_not())
+            .then(block.clone())
+            .validate(
+                |(((((attributes, is_const), name), params), return_type), body), e, emitter| {
+                    let attributes: Vec<_> = attributes.into_iter().flatten().collect();
+                    if attributes.contains(&ast::FnAttribute::Inline)
+                        && attributes.contains(&ast::FnAttribute::NoInline)
+                    {
+                        emitter.emit(Rich::custom(
+                            e.span(),
+                            "conflicting attributes `inline` and `noinline`",
+                        ));
+                    }
+                    ast::Stmt::FnDecl {
+                        attributes,
+                        is_const,
+                        name,
+                        params,
+                        r#type: return_type,
+                        body,
+                        span: e.span().into_range(),
+                    }
+                },
+            );
 
-        // "fn" identifier function_parameters "->" type function_body
-        let function_declaration = just(Token::FunctionDeclaration)
+        // "extern" "fn" identifier function_parameters "->" type ";"
+        let extern_declaration = just(Token::Extern)
+            .ignore_then(just(Token::FunctionDeclaration))
             .ignore_then(identifier)
             .then(function_parameters)
             .then_ignore(just(Token::RightArrow))
             .then(r#type)
-            .then(block.clone())
-            .map(|(((name, params), return_type), body)| ast::Stmt::FnDecl {
+            .then_ignore(just(Token::Semicolon))
+            .map_with(|((name, params), return_type), e| ast::Stmt::ExternDecl {
                 name,
                 params,
                 r#type: return_type,
-                body,
+                span: e.span().into_range(),
             });
 
         // "if" expr block [ "else" (if_stmt | block) ]
@@ -293,14 +799,82 @@ where
                 })
         });
 
+        // "while" expr block
+        let while_statement = just(Token::While)
+            .ignore_then(expr.clone())
+            .then(block.clone())
+            .map(|(condition, body)| ast::Stmt::While {
+                condition: Box::new(condition),
+                body,
+            });
+
+        // "do" block "while" expr ";"
+        let do_while_statement = just(Token::Do)
+            .ignore_then(block.clone())
+            .then_ignore(just(Token::While))
+            .then(expr.clone())
+            .then_ignore(just(Token::Semicolon))
+            .map(|(body, condition)| ast::Stmt::DoWhile {
+                body,
+                condition: Box::new(condition),
+            });
+
+        // "break" [ label expr ] ";"
+        let break_statement = just(Token::Break)
+            .ignore_then(label.then(expr.clone()).or_not())
+            .then_ignore(just(Token::Semicolon))
+            .map_with(|target, e| {
+                let (label, value) = target.unzip();
+                ast::Stmt::Break {
+                    label,
+                    value: value.map(Box::new),
+                    span: e.span().into_range(),
+                }
+            });
+
+        // block
+        let block_statement = block.clone().map(|body| ast::Stmt::Block { body });
+
+        // "continue" ";"
+        let continue_statement = just(Token::Continue)
+            .then_ignore(just(Token::Semicolon))
+            .map_with(|_, e| ast::Stmt::Continue {
+                span: e.span().into_range(),
+            });
+
         let statement = choice((
             let_declaration,
             var_declaration,
+            const_declaration,
             assignment,
             return_statement,
             function_declaration,
-            expr_statement,
+            extern_declaration,
+            struct_declaration,
+            enum_declaration,
+            // Before expression statements, so that an `if` at the start of a statement is an if statement
             if_statement,
+            expr_statement,
+            while_statement,
+            do_while_statement,
+            break_statement,
+            continue_statement,
+            block_statement,
+        ))
+        // On a syntax error, skip to the end of the statement and report the errors in the statements after it too.
+        // The statement stays inside its block, so a missing `;` before `}` is left to the block's recovery.
+        // The placeholder is never compiled, since a program with errors is rejected
+        .recover_with(via_parser(
+            braced_group
+                .or(none_of([Token::Semicolon, Token::LBrace, Token::RBrace]).ignored())
+                .repeated()
+                .then(just(Token::Semicolon))
+                .map_with(|_, e| ast::Stmt::ExprStmt {
+                    expr: Box::new(ast::Expr::new(
+                        ast::ExprKind::IntLit(0),
+                        e.span().into_range(),
+                    )),
+                }),
         ));
 
         statement
@@ -318,14 +892,119 @@ where
                 }
                 body
             })
-    });
+    };
+    statements.define(statement_list);
 
     statements
         .then_ignore(end())
         .map(|statements| ast::Program { statements })
 }
 
+/// Build a binary operation spanning both operands
+fn binary<'a>(lhs: ast::Expr<'a>, (op, rhs): (ast::BinOp, ast::Expr<'a>)) -> ast::Expr<'a> {
+    let span = lhs.span.start..rhs.span.end;
+    ast::Expr::new(
+        ast::ExprKind::BinOp {
+            lhs: Box::new(lhs),
+            op,
+            rhs: Box::new(rhs),
+        },
+        span,
+    )
+}
+
+/// Parse an integer literal token, which may have a `0x`, `0o` or `0b` prefix and `_` separators
+fn parse_int_literal(value: &str) -> Result<i64, String> {
+    let (radix, digits) = match value.get(..2) {
+        Some("0x" | "0X") => (16, &value[2..]),
+        Some("0o" | "0O") => (8, &value[2..]),
+        Some("0b" | "0B") => (2, &value[2..]),
+        _ => (10, value),
+    };
+    // Separators may only appear between digits
+    if digits.starts_with('_') || digits.ends_with('_') {
+        return Err(format!("misplaced underscore in integer literal `{value}`"));
+    }
+    i64::from_str_radix(&digits.replace('_', ""), radix)
+        .map_err(|_| "integer literal too large".to_string())
+}
+
+/// Process the escapes of a string literal's contents: `\n`, `\t`, `\r`, `\\`, `\"`, `\0` and
+/// `\u{XXXX}` for a unicode code point of one to six hex digits.
+/// On error, returns the byte range of the offending escape within `value` and a message.
+fn unescape(value: &str) -> Result<String, (std::ops::Range<usize>, String)> {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        // The lexer guarantees that a backslash is followed by another character
+        let (_, escape) = chars.next().unwrap();
+        let end = start + 1 + escape.len_utf8();
+        out.push(match escape {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            '\\' => '\\',
+            '"' => '"',
+            'u' => {
+                // `\u{` hex digits `}`
+                let rest = &value[end..];
+                let digits = rest
+                    .strip_prefix('{')
+                    .and_then(|rest| rest.split_once('}'))
+                    .map(|(digits, _)| digits);
+                let Some(digits) = digits else {
+                    return Err((
+                        start..end,
+                        "unicode escape must be written as `\\u{XXXX}`".to_string(),
+                    ));
+                };
+                let end = end + digits.len() + 2;
+                let is_hex = (1..=6).contains(&digits.len())
+                    && digits.chars().all(|c| c.is_ascii_hexdigit());
+                let code_point = is_hex.then(|| u32::from_str_radix(digits, 16).unwrap());
+                let Some(c) = code_point.and_then(char::from_u32) else {
+                    return Err((
+                        start..end,
+                        format!("invalid unicode escape `{}`", &value[start..end]),
+                    ));
+                };
+                // Skip past the braces and digits
+                while chars.next_if(|&(index, _)| index < end).is_some() {}
+                c
+            }
+            _ => {
+                return Err((
+                    start..end,
+                    format!("unknown escape sequence `{}`", &value[start..end]),
+                ));
+            }
+        });
+    }
+    Ok(out)
+}
+
+/// Returns an error message if `value` is an integer literal that doesn't fit in `ty`
+fn check_literal_range(ty: ast::Type, value: &ast::Expr) -> Option<String> {
+    let literal = ast::int_literal_value(value)?;
+    let out_of_range =
+        matches!(ty, ast::Type::I32 | ast::Type::I64) && !ast::int_literal_fits(literal, ty);
+    out_of_range.then(|| format!("literal {literal} out of range for {ty}"))
+}
+
 pub fn parse(src: &str) -> ParseResult<ast::Program, chumsky::error::Rich<'_, Token<'_>>> {
+    parse_with(src, Options::default())
+}
+
+/// Parse a program like [`parse`], with the given options
+pub fn parse_with(
+    src: &str,
+    options: Options,
+) -> ParseResult<ast::Program, chumsky::error::Rich<'_, Token<'_>>> {
     // Create a logos lexer over the source code
     let token_iter = Token::lexer(src)
         .spanned()
@@ -335,6 +1014,10 @@ pub fn parse(src: &str) -> ParseResult<ast::Program, chumsky::error::Rich<'_, To
             // Turn the `Range<usize>` spans logos gives us into chumsky's `SimpleSpan` via `Into`, because it's easier
             // to work with
             Ok(tok) => (tok, span.into()),
+            // An unterminated block comment runs to the end of the input, so point at its opener
+            Err(()) if src[span.clone()].starts_with("/*") => {
+                (Token::Error, (span.start..span.start + "/*".len()).into())
+            }
             Err(()) => (Token::Error, span.into()),
         });
 
@@ -345,7 +1028,7 @@ pub fn parse(src: &str) -> ParseResult<ast::Program, chumsky::error::Rich<'_, To
         .map((0..src.len()).into(), |(t, s): (_, _)| (t, s));
 
     // Parse the token stream with our chumsky parser
-    parser().parse(token_stream)
+    parser(options).parse(token_stream)
 }
 
 #[cfg(test)]
@@ -399,6 +1082,16 @@ mod tests {
         assert_yaml_snapshot!(program);
     }
 
+    #[test]
+    fn test_parse_bitwise_not() {
+        let input = "~x";
+        let result = parse(input);
+        assert!(has_no_errors(&result));
+
+        let program = result.into_result().unwrap();
+        assert_yaml_snapshot!(program);
+    }
+
     #[test]
     fn test_parse_function_declaration() {
         let input = "fn zero() -> i32 { 0 }";
@@ -432,6 +1125,16 @@ mod tests {
         assert_yaml_snapshot!(program);
     }
 
+    #[test]
+    fn test_parse_call_and_variable_with_same_name() {
+        let input = "value() + value";
+        let result = parse(input);
+        assert!(has_no_errors(&result));
+
+        let program = result.into_result().unwrap();
+        assert_yaml_snapshot!(program);
+    }
+
     #[test]
     fn test_parse_variable_declaration() {
         let input = "let x: i32 = 42;";
@@ -573,6 +1276,36 @@ mod tests {
         assert_yaml_snapshot!(format!("{:?}", errors));
     }
 
+    #[test]
+    fn test_parse_recovers_after_statement_errors() {
+        let input = indoc! {"
+            fn add(a: i32, b: i32) -> i32 {
+                let sum = a + ;
+                sum
+            }
+
+            let x = 1 2;
+            add(x, 3)
+        "};
+        let errors = parse(input).into_errors();
+        let spans: Vec<_> = errors
+            .iter()
+            .map(|error| &input[error.span().into_range()])
+            .collect();
+        assert_eq!(spans, [";", "2"]);
+    }
+
+    #[test]
+    fn test_parse_recovers_after_block_errors() {
+        // The block is skipped as a whole, including its nested braces
+        let input = "fn f() -> i32 { if true { 1 } + }
+let y = ;
+0";
+        let errors = parse(input).into_errors();
+        assert_eq!(errors.len(), 2, "{errors:?}");
+        assert_eq!(&input[errors[1].span().into_range()], ";");
+    }
+
     #[test]
     fn test_parse_with_comments() {
         let input = indoc! {"
@@ -594,4 +1327,602 @@ mod tests {
         let program = result.into_result().unwrap();
         assert_yaml_snapshot!(program);
     }
+
+    #[test]
+    fn test_parse_struct_declaration() {
+        let input = "struct Point { x: i32, y: i64 }";
+        let result = parse(input);
+        assert!(has_no_errors(&result));
+
+        let program = result.into_result().unwrap();
+        assert_yaml_snapshot!(program);
+    }
+
+    #[test]
+    fn test_parse_enum_declaration_and_variant() {
+        let input = indoc! {"
+            enum Color { Red, Green = 5, Blue }
+            Color::Blue as i32
+        "};
+        let result = parse(input);
+        assert!(has_no_errors(&result));
+
+        let program = result.into_result().unwrap();
+        assert_yaml_snapshot!(program);
+    }
+
+    #[test]
+    fn test_parse_do_while_statement() {
+        let input = "do { continue; } while false;";
+        let result = parse(input);
+        assert!(has_no_errors(&result));
+
+        let program = result.into_result().unwrap();
+        assert_yaml_snapshot!(program);
+    }
+
+    #[test]
+    fn test_parse_empty_function_body() {
+        let input = "fn noop() -> void {}";
+        let result = parse(input);
+        assert!(has_no_errors(&result));
+
+        let program = result.into_result().unwrap();
+        assert_yaml_snapshot!(program);
+    }
+
+    #[test]
+    fn test_parse_trailing_expression_only_at_block_end() {
+        // A value without a `;` must be the last thing in every kind of block
+        for input in [
+            "fn f() -> i32 { 1 let x = 2; x }",
+            "if true { 1 let x = 2; }",
+            "while true { 1 break; }",
+            "do { 1 break; } while true;",
+            "1 let x = 2;",
+        ] {
+            assert!(!has_no_errors(&parse(input)), "{input:?} should not parse");
+        }
+    }
+
+    #[test]
+    fn test_parse_trailing_semicolon_discards_value() {
+        let program = parse("let x = 5;\nx").into_result().unwrap();
+        assert!(matches!(
+            program.statements.last(),
+            Some(ast::Stmt::Expr { .. })
+        ));
+
+        let program = parse("let x = 5;\nx;").into_result().unwrap();
+        assert!(matches!(
+            program.statements.last(),
+            Some(ast::Stmt::ExprStmt { .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_statements_on_one_line() {
+        let input = "let a = 1; let b = 2; a; b";
+        let result = parse(input);
+        assert!(has_no_errors(&result));
+
+        let program = result.into_result().unwrap();
+        let spans: Vec<_> = program
+            .statements
+            .iter()
+            .map(|stmt| match stmt {
+                ast::Stmt::LetDecl { span, .. } => &input[span.clone()],
+                ast::Stmt::ExprStmt { expr } | ast::Stmt::Expr { expr } => {
+                    &input[expr.span.clone()]
+                }
+                _ => panic!("unexpected statement: {stmt:?}"),
+            })
+            .collect();
+        assert_eq!(spans, ["let a = 1;", "let b = 2;", "a", "b"]);
+        assert_yaml_snapshot!(program);
+    }
+
+    #[test]
+    fn test_parse_named_arguments() {
+        let input = "add(1, b: 2, c: x)";
+        let result = parse(input);
+        assert!(has_no_errors(&result));
+
+        let program = result.into_result().unwrap();
+        assert_yaml_snapshot!(program);
+    }
+
+    #[test]
+    fn test_parse_positional_after_named_argument() {
+        let input = "add(a: 1, 2)";
+        let errors = parse(input).into_errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].reason().to_string(),
+            "positional argument after named arguments"
+        );
+        assert_eq!(errors[0].span().into_range(), 10..11);
+    }
+
+    #[test]
+    fn test_parse_comma_expression() {
+        let input = "(f(), 2 + 3)";
+        let result = parse(input);
+        assert!(has_no_errors(&result));
+
+        let program = result.into_result().unwrap();
+        let Some(ast::Stmt::Expr { expr }) = program.statements.last() else {
+            panic!("expected a trailing expression");
+        };
+        assert_eq!(expr.span, 0..input.len());
+        assert_yaml_snapshot!(program);
+    }
+
+    #[test]
+    fn test_parse_extern_declaration() {
+        let input = "extern fn abs(x: i32) -> i32;\nabs(1)";
+        let result = parse(input);
+        assert!(has_no_errors(&result));
+
+        let program = result.into_result().unwrap();
+        assert_yaml_snapshot!(program);
+    }
+
+    #[test]
+    fn test_parse_inferred_return_type() {
+        let input = "fn one() { 1 }\none()";
+        let result = parse(input);
+        assert!(has_no_errors(&result));
+
+        let program = result.into_result().unwrap();
+        assert_yaml_snapshot!(program);
+    }
+
+    #[test]
+    fn test_parse_function_attributes() {
+        let input = "#[inline]\nfn one() -> i32 { 1 }\n#[noinline]\nfn two() -> i32 { 2 }";
+        let result = parse(input);
+        assert!(has_no_errors(&result));
+
+        let program = result.into_result().unwrap();
+        assert_yaml_snapshot!(program);
+    }
+
+    #[test]
+    fn test_parse_logical_xor() {
+        let input = "a || b ^^ c && d";
+        let result = parse(input);
+        assert!(has_no_errors(&result));
+
+        let program = result.into_result().unwrap();
+        assert_yaml_snapshot!(program);
+    }
+
+    #[test]
+    fn test_parse_block_statement() {
+        let input = "{ let x = 1; }\nx";
+        let result = parse(input);
+        assert!(has_no_errors(&result));
+
+        let program = result.into_result().unwrap();
+        assert_yaml_snapshot!(program);
+    }
+
+    #[test]
+    fn test_parse_const_fn() {
+        let input = "const fn square(x: i32) -> i32 { x * x }";
+        let result = parse(input);
+        assert!(has_no_errors(&result));
+
+        let program = result.into_result().unwrap();
+        assert_yaml_snapshot!(program);
+    }
+
+    #[test]
+    fn test_parse_const_item() {
+        let input = "const N: i32 = square(4);";
+        let result = parse(input);
+        assert!(has_no_errors(&result));
+
+        let program = result.into_result().unwrap();
+        assert_yaml_snapshot!(program);
+    }
+
+    #[test]
+    fn test_parse_invalid_function_attributes() {
+        for (input, message) in [
+            ("#[cold]\nfn f() {}", "unknown attribute `cold`"),
+            (
+                "#[inline]\n#[noinline]\nfn f() {}",
+                "conflicting attributes `inline` and `noinline`",
+            ),
+        ] {
+            let errors = parse(input).into_errors();
+            assert_eq!(errors.len(), 1, "input: {input}");
+            assert_eq!(errors[0].reason().to_string(), message, "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_parse_increment_decrement() {
+        let input = "var i = 0;\n++i;\ni--;\n1 - -i";
+        let result = parse(input);
+        assert!(has_no_errors(&result));
+
+        let program = result.into_result().unwrap();
+        assert_yaml_snapshot!(program);
+    }
+
+    #[test]
+    fn test_parse_labeled_block() {
+        let input = "'a: { break 'a 1; 2 }";
+        let result = parse(input);
+        assert!(has_no_errors(&result));
+
+        let program = result.into_result().unwrap();
+        assert_yaml_snapshot!(program);
+    }
+
+    #[test]
+    fn test_parse_if_expression() {
+        let input = "let x = if a { 1 } else if b { 2 } else { 3 };";
+        let result = parse(input);
+        assert!(has_no_errors(&result));
+
+        let program = result.into_result().unwrap();
+        assert_yaml_snapshot!(program);
+    }
+
+    #[test]
+    fn test_parse_chained_comparison() {
+        let errors = parse("let ok = 1 < x < 10;").into_errors();
+        assert_eq!(errors.len(), 1);
+        assert!(
+            errors[0]
+                .reason()
+                .to_string()
+                .starts_with("comparison operators cannot be chained"),
+            "{errors:?}"
+        );
+        assert_eq!(errors[0].span().into_range(), 9..19);
+
+        // Parenthesized comparisons and `==` after an ordering are not chains
+        assert!(has_no_errors(&parse("(1 < x) == (x < 10)")));
+        assert!(has_no_errors(&parse("1 < x == true")));
+    }
+
+    #[test]
+    fn test_parse_chained_comparison_sugar() {
+        let input = "1 < x <= 10 > y";
+        let result = parse_with(input, Options { sugar: true });
+        assert!(has_no_errors(&result));
+
+        let program = result.into_result().unwrap();
+        assert_yaml_snapshot!(program);
+
+        // The middle operand would be evaluated twice
+        let errors = parse_with("1 < f() < 10", Options { sugar: true }).into_errors();
+        assert_eq!(errors.len(), 1);
+        assert!(
+            errors[0]
+                .reason()
+                .to_string()
+                .starts_with("comparison operators cannot be chained"),
+            "{errors:?}"
+        );
+    }
+
+    #[test]
+    fn test_parse_if_expression_without_else() {
+        let errors = parse("let x = if c { 1 };").into_errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].reason().to_string(),
+            "if expression without else cannot produce a value"
+        );
+        assert_eq!(errors[0].span().into_range(), 8..18);
+    }
+
+    #[test]
+    fn test_parse_float_literal() {
+        let input = "1.5 * 2.0";
+        let result = parse(input);
+        assert!(has_no_errors(&result));
+
+        let program = result.into_result().unwrap();
+        assert_yaml_snapshot!(program);
+    }
+
+    #[test]
+    fn test_parse_sizeof() {
+        let input = "sizeof(i32) + sizeof(Point)";
+        let result = parse(input);
+        assert!(has_no_errors(&result));
+
+        let program = result.into_result().unwrap();
+        assert_yaml_snapshot!(program);
+    }
+
+    #[test]
+    fn test_parse_string_literals() {
+        for (input, expected) in [
+            (r#""tab\tquote\"""#, "tab\tquote\""),
+            (r#"r"C:\temp\new""#, r"C:\temp\new"),
+            (
+                "\"\"\"\nline one\n  \"two\" \\n\n\"\"\"",
+                "\nline one\n  \"two\" \\n\n",
+            ),
+        ] {
+            let program = parse(input).into_result().unwrap();
+            let ast::Stmt::Expr { expr } = &program.statements[0] else {
+                panic!("expected an expression statement for {input}");
+            };
+            assert_eq!(
+                expr.kind,
+                ast::ExprKind::StrLit(expected.to_string()),
+                "input: {input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_string_escapes() {
+        for (input, expected) in [
+            (r#""a\nb""#, "a\nb"),
+            (r#""a\tb""#, "a\tb"),
+            (r#""a\rb""#, "a\rb"),
+            (r#""a\\b""#, "a\\b"),
+            (r#""a\"b""#, "a\"b"),
+            (r#""a\0b""#, "a\0b"),
+            (r#""caf\u{e9}""#, "café"),
+            (r#""\u{1F600}!""#, "😀!"),
+            (r#""\u{10FFFF}""#, "\u{10FFFF}"),
+        ] {
+            let program = parse(input).into_result().unwrap();
+            let ast::Stmt::Expr { expr } = &program.statements[0] else {
+                panic!("expected an expression statement for {input}");
+            };
+            assert_eq!(
+                expr.kind,
+                ast::ExprKind::StrLit(expected.to_string()),
+                "input: {input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_invalid_string_escapes() {
+        for (input, message, span) in [
+            (
+                r#"let s = "ok\q";"#,
+                "unknown escape sequence `\\q`",
+                11..13,
+            ),
+            (r#""\u{D800}""#, "invalid unicode escape `\\u{D800}`", 1..9),
+            (
+                r#""\u{110000}""#,
+                "invalid unicode escape `\\u{110000}`",
+                1..11,
+            ),
+            (r#""\u{+41}""#, "invalid unicode escape `\\u{+41}`", 1..8),
+            (r#""\u{}""#, "invalid unicode escape `\\u{}`", 1..5),
+            (
+                r#""\u41""#,
+                "unicode escape must be written as `\\u{XXXX}`",
+                1..3,
+            ),
+        ] {
+            let errors = parse(input).into_errors();
+            assert_eq!(errors.len(), 1, "input: {input}");
+            assert_eq!(errors[0].reason().to_string(), message, "input: {input}");
+            assert_eq!(errors[0].span().into_range(), span, "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_parse_invalid_string_literals() {
+        // Unknown escapes are only rejected outside raw strings
+        assert!(!has_no_errors(&parse(r#""\q""#)));
+        assert!(has_no_errors(&parse(r#"r"\q""#)));
+
+        // An unterminated raw string swallows the rest of the input as a single lex error
+        for input in ["r\"C:\\temp;\n1", "\"\"\"never\nclosed"] {
+            let tokens: Vec<_> = Token::lexer(input).spanned().collect();
+            assert_eq!(tokens, vec![(Err(()), 0..input.len())], "input: {input}");
+            assert!(!has_no_errors(&parse(input)));
+        }
+    }
+
+    #[test]
+    fn test_lex_preserving_comments() {
+        let input = "// one\nlet x = /* two */ 1;";
+        let comments: Vec<_> = crate::token::lexer_preserving_comments(input)
+            .spanned()
+            .filter(|(tok, _)| matches!(tok, Ok(Token::LineComment(_) | Token::BlockComment(_))))
+            .collect();
+        assert_eq!(
+            comments,
+            vec![
+                (Ok(Token::LineComment("// one")), 0..6),
+                (Ok(Token::BlockComment("/* two */")), 15..24),
+            ]
+        );
+
+        // The default lexer, and so `parse`, still skips comments
+        assert!(
+            Token::lexer(input)
+                .all(|tok| !matches!(tok, Ok(Token::LineComment(_) | Token::BlockComment(_))))
+        );
+        assert!(has_no_errors(&parse(input)));
+    }
+
+    #[test]
+    fn test_parse_unterminated_block_comment() {
+        let input = "let x = 1;\n/* never\nclosed\nx";
+        let tokens: Vec<_> = Token::lexer(input).spanned().collect();
+        assert_eq!(tokens.last(), Some(&(Err(()), 11..input.len())));
+
+        // The error points at the `/*` instead of the rest of the file
+        let errors = parse(input).into_errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].span().into_range(), 11..13);
+
+        // A terminated comment still closes at the first `*/`
+        assert!(has_no_errors(&parse("/**/ 1 /* a * b */")));
+    }
+
+    #[test]
+    fn test_parse_scientific_float_literals() {
+        for (input, expected) in [("1e10", 1e10), ("2.5e-3", 2.5e-3), ("1E+6", 1e6)] {
+            let program = parse(input).into_result().unwrap();
+            let ast::Stmt::Expr { expr } = &program.statements[0] else {
+                panic!("expected an expression statement for {input}");
+            };
+            assert_eq!(
+                expr.kind,
+                ast::ExprKind::FloatLit(expected),
+                "input: {input}"
+            );
+        }
+
+        // An exponent without digits is a lex error
+        assert!(!has_no_errors(&parse("1e")));
+        assert!(!has_no_errors(&parse("2.5e+")));
+    }
+
+    #[test]
+    fn test_parse_match_expression() {
+        let input = "match x { 1 => 10, -2 => 20, _ => 30 }";
+        let result = parse(input);
+        assert!(has_no_errors(&result));
+
+        let program = result.into_result().unwrap();
+        assert_yaml_snapshot!(program);
+    }
+
+    #[test]
+    fn test_parse_struct_literal_and_field_access() {
+        let input = "Point { x: 1, y: 2 }.x";
+        let result = parse(input);
+        assert!(has_no_errors(&result));
+
+        let program = result.into_result().unwrap();
+        assert_yaml_snapshot!(program);
+    }
+
+    #[test]
+    fn test_parse_while_statement() {
+        let input = "while x < 10 { x = x + 1; break; }";
+        let result = parse(input);
+        assert!(has_no_errors(&result));
+
+        let program = result.into_result().unwrap();
+        assert_yaml_snapshot!(program);
+    }
+
+    #[test]
+    fn test_parse_integer_literal_bases() {
+        for (input, expected) in [
+            ("1_000_000", 1_000_000),
+            ("0xFF_FF", 0xFFFF),
+            ("0o7_7", 0o77),
+            ("0b1010_1010", 0b1010_1010),
+        ] {
+            let program = parse(input).into_result().unwrap();
+            let ast::Stmt::Expr { expr } = &program.statements[0] else {
+                panic!("expected an expression statement for {input}");
+            };
+            assert_eq!(expr.kind, ast::ExprKind::IntLit(expected), "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_parse_misplaced_underscore() {
+        for input in ["0x_FF", "0b1010_", "1_"] {
+            let errors = parse(input).into_errors();
+            assert_eq!(errors.len(), 1, "input: {input}");
+            assert_eq!(
+                errors[0].reason().to_string(),
+                format!("misplaced underscore in integer literal `{input}`")
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_malformed_input_does_not_panic() {
+        // Inputs that used to panic, or are easy to get wrong, found while fuzzing the parser
+        for input in [
+            "99999999999999999999",
+            "match 1 { 99999999999999999999 => 1, _ => 0 }",
+            "enum E { A = 99999999999999999999 }",
+            "0x",
+            "fn (",
+            "1.",
+            "/*",
+            "\u{0}",
+        ] {
+            assert!(!has_no_errors(&parse(input)), "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_parse_integer_literal_i64_max() {
+        let program = parse("9223372036854775807").into_result().unwrap();
+        let Some(ast::Stmt::Expr { expr }) = program.statements.last() else {
+            panic!("expected a trailing expression");
+        };
+        assert_eq!(expr.kind, ast::ExprKind::IntLit(i64::MAX));
+    }
+
+    #[test]
+    fn test_parse_integer_literal_too_large() {
+        let input = "1 + 99999999999999999999";
+        let result = parse(input);
+        assert!(!has_no_errors(&result));
+
+        let errors = result.into_errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].reason().to_string(), "integer literal too large");
+        assert_eq!(errors[0].span().into_range(), 4..24);
+    }
+
+    #[test]
+    fn test_parse_multiline_expression() {
+        // Every form must match the same snapshot, which doesn't include spans
+        for input in [
+            "1 + 2 * (3 - 4) == 5",
+            "1 +\n    2 *\n    (3 - 4)\n    == 5",
+            "1 +\r\n    2 *\r\n    (3 - 4)\r\n    == 5",
+        ] {
+            let program = parse(input).into_result().unwrap();
+            assert_yaml_snapshot!("parse_multiline_expression", program);
+        }
+    }
+
+    #[test]
+    fn test_parse_multiline_error_span() {
+        let input = "1 +\r\n    2 +\n    99999999999999999999";
+        let errors = parse(input).into_errors();
+        assert_eq!(errors.len(), 1);
+
+        // The span points at the literal on the third line
+        let span = errors[0].span().into_range();
+        assert_eq!(&input[span.clone()], "99999999999999999999");
+        assert_eq!(input[..span.start].matches('\n').count(), 2);
+    }
+
+    #[test]
+    fn test_parse_literal_out_of_range() {
+        let input = "let x: i32 = 5000000000;";
+        let result = parse(input);
+        assert!(!has_no_errors(&result));
+
+        let errors = result.into_errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].reason().to_string(),
+            "literal 5000000000 out of range for i32"
+        );
+        assert_eq!(errors[0].span().into_range(), 13..23);
+    }
 }
@@ -0,0 +1,148 @@
+//! Conditional compilation with `#if FLAG` / `#endif`, run before lexing
+//!
+//! Directives must be alone on their line. Lines inside an `#if` whose flag
+//! isn't defined are blanked out, as are the directive lines themselves. Every
+//! byte is replaced by a space rather than removed, so spans into the
+//! preprocessed source still point at the right place in the original. Lines
+//! starting with `#[` are attributes, not directives, and are kept as they are.
+
+use crate::diagnostic::Diagnostic;
+
+/// Strip the lines of `#if` sections whose flag is not in `defines`
+pub fn preprocess(src: &str, defines: &[String]) -> Result<String, Diagnostic> {
+    let mut out = String::with_capacity(src.len());
+    // The span of each open `#if` and whether its lines are kept
+    let mut open = Vec::new();
+    let mut start = 0;
+
+    for line in src.split_inclusive('\n') {
+        let indent = line.len() - line.trim_start().len();
+        let span = start + indent..start + line.trim_end().len();
+        start += line.len();
+
+        // Attributes such as `#[inline]` are left for the parser
+        let directive = line
+            .trim()
+            .strip_prefix('#')
+            .filter(|rest| !rest.starts_with('['));
+        let Some(directive) = directive else {
+            if open.iter().all(|(_, active)| *active) {
+                out.push_str(line);
+            } else {
+                blank(&mut out, line);
+            }
+            continue;
+        };
+        let mut words = directive.split_whitespace();
+        match (words.next(), words.next(), words.next()) {
+            (Some("if"), Some(flag), None) if is_flag(flag) => {
+                open.push((span, defines.iter().any(|define| define == flag)));
+            }
+            (Some("if"), ..) => {
+                return Err(Diagnostic::error(
+                    "Expected a single flag name after `#if`",
+                    span,
+                ));
+            }
+            (Some("endif"), None, _) => {
+                if open.pop().is_none() {
+                    return Err(Diagnostic::error("`#endif` without a matching `#if`", span));
+                }
+            }
+            _ => {
+                return Err(Diagnostic::error(
+                    format!("Unknown directive `#{directive}`"),
+                    span,
+                ));
+            }
+        }
+        blank(&mut out, line);
+    }
+
+    match open.pop() {
+        Some((span, _)) => Err(Diagnostic::error("`#if` is never closed by `#endif`", span)),
+        None => Ok(out),
+    }
+}
+
+fn is_flag(flag: &str) -> bool {
+    flag.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Replace a line with spaces of the same length, keeping its line break
+fn blank(out: &mut String, line: &str) {
+    let content = line.trim_end_matches(['\r', '\n']);
+    out.extend(std::iter::repeat_n(' ', content.len()));
+    out.push_str(&line[content.len()..]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    fn defines(flags: &[&str]) -> Vec<String> {
+        flags.iter().map(|flag| flag.to_string()).collect()
+    }
+
+    #[test]
+    fn test_strip_undefined_sections() {
+        let input = indoc! {"
+            let a = 1;
+            #if FAST
+            let b = 2;
+            #if DEBUG
+            let c = 3;
+            #endif
+            #endif
+            a
+        "};
+        let output = preprocess(input, &defines(&["FAST"])).unwrap();
+        assert_eq!(output.len(), input.len());
+        assert_eq!(
+            output.lines().map(str::trim_end).collect::<Vec<_>>(),
+            ["let a = 1;", "", "let b = 2;", "", "", "", "", "a"]
+        );
+
+        let output = preprocess(input, &defines(&["DEBUG"])).unwrap();
+        assert_eq!(
+            output.lines().map(str::trim_end).collect::<Vec<_>>(),
+            ["let a = 1;", "", "", "", "", "", "", "a"]
+        );
+    }
+
+    #[test]
+    fn test_keep_attributes() {
+        let input = "#[inline]\nfn f() {}\n";
+        assert_eq!(preprocess(input, &[]).unwrap(), input);
+    }
+
+    #[test]
+    fn test_unbalanced_directives() {
+        let error = preprocess("#if FAST\n1\n", &[]).unwrap_err();
+        assert_eq!(error.message, "`#if` is never closed by `#endif`");
+        assert_eq!(error.span, 0..8);
+
+        let error = preprocess("1\n  #endif\n", &[]).unwrap_err();
+        assert_eq!(error.message, "`#endif` without a matching `#if`");
+        assert_eq!(error.span, 4..10);
+    }
+
+    #[test]
+    fn test_malformed_directives() {
+        for input in ["#if\n#endif", "#if A B\n#endif", "#if A-B\n#endif"] {
+            let error = preprocess(input, &[]).unwrap_err();
+            assert_eq!(
+                error.message, "Expected a single flag name after `#if`",
+                "input: {input}"
+            );
+        }
+        for (input, message) in [
+            ("#else\n", "Unknown directive `#else`"),
+            ("#iffy\n", "Unknown directive `#iffy`"),
+        ] {
+            let error = preprocess(input, &[]).unwrap_err();
+            assert_eq!(error.message, message, "input: {input}");
+        }
+    }
+}
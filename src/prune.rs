@@ -0,0 +1,212 @@
+//! Dead branch elimination over the AST
+//!
+//! Runs after constant folding, which turns conditions such as `1 < 2` into
+//! boolean literals.
+
+use crate::{
+    ast,
+    visit::{self, VisitorMut},
+};
+
+/// Replace each `if` whose condition is a boolean literal with the statements of
+/// the branch that is taken, or remove it when no branch is taken
+///
+/// A taken branch that declares variables, functions or types at its top level
+/// is left in place, since splicing it into the enclosing block would change
+/// what those names shadow and how long they live.
+pub fn prune_program(program: &mut ast::Program) {
+    BranchPruner.visit_program_mut(program);
+}
+
+struct BranchPruner;
+
+impl<'a> VisitorMut<'a> for BranchPruner {
+    fn visit_program_mut(&mut self, program: &mut ast::Program<'a>) {
+        visit::walk_program_mut(self, program);
+        prune_stmts(&mut program.statements);
+    }
+
+    fn visit_stmt_mut(&mut self, stmt: &mut ast::Stmt<'a>) {
+        // Prune the nested blocks first, so an inlined branch is already pruned
+        visit::walk_stmt_mut(self, stmt);
+        match stmt {
+            ast::Stmt::FnDecl { body, .. }
+            | ast::Stmt::While { body, .. }
+            | ast::Stmt::DoWhile { body, .. }
+            | ast::Stmt::Block { body } => prune_stmts(body),
+            ast::Stmt::If {
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                prune_stmts(then_branch);
+                if let Some(else_branch) = else_branch {
+                    prune_stmts(else_branch);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut ast::Expr<'a>) {
+        visit::walk_expr_mut(self, expr);
+        match &mut expr.kind {
+            ast::ExprKind::Block { body, .. } => prune_stmts(body),
+            ast::ExprKind::If {
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                prune_stmts(then_branch);
+                prune_stmts(else_branch);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn prune_stmts(stmts: &mut Vec<ast::Stmt>) {
+    let len = stmts.len();
+    *stmts = std::mem::take(stmts)
+        .into_iter()
+        .enumerate()
+        .flat_map(|(i, stmt)| prune_stmt(stmt, i + 1 == len))
+        .collect();
+}
+
+/// The statements that replace `stmt`, which is the last of its block if `is_last`
+fn prune_stmt(stmt: ast::Stmt, is_last: bool) -> Vec<ast::Stmt> {
+    let ast::Stmt::If {
+        condition,
+        then_branch,
+        else_branch,
+    } = &stmt
+    else {
+        return vec![stmt];
+    };
+    let ast::ExprKind::BoolLit(value) = condition.kind else {
+        return vec![stmt];
+    };
+    let taken = match (value, else_branch) {
+        (true, _) => then_branch,
+        (false, Some(else_branch)) => else_branch,
+        (false, None) => return Vec::new(),
+    };
+    if !can_inline(taken, is_last) {
+        return vec![stmt];
+    }
+    match stmt {
+        ast::Stmt::If {
+            then_branch,
+            else_branch,
+            ..
+        } => {
+            if value {
+                then_branch
+            } else {
+                else_branch.unwrap_or_default()
+            }
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Whether a branch can replace its `if` without changing scoping, or where
+/// its trailing expression ends up
+fn can_inline(branch: &[ast::Stmt], is_last: bool) -> bool {
+    let declares = branch.iter().any(|stmt| {
+        matches!(
+            stmt,
+            ast::Stmt::LetDecl { .. }
+                | ast::Stmt::VarDecl { .. }
+                | ast::Stmt::FnDecl { .. }
+                | ast::Stmt::ExternDecl { .. }
+                | ast::Stmt::StructDecl { .. }
+                | ast::Stmt::EnumDecl { .. }
+        )
+    });
+    // A trailing expression is only the value of the enclosing block at its end
+    let has_value = matches!(branch.last(), Some(ast::Stmt::Expr { .. }));
+    !declares && (is_last || !has_value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fold::fold_program, parser::parse};
+    use indoc::indoc;
+
+    fn prune_source(input: &str) -> ast::Program {
+        let mut program = parse(input).into_result().unwrap();
+        fold_program(&mut program);
+        prune_program(&mut program);
+        program
+    }
+
+    fn int_values(stmts: &[ast::Stmt]) -> Vec<i64> {
+        stmts
+            .iter()
+            .map(|stmt| match stmt {
+                ast::Stmt::Assign { value, .. } => match value.kind {
+                    ast::ExprKind::IntLit(value) => value,
+                    _ => panic!("expected a literal, found {value:?}"),
+                },
+                ast::Stmt::Expr { expr } => match expr.kind {
+                    ast::ExprKind::IntLit(value) => value,
+                    _ => panic!("expected a literal, found {expr:?}"),
+                },
+                stmt => panic!("unexpected statement: {stmt:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_prune_true_condition() {
+        let program = prune_source("var x = 0;\nif 1 < 2 { x = 1; } else { x = 2; }\nx = 3;");
+        assert_eq!(int_values(&program.statements[1..]), [1, 3]);
+
+        let program = prune_source("var x = 0;\nif true { x = 1; }\nx = 3;");
+        assert_eq!(int_values(&program.statements[1..]), [1, 3]);
+    }
+
+    #[test]
+    fn test_prune_false_condition() {
+        let program = prune_source("var x = 0;\nif 2 < 1 { x = 1; } else { x = 2; }\nx = 3;");
+        assert_eq!(int_values(&program.statements[1..]), [2, 3]);
+
+        let program = prune_source("var x = 0;\nif false { x = 1; }\nx = 3;");
+        assert_eq!(int_values(&program.statements[1..]), [3]);
+    }
+
+    #[test]
+    fn test_prune_trailing_value() {
+        // The value of the last `if` becomes the value of the program
+        let program = prune_source("if false { 1 } else { 2 }");
+        assert_eq!(int_values(&program.statements), [2]);
+    }
+
+    #[test]
+    fn test_prune_else_if_chain() {
+        let program = prune_source(indoc! {"
+            var x = 0;
+            if false { x = 1; } else if true { x = 2; } else { x = 3; }
+        "});
+        assert_eq!(int_values(&program.statements[1..]), [2]);
+    }
+
+    #[test]
+    fn test_prune_keeps_scoped_branches() {
+        // Inlining would let the inner `x` shadow the outer one after the `if`
+        let input = indoc! {"
+            let x = 1;
+            if true { let x = 2; }
+            x
+        "};
+        let program = prune_source(input);
+        assert!(
+            matches!(program.statements[1], ast::Stmt::If { .. }),
+            "statements were: {:?}",
+            program.statements
+        );
+    }
+}
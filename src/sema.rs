@@ -0,0 +1,2256 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    ast::{self, Span},
+    diagnostic::Diagnostic,
+    visit::{self, VisitorMut},
+};
+
+/// Type-check a program and return every diagnostic found
+pub fn check<'a>(program: &ast::Program<'a>) -> Vec<Diagnostic> {
+    check_with(program, Options::default())
+}
+
+/// Language options that change what the checker accepts
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Options {
+    /// Accept integer conditions, which are true when non-zero (as in C)
+    pub c_truthiness: bool,
+    /// Warn when a `let` or `var` shadows a variable of an enclosing scope
+    pub warn_shadow: bool,
+}
+
+/// Type-check a program with the given options
+pub fn check_with<'a>(program: &ast::Program<'a>, options: Options) -> Vec<Diagnostic> {
+    let mut checker = Checker::new(options);
+    checker.check_program(program);
+    checker.diagnostics
+}
+
+/// Type-check a program like [`check_with`], then fill in the return types
+/// of the functions that omit them with the types inferred from their bodies
+pub fn infer_with<'a>(program: &mut ast::Program<'a>, options: Options) -> Vec<Diagnostic> {
+    infer_with_types(program, options).0
+}
+
+/// Type-check and annotate a program like [`infer_with`], also returning the type
+/// determined for each expression, ordered by position
+pub fn infer_with_types<'a>(
+    program: &mut ast::Program<'a>,
+    options: Options,
+) -> (Vec<Diagnostic>, Vec<(Span, ast::Type<'a>)>) {
+    let mut checker = Checker::new(options);
+    checker.check_program(program);
+    ReturnTypeAnnotator {
+        inferred: checker.inferred,
+    }
+    .visit_program_mut(program);
+    let mut types = checker.expr_types.into_iter().collect::<Vec<_>>();
+    types.sort_by_key(|(span, _)| (span.start, span.end));
+    (checker.diagnostics, types)
+}
+
+/// Writes inferred return types back into the function declarations
+struct ReturnTypeAnnotator<'a> {
+    /// Inferred return types, keyed by the span of the function declaration
+    inferred: HashMap<Span, ast::Type<'a>>,
+}
+
+impl<'a> VisitorMut<'a> for ReturnTypeAnnotator<'a> {
+    fn visit_stmt_mut(&mut self, stmt: &mut ast::Stmt<'a>) {
+        if let ast::Stmt::FnDecl {
+            r#type: r#type @ None,
+            span,
+            ..
+        } = stmt
+        {
+            *r#type = self.inferred.get(span).copied();
+        }
+        visit::walk_stmt_mut(self, stmt);
+    }
+}
+
+/// A variable visible in the current scope
+#[derive(Clone)]
+struct VariableInfo<'a> {
+    /// The type of the variable, or `None` if it couldn't be determined
+    ty: Option<ast::Type<'a>>,
+    /// Whether the variable is mutable
+    is_mutable: bool,
+    /// Where the variable was declared
+    span: Span,
+    /// Whether the variable has been read
+    used: bool,
+}
+
+/// The signature of a declared function
+struct FunctionSig<'a> {
+    /// The parameter types
+    params: Vec<ast::Type<'a>>,
+    /// The parameter names, which named arguments refer to
+    param_names: Vec<&'a str>,
+    /// The return type, or `None` while it is being inferred from the body
+    return_type: Option<ast::Type<'a>>,
+    /// Whether the function is a `const fn`, callable from other const fns
+    is_const: bool,
+}
+
+/// The return type of the function being checked
+struct ReturnType<'a> {
+    /// The declared type, or the type of the first value returned when inferring
+    ty: Option<ast::Type<'a>>,
+    /// Whether the type is inferred from the function body
+    inferred: bool,
+}
+
+impl<'a> ReturnType<'a> {
+    fn declared(ty: ast::Type<'a>) -> Self {
+        Self {
+            ty: Some(ty),
+            inferred: false,
+        }
+    }
+}
+
+struct Checker<'a> {
+    /// Stack of variable scopes
+    scopes: Vec<HashMap<&'a str, VariableInfo<'a>>>,
+    /// Stack of function scopes, so nested functions are only visible in their enclosing function
+    fn_scopes: Vec<HashMap<&'a str, FunctionSig<'a>>>,
+    /// Top-level `var`s, which function bodies can use too
+    globals: HashMap<&'a str, VariableInfo<'a>>,
+    /// Declaration spans of the globals read inside a function
+    used_globals: HashSet<Span>,
+    /// Declared structs and their fields
+    structs: HashMap<&'a str, Vec<ast::StructField<'a>>>,
+    /// Declared enums and their variant names
+    enums: HashMap<&'a str, Vec<&'a str>>,
+    /// Return types of the functions being checked, innermost last
+    return_types: Vec<ReturnType<'a>>,
+    /// Return types inferred for functions that omit them, keyed by declaration span
+    inferred: HashMap<Span, ast::Type<'a>>,
+    /// The type of each expression checked so far, keyed by its span
+    expr_types: HashMap<Span, ast::Type<'a>>,
+    /// Number of loops enclosing the current statement
+    loop_depth: usize,
+    /// Whether the current statement is in the body of a `const fn`
+    in_const_fn: bool,
+    /// Labeled blocks enclosing the current statement, innermost last,
+    /// with the type of the value they yield once it is known
+    labels: Vec<(&'a str, Option<ast::Type<'a>>)>,
+    /// Language options
+    options: Options,
+    /// Diagnostics collected so far
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Checker<'a> {
+    fn new(options: Options) -> Self {
+        Self {
+            scopes: vec![HashMap::new()],
+            fn_scopes: vec![HashMap::new()],
+            globals: HashMap::new(),
+            used_globals: HashSet::new(),
+            structs: HashMap::new(),
+            enums: HashMap::new(),
+            return_types: Vec::new(),
+            inferred: HashMap::new(),
+            expr_types: HashMap::new(),
+            loop_depth: 0,
+            in_const_fn: false,
+            labels: Vec::new(),
+            options,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    fn error(&mut self, message: impl Into<String>, span: &Span) {
+        self.diagnostics
+            .push(Diagnostic::error(message, span.clone()));
+    }
+
+    fn check_program(&mut self, program: &ast::Program<'a>) {
+        // Top-level types and functions are visible before their declaration, as in codegen
+        for stmt in &program.statements {
+            if let ast::Stmt::EnumDecl {
+                name,
+                variants,
+                span,
+            } = stmt
+            {
+                self.declare_enum(name, variants, span);
+            }
+        }
+        for stmt in &program.statements {
+            if let ast::Stmt::StructDecl { name, fields, span } = stmt {
+                self.declare_struct(name, fields, span);
+            }
+        }
+        for stmt in &program.statements {
+            match stmt {
+                ast::Stmt::FnDecl {
+                    is_const,
+                    name,
+                    params,
+                    r#type,
+                    span,
+                    ..
+                } => self.declare_fn(name, params, *r#type, *is_const, span),
+                ast::Stmt::ExternDecl {
+                    name,
+                    params,
+                    r#type,
+                    span,
+                } => self.declare_fn(name, params, Some(*r#type), false, span),
+                _ => {}
+            }
+        }
+
+        // The top-level statements make up the body of `main`, which returns i32
+        self.return_types.push(ReturnType::declared(ast::Type::I32));
+        self.check_block(&program.statements, true);
+        self.return_types.pop();
+    }
+
+    /// Check a list of statements in a new scope.
+    /// `hoisted` is set for the top level, whose declarations were already registered.
+    fn check_block(&mut self, stmts: &[ast::Stmt<'a>], hoisted: bool) {
+        self.push_scope();
+        for stmt in stmts {
+            self.check_stmt(stmt, hoisted);
+        }
+        self.pop_scope();
+    }
+
+    fn push_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+        self.fn_scopes.push(HashMap::new());
+    }
+
+    /// Leave the innermost scope, warning about its unused variables
+    fn pop_scope(&mut self) {
+        self.fn_scopes.pop();
+
+        let mut unused = self
+            .scopes
+            .pop()
+            .unwrap()
+            .into_iter()
+            .filter(|(name, var)| {
+                !var.used && !self.used_globals.contains(&var.span) && !name.starts_with('_')
+            })
+            .collect::<Vec<_>>();
+        unused.sort_by_key(|(_, var)| var.span.start);
+        for (name, var) in unused {
+            self.diagnostics.push(Diagnostic::warning(
+                format!("unused variable '{name}'"),
+                var.span,
+            ));
+        }
+    }
+
+    fn check_stmt(&mut self, stmt: &ast::Stmt<'a>, hoisted: bool) {
+        if self.in_const_fn {
+            self.check_const_stmt(stmt);
+        }
+        match stmt {
+            ast::Stmt::FnDecl {
+                is_const,
+                name,
+                params,
+                r#type,
+                body,
+                span,
+                ..
+            } => {
+                if !hoisted {
+                    self.declare_fn(name, params, *r#type, *is_const, span);
+                }
+                if *is_const {
+                    let types = params.iter().map(|param| param.r#type).chain(*r#type);
+                    if types
+                        .map(|ty| self.resolve_type(ty))
+                        .any(|ty| !matches!(ty, ast::Type::I32 | ast::Type::Bool))
+                    {
+                        self.error(
+                            "A const fn can only take and return i32 and bool values",
+                            span,
+                        );
+                    }
+                }
+
+                // Function bodies can't see the locals of the enclosing function, only the globals.
+                // The parameters get a scope of their own, so that they can shadow a global
+                let globals = self
+                    .globals
+                    .iter()
+                    .map(|(name, var)| (*name, var.clone()))
+                    .collect();
+                let outer_scopes =
+                    std::mem::replace(&mut self.scopes, vec![globals, HashMap::new()]);
+                let outer_loop_depth = std::mem::take(&mut self.loop_depth);
+                let outer_in_const_fn = std::mem::replace(&mut self.in_const_fn, *is_const);
+                let outer_labels = std::mem::take(&mut self.labels);
+                for param in params {
+                    let ty = self.resolve_type(param.r#type);
+                    self.declare_var(param.name, Some(ty), false, span);
+                }
+                self.return_types.push(match r#type {
+                    Some(ty) => ReturnType::declared(self.resolve_type(*ty)),
+                    None => ReturnType {
+                        ty: None,
+                        inferred: true,
+                    },
+                });
+                self.check_block(body, false);
+                let return_type = self.return_types.pop().unwrap();
+                if return_type.inferred {
+                    // A function that never returns a value returns void
+                    let ty = return_type.ty.unwrap_or(ast::Type::Void);
+                    if let Some(sig) = self.resolve_fn_mut(name) {
+                        sig.return_type = Some(ty);
+                    }
+                    self.inferred.insert(span.clone(), ty);
+                }
+                self.loop_depth = outer_loop_depth;
+                self.in_const_fn = outer_in_const_fn;
+                self.labels = outer_labels;
+                let scopes = std::mem::replace(&mut self.scopes, outer_scopes);
+                self.used_globals.extend(
+                    scopes[0]
+                        .values()
+                        .filter(|var| var.used)
+                        .map(|var| var.span.clone()),
+                );
+            }
+            ast::Stmt::ExternDecl {
+                name,
+                params,
+                r#type,
+                span,
+            } => {
+                if !hoisted {
+                    self.declare_fn(name, params, Some(*r#type), false, span);
+                }
+            }
+            ast::Stmt::StructDecl { name, fields, span } => {
+                if !hoisted {
+                    self.declare_struct(name, fields, span);
+                }
+            }
+            ast::Stmt::EnumDecl {
+                name,
+                variants,
+                span,
+            } => {
+                if !hoisted {
+                    self.declare_enum(name, variants, span);
+                }
+            }
+            ast::Stmt::LetDecl {
+                name,
+                r#type: None,
+                value: Some(value),
+                ..
+            } if *name == "_" => {
+                // `let _ = expr;` discards the value, which may be void
+                self.check_expr(value);
+            }
+            ast::Stmt::LetDecl {
+                is_const,
+                name,
+                r#type,
+                value,
+                span,
+            } => {
+                if *is_const {
+                    if !hoisted {
+                        self.error("A const item can only be declared at the top level", span);
+                    }
+                    if r#type.is_some_and(|ty| {
+                        !matches!(self.resolve_type(ty), ast::Type::I32 | ast::Type::Bool)
+                    }) {
+                        self.error("A const item can only have type i32 or bool", span);
+                    }
+                }
+                let Some(value) = value else {
+                    self.error("Initial value required for let declaration", span);
+                    let ty = r#type.map(|ty| self.resolve_type(ty));
+                    self.warn_shadowing(name, span);
+                    self.declare_var(name, ty, false, span);
+                    return;
+                };
+                let ty = self.check_declaration("let", *r#type, value, span);
+                self.warn_shadowing(name, span);
+                self.declare_var(name, ty, false, span);
+            }
+            ast::Stmt::VarDecl {
+                name,
+                r#type,
+                value,
+                span,
+            } => {
+                let ty = match (value, r#type) {
+                    (Some(value), _) => self.check_declaration("var", *r#type, value, span),
+                    (None, Some(ast::Type::Void)) => {
+                        self.error("cannot declare a variable of type void", span);
+                        None
+                    }
+                    (None, Some(ty)) => {
+                        let ty = self.resolve_type(*ty);
+                        if self.check_variable_type(ty, span)
+                            && matches!(ty, ast::Type::Struct(_) | ast::Type::Enum(_))
+                        {
+                            self.error(format!("Unsupported type for default value: {ty}"), span);
+                        }
+                        Some(ty)
+                    }
+                    (None, None) => {
+                        self.error(
+                            "Type annotation required for var declaration without initializer",
+                            span,
+                        );
+                        None
+                    }
+                };
+                self.warn_shadowing(name, span);
+                self.declare_var(name, ty, true, span);
+                if hoisted {
+                    self.globals.insert(
+                        name,
+                        VariableInfo {
+                            ty,
+                            is_mutable: true,
+                            span: span.clone(),
+                            used: false,
+                        },
+                    );
+                }
+            }
+            ast::Stmt::Assign { name, value, span } => {
+                let var = self.resolve_var(name).map(|var| (var.ty, var.is_mutable));
+                // A literal takes the type of the variable, like in an annotated declaration
+                let value_ty = match var {
+                    Some((Some(var_ty), _)) => self.check_expr_expecting(value, var_ty),
+                    _ => self.check_expr(value),
+                };
+                let Some((var_ty, is_mutable)) = var else {
+                    self.error(format!("Variable '{name}' not found"), span);
+                    return;
+                };
+                if !is_mutable {
+                    self.error(
+                        format!("Cannot assign to immutable variable '{name}'"),
+                        span,
+                    );
+                }
+                if let (Some(var_ty), Some(value_ty)) = (var_ty, value_ty) {
+                    if is_narrowing(value_ty, var_ty) {
+                        self.error("possible truncation; use an explicit cast", &value.span);
+                    } else if var_ty != value_ty {
+                        self.error(
+                            format!(
+                                "Type mismatch in assignment to variable '{name}': expected {var_ty}, found {value_ty}"
+                            ),
+                            &value.span,
+                        );
+                    }
+                }
+            }
+            ast::Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.check_condition(condition);
+                self.check_block(then_branch, false);
+                if let Some(else_branch) = else_branch {
+                    self.check_block(else_branch, false);
+                }
+            }
+            ast::Stmt::While { condition, body } => {
+                self.check_condition(condition);
+                self.loop_depth += 1;
+                self.check_block(body, false);
+                self.loop_depth -= 1;
+            }
+            ast::Stmt::DoWhile { body, condition } => {
+                self.loop_depth += 1;
+                self.check_block(body, false);
+                self.loop_depth -= 1;
+                self.check_condition(condition);
+            }
+            ast::Stmt::Break {
+                label: Some(label),
+                value,
+                span,
+            } => {
+                let value_ty = value.as_ref().and_then(|value| self.check_expr(value));
+                let Some(index) = self.labels.iter().rposition(|(name, _)| name == label) else {
+                    self.error(format!("Unknown label '{label}"), span);
+                    return;
+                };
+                if let (Some(value), Some(value_ty)) = (value, value_ty) {
+                    self.yield_from_block(index, value_ty, &value.span);
+                }
+            }
+            ast::Stmt::Break { span, .. } => {
+                if self.loop_depth == 0 {
+                    self.error("`break` outside of a loop", span);
+                }
+            }
+            ast::Stmt::Continue { span } => {
+                if self.loop_depth == 0 {
+                    self.error("`continue` outside of a loop", span);
+                }
+            }
+            ast::Stmt::Block { body } => self.check_block(body, false),
+            ast::Stmt::Return { expr, span } => match expr {
+                Some(expr) => self.check_return_value(expr),
+                None => {
+                    let Some(return_type) = self.return_types.last_mut() else {
+                        return;
+                    };
+                    let inferred = return_type.inferred;
+                    match return_type.ty {
+                        None => return_type.ty = Some(ast::Type::Void),
+                        Some(ast::Type::Void) => {}
+                        Some(expected) if inferred => self.error(
+                            format!("Conflicting return types: expected {expected}, found void"),
+                            span,
+                        ),
+                        Some(expected) => self.error(
+                            format!("Missing return value in function returning {expected}"),
+                            span,
+                        ),
+                    }
+                }
+            },
+            ast::Stmt::ExprStmt { expr } => {
+                self.check_expr(expr);
+            }
+            ast::Stmt::Expr { expr } => self.check_return_value(expr),
+        }
+    }
+
+    /// Record the type of a value yielded by the labeled block at `index`,
+    /// reporting an error if it differs from the values yielded before
+    fn yield_from_block(&mut self, index: usize, ty: ast::Type<'a>, span: &Span) {
+        let (label, expected) = self.labels[index];
+        match expected {
+            Some(expected) if expected != ty => self.error(
+                format!(
+                    "Labeled block '{label} yields different types: expected {expected}, found {ty}"
+                ),
+                span,
+            ),
+            Some(_) => {}
+            None => self.labels[index].1 = Some(ty),
+        }
+    }
+
+    /// Check a branch of an if expression in a new scope and return the type of its trailing value
+    fn check_branch_value(
+        &mut self,
+        branch_name: &str,
+        body: &[ast::Stmt<'a>],
+        span: &Span,
+    ) -> Option<ast::Type<'a>> {
+        self.push_scope();
+        let ty = match body.split_last() {
+            Some((ast::Stmt::Expr { expr: value }, stmts)) => {
+                for stmt in stmts {
+                    self.check_stmt(stmt, false);
+                }
+                self.check_expr(value)
+            }
+            _ => {
+                for stmt in body {
+                    self.check_stmt(stmt, false);
+                }
+                self.error(format!("{branch_name} branch must end with a value"), span);
+                None
+            }
+        };
+        self.pop_scope();
+        ty
+    }
+
+    /// Check the initializer of a let/var declaration against its annotation
+    /// and return the type of the declared variable
+    fn check_declaration(
+        &mut self,
+        kind: &str,
+        annotation: Option<ast::Type<'a>>,
+        value: &ast::Expr<'a>,
+        span: &Span,
+    ) -> Option<ast::Type<'a>> {
+        let annotation = annotation.map(|ty| self.resolve_type(ty));
+        let value_ty = match annotation {
+            Some(ty) => self.check_expr_expecting(value, ty),
+            None => self.check_expr(value),
+        };
+        let ty = match annotation {
+            Some(ty) => ty,
+            None => {
+                if value_ty == Some(ast::Type::Void) {
+                    self.error("cannot declare a variable of type void", span);
+                    return None;
+                }
+                return value_ty;
+            }
+        };
+        if ty == ast::Type::Void {
+            self.error("cannot declare a variable of type void", span);
+            return None;
+        }
+        if self.check_variable_type(ty, &value.span) {
+            if let Some(value_ty) = value_ty {
+                if is_narrowing(value_ty, ty) {
+                    self.error("possible truncation; use an explicit cast", &value.span);
+                } else if value_ty != ty {
+                    self.error(
+                        format!(
+                            "Type mismatch in {kind} declaration: expected {ty}, found {value_ty}"
+                        ),
+                        &value.span,
+                    );
+                }
+            }
+        }
+        Some(ty)
+    }
+
+    /// Check a value that is returned from the enclosing function
+    fn check_return_value(&mut self, expr: &ast::Expr<'a>) {
+        // Only a declared return type is a context for literals, an inferred one is fixed by them
+        let declared = self
+            .return_types
+            .last()
+            .filter(|return_type| !return_type.inferred)
+            .and_then(|return_type| return_type.ty);
+        let value_ty = match declared {
+            Some(ty) => self.check_expr_expecting(expr, ty),
+            None => self.check_expr(expr),
+        };
+        let (Some(value_ty), Some(return_type)) = (value_ty, self.return_types.last_mut()) else {
+            return;
+        };
+        let Some(expected) = return_type.ty else {
+            // The first value returned from an unannotated function fixes its return type
+            return_type.ty = Some(value_ty);
+            return;
+        };
+        if return_type.inferred {
+            if value_ty != expected {
+                self.error(
+                    format!("Conflicting return types: expected {expected}, found {value_ty}"),
+                    &expr.span,
+                );
+            }
+            return;
+        }
+        // Booleans are zero-extended when returned from an integer function
+        let coercible =
+            value_ty == ast::Type::Bool && matches!(expected, ast::Type::I32 | ast::Type::I64);
+        if value_ty != expected && !coercible {
+            self.error(
+                format!("Type mismatch in return value: expected {expected}, found {value_ty}"),
+                &expr.span,
+            );
+        }
+    }
+
+    /// Report a statement that a `const fn` can't contain. Its expressions are
+    /// checked by [`Self::check_expr`]
+    fn check_const_stmt(&mut self, stmt: &ast::Stmt<'a>) {
+        match stmt {
+            ast::Stmt::LetDecl { .. }
+            | ast::Stmt::If { .. }
+            | ast::Stmt::Block { .. }
+            | ast::Stmt::Return { .. }
+            | ast::Stmt::ExprStmt { .. }
+            | ast::Stmt::Expr { .. } => {}
+            ast::Stmt::While { condition, .. } | ast::Stmt::DoWhile { condition, .. } => {
+                self.error(CONST_FN_SUBSET, &condition.span)
+            }
+            ast::Stmt::FnDecl { span, .. }
+            | ast::Stmt::ExternDecl { span, .. }
+            | ast::Stmt::StructDecl { span, .. }
+            | ast::Stmt::EnumDecl { span, .. }
+            | ast::Stmt::VarDecl { span, .. }
+            | ast::Stmt::Assign { span, .. }
+            | ast::Stmt::Break { span, .. }
+            | ast::Stmt::Continue { span } => self.error(CONST_FN_SUBSET, span),
+        }
+    }
+
+    fn check_condition(&mut self, condition: &ast::Expr<'a>) {
+        if let Some(ty) = self.check_expr(condition) {
+            let truthy = self.options.c_truthiness && is_integer(ty);
+            if ty != ast::Type::Bool && !truthy {
+                self.error(
+                    format!("Condition must be a boolean value, found {ty}"),
+                    &condition.span,
+                );
+            }
+        }
+    }
+
+    /// Infer the type of an expression whose context expects `expected`, so that
+    /// a bare integer literal takes that type instead of defaulting to i32
+    fn check_expr_expecting(
+        &mut self,
+        expr: &ast::Expr<'a>,
+        expected: ast::Type<'a>,
+    ) -> Option<ast::Type<'a>> {
+        self.literal_type(expr, expected)
+            .or_else(|| self.check_expr(expr))
+    }
+
+    /// The type of `expr` if it is an integer literal in a context expecting the
+    /// integer type `expected`, reporting literals that don't fit in it
+    fn literal_type(
+        &mut self,
+        expr: &ast::Expr<'a>,
+        expected: ast::Type<'a>,
+    ) -> Option<ast::Type<'a>> {
+        let value = ast::int_literal_value(expr).filter(|_| is_integer(expected))?;
+        if !ast::int_literal_fits(value, expected) {
+            self.error(
+                format!("literal {value} out of range for {expected}"),
+                &expr.span,
+            );
+        }
+        self.expr_types.insert(expr.span.clone(), expected);
+        Some(expected)
+    }
+
+    /// Infer the type of an expression, reporting any errors inside it.
+    /// Returns `None` if the type couldn't be determined.
+    fn check_expr(&mut self, expr: &ast::Expr<'a>) -> Option<ast::Type<'a>> {
+        let ty = self.infer_expr(expr);
+        if let Some(ty) = ty {
+            self.expr_types.insert(expr.span.clone(), ty);
+        }
+        ty
+    }
+
+    /// The type of an expression, for [`Self::check_expr`] to record
+    fn infer_expr(&mut self, expr: &ast::Expr<'a>) -> Option<ast::Type<'a>> {
+        if self.in_const_fn
+            && !matches!(
+                expr.kind,
+                ast::ExprKind::IntLit(_)
+                    | ast::ExprKind::BoolLit(_)
+                    | ast::ExprKind::BinOp { .. }
+                    | ast::ExprKind::UnaryOp { .. }
+                    | ast::ExprKind::FnCall { .. }
+                    | ast::ExprKind::VarRef { .. }
+                    | ast::ExprKind::If { .. }
+            )
+        {
+            self.error(CONST_FN_SUBSET, &expr.span);
+        }
+        match &expr.kind {
+            ast::ExprKind::IntLit(value) => Some(ast::int_literal_type(i128::from(*value))),
+            ast::ExprKind::FloatLit(_) => Some(ast::Type::F64),
+            ast::ExprKind::BoolLit(_) => Some(ast::Type::Bool),
+            ast::ExprKind::StrLit(_) => Some(ast::Type::String),
+            ast::ExprKind::BinOp { lhs, op, rhs } => {
+                let (lhs_ty, rhs_ty) = self.check_operands(lhs, rhs);
+                let (lhs_ty, rhs_ty) = (lhs_ty?, rhs_ty?);
+                let (operands_ok, result) = match op {
+                    ast::BinOp::Add | ast::BinOp::Sub | ast::BinOp::Mul | ast::BinOp::Div => {
+                        (is_numeric(lhs_ty), lhs_ty)
+                    }
+                    ast::BinOp::Equal | ast::BinOp::NotEqual => (
+                        is_numeric(lhs_ty)
+                            || matches!(lhs_ty, ast::Type::Bool | ast::Type::Enum(_)),
+                        ast::Type::Bool,
+                    ),
+                    ast::BinOp::LessThan
+                    | ast::BinOp::LessThanOrEqual
+                    | ast::BinOp::GreaterThan
+                    | ast::BinOp::GreaterThanOrEqual => (is_numeric(lhs_ty), ast::Type::Bool),
+                    ast::BinOp::And | ast::BinOp::Or | ast::BinOp::Xor => {
+                        (lhs_ty == ast::Type::Bool, ast::Type::Bool)
+                    }
+                };
+                let mut diagnostic = if lhs_ty != rhs_ty {
+                    Diagnostic::error(
+                        format!("Type mismatch in binary operation: {lhs_ty} and {rhs_ty}"),
+                        expr.span.clone(),
+                    )
+                } else if !operands_ok {
+                    Diagnostic::error(
+                        format!("Operator {op:?} is not supported for {lhs_ty}"),
+                        expr.span.clone(),
+                    )
+                } else {
+                    return Some(result);
+                };
+                // Booleans never count as 0 or 1 implicitly, but can be cast explicitly
+                let arithmetic = matches!(
+                    op,
+                    ast::BinOp::Add | ast::BinOp::Sub | ast::BinOp::Mul | ast::BinOp::Div
+                );
+                for (operand, ty) in [(lhs, lhs_ty), (rhs, rhs_ty)] {
+                    if arithmetic && ty == ast::Type::Bool {
+                        diagnostic = diagnostic
+                            .with_label(operand.span.clone(), "use `as i32` to count it as 0 or 1");
+                    }
+                }
+                self.diagnostics.push(diagnostic);
+                Some(result)
+            }
+            ast::ExprKind::UnaryOp { op, expr: operand } => {
+                // A negated literal such as `-2147483648` is typed by its value
+                if let (ast::UnaryOp::Neg, ast::ExprKind::IntLit(value)) = (op, &operand.kind) {
+                    return Some(ast::int_literal_type(-i128::from(*value)));
+                }
+                let ty = self.check_expr(operand)?;
+                let operand_ok = match op {
+                    ast::UnaryOp::Neg => is_numeric(ty),
+                    ast::UnaryOp::Not => ty == ast::Type::Bool,
+                    ast::UnaryOp::BitNot => is_integer(ty),
+                };
+                if !operand_ok {
+                    self.error(
+                        format!("Operator {op:?} is not supported for {ty}"),
+                        &expr.span,
+                    );
+                }
+                Some(ty)
+            }
+            ast::ExprKind::FnCall {
+                name,
+                args,
+                arg_names,
+            } => {
+                let arg_types = args
+                    .iter()
+                    .map(|arg| self.check_expr(arg))
+                    .collect::<Vec<_>>();
+
+                let Some(sig) = self.resolve_fn(name) else {
+                    let ty = self.check_builtin_call(name, &arg_types, arg_names, &expr.span);
+                    if self.in_const_fn && ty.is_some() {
+                        self.error(
+                            format!("Cannot call non-const function '{name}' in a const fn"),
+                            &expr.span,
+                        );
+                    }
+                    return ty;
+                };
+                let params = sig.params.clone();
+                let param_names = sig.param_names.clone();
+                let is_const = sig.is_const;
+                let Some(return_type) = sig.return_type else {
+                    self.error(
+                        format!(
+                            "Cannot call '{name}' before its return type is inferred; add an explicit `-> type`"
+                        ),
+                        &expr.span,
+                    );
+                    return None;
+                };
+                if self.in_const_fn && !is_const {
+                    self.error(
+                        format!("Cannot call non-const function '{name}' in a const fn"),
+                        &expr.span,
+                    );
+                }
+
+                if params.len() != args.len() {
+                    self.error(
+                        format!(
+                            "Function '{name}' expects {} arguments, but {} were given",
+                            params.len(),
+                            args.len()
+                        ),
+                        &expr.span,
+                    );
+                    return Some(return_type);
+                }
+
+                // The parameter each argument is passed to, by position or by name
+                let positional = args.len() - arg_names.len();
+                let mut arg_params: Vec<_> = (0..positional).map(Some).collect();
+                for (arg, arg_name) in args[positional..].iter().zip(arg_names) {
+                    let param = param_names.iter().position(|param| param == arg_name);
+                    match param {
+                        None => self.error(
+                            format!("Function '{name}' has no parameter named '{arg_name}'"),
+                            &arg.span,
+                        ),
+                        Some(param) if arg_params.contains(&Some(param)) => self.error(
+                            format!("Argument '{arg_name}' to '{name}' is given more than once"),
+                            &arg.span,
+                        ),
+                        Some(_) => {}
+                    }
+                    arg_params.push(param);
+                }
+
+                for ((arg, arg_ty), param) in args.iter().zip(arg_types).zip(arg_params) {
+                    if let (Some(arg_ty), Some(param)) = (arg_ty, param) {
+                        let param_ty = params[param];
+                        let arg_ty = self.literal_type(arg, param_ty).unwrap_or(arg_ty);
+                        if arg_ty != param_ty {
+                            self.error(
+                                format!(
+                                    "Type mismatch in argument to '{name}': expected {param_ty}, found {arg_ty}"
+                                ),
+                                &arg.span,
+                            );
+                        }
+                    }
+                }
+                Some(return_type)
+            }
+            ast::ExprKind::VarRef { name } => match self.use_var(name) {
+                Some(var) => var.ty,
+                None => {
+                    self.error(format!("Variable '{name}' not found"), &expr.span);
+                    None
+                }
+            },
+            ast::ExprKind::StructLit { name, fields } => {
+                let Some(declared) = self.structs.get(name).cloned() else {
+                    self.error(format!("Struct '{name}' not found"), &expr.span);
+                    for field in fields {
+                        self.check_expr(&field.value);
+                    }
+                    return None;
+                };
+
+                for (i, field) in fields.iter().enumerate() {
+                    let value_ty = self.check_expr(&field.value);
+                    if fields[..i].iter().any(|other| other.name == field.name) {
+                        self.error(
+                            format!(
+                                "Field '{}' specified more than once in struct literal '{name}'",
+                                field.name
+                            ),
+                            &field.value.span,
+                        );
+                        continue;
+                    }
+                    let Some(declared_field) = declared.iter().find(|f| f.name == field.name)
+                    else {
+                        self.error(
+                            format!("Struct '{name}' has no field '{}'", field.name),
+                            &field.value.span,
+                        );
+                        continue;
+                    };
+                    if let Some(value_ty) = value_ty {
+                        if value_ty != declared_field.r#type {
+                            self.error(
+                                format!(
+                                    "Type mismatch for field '{}' in struct literal '{name}': expected {}, found {value_ty}",
+                                    field.name, declared_field.r#type
+                                ),
+                                &field.value.span,
+                            );
+                        }
+                    }
+                }
+                for declared_field in &declared {
+                    if !fields.iter().any(|field| field.name == declared_field.name) {
+                        self.error(
+                            format!(
+                                "Missing field '{}' in struct literal '{name}'",
+                                declared_field.name
+                            ),
+                            &expr.span,
+                        );
+                    }
+                }
+                Some(ast::Type::Struct(*name))
+            }
+            ast::ExprKind::FieldAccess {
+                expr: object,
+                field,
+            } => {
+                let ty = self.check_expr(object)?;
+                let ast::Type::Struct(struct_name) = ty else {
+                    self.error(
+                        format!("Field access on a non-struct value of type {ty}"),
+                        &expr.span,
+                    );
+                    return None;
+                };
+                let field_ty = self
+                    .structs
+                    .get(struct_name)?
+                    .iter()
+                    .find(|f| f.name == *field)
+                    .map(|f| f.r#type);
+                if field_ty.is_none() {
+                    self.error(
+                        format!("Struct '{struct_name}' has no field '{field}'"),
+                        &expr.span,
+                    );
+                }
+                field_ty
+            }
+            ast::ExprKind::EnumVariant { name, variant } => {
+                let Some(variants) = self.enums.get(name) else {
+                    self.error(format!("Enum '{name}' not found"), &expr.span);
+                    return None;
+                };
+                if !variants.contains(variant) {
+                    self.error(
+                        format!("Enum '{name}' has no variant '{variant}'"),
+                        &expr.span,
+                    );
+                }
+                Some(ast::Type::Enum(*name))
+            }
+            ast::ExprKind::Cast {
+                expr: operand,
+                r#type,
+            } => {
+                let target = self.resolve_type(*r#type);
+                let ty = self.check_expr(operand)?;
+                let castable = is_numeric(ty) || matches!(ty, ast::Type::Bool | ast::Type::Enum(_));
+                if !castable || !is_numeric(target) {
+                    self.error(format!("Cannot cast {ty} to {target}"), &expr.span);
+                }
+                Some(target)
+            }
+            ast::ExprKind::SizeOf { r#type } => {
+                let ty = self.resolve_type(*r#type);
+                if ty == ast::Type::Void {
+                    self.error("Cannot take the size of void", &expr.span);
+                } else {
+                    self.check_variable_type(ty, &expr.span);
+                }
+                Some(ast::Type::I32)
+            }
+            ast::ExprKind::Block { label, body } => {
+                self.labels.push((*label, None));
+                self.push_scope();
+                let index = self.labels.len() - 1;
+                match body.split_last() {
+                    Some((ast::Stmt::Expr { expr: value }, stmts)) => {
+                        for stmt in stmts {
+                            self.check_stmt(stmt, false);
+                        }
+                        if let Some(ty) = self.check_expr(value) {
+                            self.yield_from_block(index, ty, &value.span);
+                        }
+                    }
+                    _ => {
+                        for stmt in body {
+                            self.check_stmt(stmt, false);
+                        }
+                        self.error(
+                            format!("Labeled block '{label} must end with a value"),
+                            &expr.span,
+                        );
+                    }
+                }
+                self.pop_scope();
+                self.labels.pop().unwrap().1
+            }
+            ast::ExprKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.check_condition(condition);
+                let then_ty = self.check_branch_value("if", then_branch, &expr.span);
+                let else_ty = self.check_branch_value("else", else_branch, &expr.span);
+                match (then_ty, else_ty) {
+                    (Some(then_ty), Some(else_ty)) if then_ty != else_ty => {
+                        self.error(
+                            format!("if and else have incompatible types: {then_ty} vs {else_ty}"),
+                            &expr.span,
+                        );
+                        None
+                    }
+                    (ty, _) => ty,
+                }
+            }
+            ast::ExprKind::Sequence { exprs } => {
+                let mut ty = None;
+                for expr in exprs {
+                    ty = self.check_expr(expr);
+                }
+                ty
+            }
+            ast::ExprKind::IncDec { op, name, .. } => {
+                let Some(var) = self.use_var(name) else {
+                    self.error(format!("Variable '{name}' not found"), &expr.span);
+                    return None;
+                };
+                let (ty, is_mutable) = (var.ty, var.is_mutable);
+                if !is_mutable {
+                    self.error(
+                        format!("Cannot assign to immutable variable '{name}'"),
+                        &expr.span,
+                    );
+                }
+                let ty = ty?;
+                if !is_integer(ty) {
+                    self.error(
+                        format!("Operator {op:?} is not supported for {ty}"),
+                        &expr.span,
+                    );
+                }
+                Some(ty)
+            }
+            ast::ExprKind::Match { scrutinee, arms } => {
+                if let Some(ty) = self.check_expr(scrutinee) {
+                    if !is_integer(ty) {
+                        self.error(
+                            format!("Match scrutinee must be an integer value, found {ty}"),
+                            &scrutinee.span,
+                        );
+                    } else {
+                        // A pattern the scrutinee can't hold would be truncated to a wrong case
+                        for arm in arms {
+                            if let ast::Pattern::IntLit(value) = arm.pattern {
+                                if !ast::int_literal_fits(value.into(), ty) {
+                                    self.error(
+                                        format!("pattern {value} out of range for {ty}"),
+                                        &arm.pattern_span,
+                                    );
+                                }
+                            }
+                        }
+                    }
+                }
+                if !arms.iter().any(|arm| arm.pattern == ast::Pattern::Wildcard) {
+                    self.error("Match on integers requires a `_` arm", &expr.span);
+                }
+
+                // All arms must agree with the type of the first arm whose type is known
+                let mut result = None;
+                for arm in arms {
+                    let Some(arm_ty) = self.check_expr(&arm.value) else {
+                        continue;
+                    };
+                    match result {
+                        None => result = Some(arm_ty),
+                        Some(ty) if ty != arm_ty => self.error(
+                            format!(
+                                "Match arms have different types: expected {ty}, found {arm_ty}"
+                            ),
+                            &arm.value.span,
+                        ),
+                        Some(_) => {}
+                    }
+                }
+                result
+            }
+        }
+    }
+
+    /// Check the operands of a binary operation. A literal operand takes the type
+    /// of the other operand, so that `n + 1` works for an `i64` `n`
+    fn check_operands(
+        &mut self,
+        lhs: &ast::Expr<'a>,
+        rhs: &ast::Expr<'a>,
+    ) -> (Option<ast::Type<'a>>, Option<ast::Type<'a>>) {
+        let is_literal = |expr| ast::int_literal_value(expr).is_some();
+        match (is_literal(lhs), is_literal(rhs)) {
+            (true, false) => {
+                let rhs_ty = self.check_expr(rhs);
+                let lhs_ty = match rhs_ty {
+                    Some(ty) => self.check_expr_expecting(lhs, ty),
+                    None => self.check_expr(lhs),
+                };
+                (lhs_ty, rhs_ty)
+            }
+            (false, true) => {
+                let lhs_ty = self.check_expr(lhs);
+                let rhs_ty = match lhs_ty {
+                    Some(ty) => self.check_expr_expecting(rhs, ty),
+                    None => self.check_expr(rhs),
+                };
+                (lhs_ty, rhs_ty)
+            }
+            _ => (self.check_expr(lhs), self.check_expr(rhs)),
+        }
+    }
+
+    /// Check a call to a builtin function.
+    /// Reports an unknown function if `name` is not a builtin.
+    fn check_builtin_call(
+        &mut self,
+        name: &str,
+        arg_types: &[Option<ast::Type<'a>>],
+        arg_names: &[&str],
+        span: &Span,
+    ) -> Option<ast::Type<'a>> {
+        match name {
+            "abs" | "sqrt" | "pow" | "min" | "max" | "assert" if !arg_names.is_empty() => {
+                self.error(
+                    format!("Builtin '{name}' does not take named arguments"),
+                    span,
+                );
+                None
+            }
+            "abs" | "sqrt" => {
+                let [arg] = arg_types else {
+                    self.error(
+                        format!(
+                            "Builtin '{name}' expects 1 argument, but {} were given",
+                            arg_types.len()
+                        ),
+                        span,
+                    );
+                    return None;
+                };
+                let ty = (*arg)?;
+                let valid = match name {
+                    "abs" => is_numeric(ty),
+                    _ => matches!(ty, ast::Type::F32 | ast::Type::F64),
+                };
+                if !valid {
+                    self.error(
+                        format!("Builtin '{name}' does not support an argument of type {ty}"),
+                        span,
+                    );
+                }
+                Some(ty)
+            }
+            "pow" => {
+                let [base, exp] = arg_types else {
+                    self.error(
+                        format!(
+                            "Builtin 'pow' expects 2 arguments, but {} were given",
+                            arg_types.len()
+                        ),
+                        span,
+                    );
+                    return None;
+                };
+                let (base, exp) = ((*base)?, (*exp)?);
+                let valid = match base {
+                    ast::Type::F32 | ast::Type::F64 => exp == base || exp == ast::Type::I32,
+                    ast::Type::I32 | ast::Type::I64 => exp == base,
+                    _ => false,
+                };
+                if !valid {
+                    self.error(
+                        format!(
+                            "Builtin 'pow' does not support arguments of type {base} and {exp}"
+                        ),
+                        span,
+                    );
+                }
+                Some(base)
+            }
+            "min" | "max" => {
+                let [lhs, rhs] = arg_types else {
+                    self.error(
+                        format!(
+                            "Builtin '{name}' expects 2 arguments, but {} were given",
+                            arg_types.len()
+                        ),
+                        span,
+                    );
+                    return None;
+                };
+                let (lhs, rhs) = ((*lhs)?, (*rhs)?);
+                if lhs != rhs || !is_numeric(lhs) {
+                    self.error(
+                        format!(
+                            "Builtin '{name}' does not support arguments of type {lhs} and {rhs}"
+                        ),
+                        span,
+                    );
+                }
+                Some(lhs)
+            }
+            "assert" => {
+                let [condition] = arg_types else {
+                    self.error(
+                        format!(
+                            "Builtin 'assert' expects 1 argument, but {} were given",
+                            arg_types.len()
+                        ),
+                        span,
+                    );
+                    return Some(ast::Type::Void);
+                };
+                if let Some(ty) = *condition {
+                    let truthy = self.options.c_truthiness && is_integer(ty);
+                    if ty != ast::Type::Bool && !truthy {
+                        self.error(
+                            format!("Condition must be a boolean value, found {ty}"),
+                            span,
+                        );
+                    }
+                }
+                Some(ast::Type::Void)
+            }
+            _ => {
+                self.error(format!("Function '{name}' not found"), span);
+                None
+            }
+        }
+    }
+
+    fn declare_var(
+        &mut self,
+        name: &'a str,
+        ty: Option<ast::Type<'a>>,
+        is_mutable: bool,
+        span: &Span,
+    ) {
+        let scope = self.scopes.last_mut().unwrap();
+        if let Some(previous) = scope.get(name) {
+            let diagnostic = Diagnostic::error(
+                format!("Variable '{name}' already declared in this scope"),
+                span.clone(),
+            )
+            .with_label(previous.span.clone(), "previously declared here");
+            self.diagnostics.push(diagnostic);
+            return;
+        }
+        scope.insert(
+            name,
+            VariableInfo {
+                ty,
+                is_mutable,
+                span: span.clone(),
+                used: false,
+            },
+        );
+    }
+
+    /// Warn under [`Options::warn_shadow`] if declaring `name` in the innermost scope
+    /// would hide a variable of an enclosing one, which is often unintended
+    fn warn_shadowing(&mut self, name: &str, span: &Span) {
+        let Some((scope, outer)) = self.scopes.split_last() else {
+            return;
+        };
+        if !self.options.warn_shadow || scope.contains_key(name) {
+            return;
+        }
+        let Some(previous) = outer.iter().rev().find_map(|scope| scope.get(name)) else {
+            return;
+        };
+        let diagnostic = Diagnostic::warning(
+            format!("'{name}' shadows a variable of an enclosing scope"),
+            span.clone(),
+        )
+        .with_label(previous.span.clone(), "shadowed variable declared here");
+        self.diagnostics.push(diagnostic);
+    }
+
+    fn resolve_var(&self, name: &str) -> Option<&VariableInfo<'a>> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    /// Resolve a variable that is being read, marking it as used
+    fn use_var(&mut self, name: &str) -> Option<&VariableInfo<'a>> {
+        let var = self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(name))?;
+        var.used = true;
+        Some(var)
+    }
+
+    /// Declare a function, whose return type is `None` if it will be inferred from its body
+    fn declare_fn(
+        &mut self,
+        name: &'a str,
+        params: &[ast::FunctionParameter<'a>],
+        return_type: Option<ast::Type<'a>>,
+        is_const: bool,
+        span: &Span,
+    ) {
+        let param_names = params.iter().map(|param| param.name).collect();
+        let params = params
+            .iter()
+            .map(|param| self.resolve_type(param.r#type))
+            .collect::<Vec<_>>();
+        let return_type = return_type.map(|ty| self.resolve_type(ty));
+        for ty in &params {
+            self.check_variable_type(*ty, span);
+        }
+        if let Some(return_type) = return_type {
+            if return_type != ast::Type::Void {
+                self.check_variable_type(return_type, span);
+            }
+        }
+
+        let scope = self.fn_scopes.last_mut().unwrap();
+        if scope.contains_key(name) {
+            self.error(format!("Function '{name}' already declared"), span);
+            return;
+        }
+        scope.insert(
+            name,
+            FunctionSig {
+                params,
+                param_names,
+                return_type,
+                is_const,
+            },
+        );
+    }
+
+    fn resolve_fn(&self, name: &str) -> Option<&FunctionSig<'a>> {
+        self.fn_scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+    }
+
+    fn resolve_fn_mut(&mut self, name: &str) -> Option<&mut FunctionSig<'a>> {
+        self.fn_scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(name))
+    }
+
+    fn declare_struct(&mut self, name: &'a str, fields: &[ast::StructField<'a>], span: &Span) {
+        if self.structs.contains_key(name) || self.enums.contains_key(name) {
+            self.error(format!("Struct '{name}' already declared"), span);
+            return;
+        }
+        let fields = fields
+            .iter()
+            .map(|field| ast::StructField {
+                name: field.name,
+                r#type: self.resolve_type(field.r#type),
+            })
+            .collect::<Vec<_>>();
+        for field in &fields {
+            self.check_variable_type(field.r#type, span);
+        }
+        self.structs.insert(name, fields);
+    }
+
+    fn declare_enum(&mut self, name: &'a str, variants: &[ast::EnumVariant<'a>], span: &Span) {
+        if self.enums.contains_key(name) || self.structs.contains_key(name) {
+            self.error(format!("Enum '{name}' already declared"), span);
+            return;
+        }
+
+        let mut names = Vec::new();
+        let mut next = 0i64;
+        for variant in variants {
+            if names.contains(&variant.name) {
+                self.error(
+                    format!(
+                        "Variant '{}' declared more than once in enum '{name}'",
+                        variant.name
+                    ),
+                    span,
+                );
+            }
+            names.push(variant.name);
+
+            // Implicit discriminants count up from the previous variant
+            let value = variant.value.unwrap_or(next);
+            if i32::try_from(value).is_err() {
+                self.error(
+                    format!("discriminant of '{}' out of range for i32", variant.name),
+                    span,
+                );
+            }
+            next = value.saturating_add(1);
+        }
+        self.enums.insert(name, names);
+    }
+
+    /// Resolve a named type written in the source to the struct or enum it refers to
+    fn resolve_type(&self, ty: ast::Type<'a>) -> ast::Type<'a> {
+        match ty {
+            ast::Type::Struct(name) if self.enums.contains_key(name) => ast::Type::Enum(name),
+            _ => ty,
+        }
+    }
+
+    /// Check that a type can be used for a value, reporting an error if it can't
+    fn check_variable_type(&mut self, ty: ast::Type<'a>, span: &Span) -> bool {
+        match ty {
+            ast::Type::Void => {
+                self.error("Void type cannot be used directly as a variable type", span);
+                false
+            }
+            ast::Type::Struct(name) if !self.structs.contains_key(name) => {
+                self.error(format!("Struct '{name}' not found"), span);
+                false
+            }
+            ast::Type::Enum(name) if !self.enums.contains_key(name) => {
+                self.error(format!("Enum '{name}' not found"), span);
+                false
+            }
+            _ => true,
+        }
+    }
+}
+
+/// The error for an operation outside the subset a `const fn` may use
+const CONST_FN_SUBSET: &str =
+    "Only arithmetic, comparisons, `if` and calls to const fns are allowed in a const fn";
+
+fn is_integer(ty: ast::Type) -> bool {
+    matches!(ty, ast::Type::I32 | ast::Type::I64)
+}
+
+fn is_numeric(ty: ast::Type) -> bool {
+    is_integer(ty) || matches!(ty, ast::Type::F32 | ast::Type::F64)
+}
+
+/// Whether storing a `from` value in a `to` slot would drop bits
+fn is_narrowing(from: ast::Type, to: ast::Type) -> bool {
+    matches!((from, to), (ast::Type::I64, ast::Type::I32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use indoc::indoc;
+
+    fn check_source(input: &str) -> Vec<String> {
+        check_source_with(input, Options::default())
+    }
+
+    fn check_source_with(input: &str, options: Options) -> Vec<String> {
+        let program = parse(input).into_result().unwrap();
+        check_with(&program, options)
+            .into_iter()
+            .filter(Diagnostic::is_error)
+            .map(|diagnostic| diagnostic.message)
+            .collect()
+    }
+
+    #[test]
+    fn test_check_valid_program() {
+        let input = indoc! {"
+            struct Point { x: i32, y: i32 }
+            fn add(a: i32, b: i32) -> i32 {
+                a + b
+            }
+            var p = Point { x: 1, y: 2 };
+            while p.x < 10 {
+                p = Point { x: p.x + 1, y: p.y };
+            }
+            add(p.x, p.y)
+        "};
+        assert_eq!(check_source(input), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_check_enum() {
+        let input = indoc! {"
+            enum Color { Red, Green = 5, Blue }
+            fn is_blue(color: Color) -> bool {
+                color == Color::Blue
+            }
+            let c: Color = Color::Green;
+            if is_blue(c) { 1 }
+            Color::Purple as i32 + c
+        "};
+        assert_eq!(
+            check_source(input),
+            vec![
+                "Enum 'Color' has no variant 'Purple'",
+                "Type mismatch in binary operation: i32 and Color",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_match() {
+        let input = indoc! {"
+            let a = match 1 { 1 => 2, _ => 3 };
+            let b = match true { 1 => 2, 2 => false };
+            a
+        "};
+        assert_eq!(
+            check_source(input),
+            vec![
+                "Match scrutinee must be an integer value, found bool",
+                "Match on integers requires a `_` arm",
+                "Match arms have different types: expected i32, found bool",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_match_pattern_out_of_range() {
+        let input = indoc! {"
+            let x: i64 = 3000000000;
+            let a = match x { 3000000000 => 1, _ => 0 };
+            let y = 1;
+            match y { -2147483648 => a, 3000000000 => 2, _ => 0 }
+        "};
+        let program = parse(input).into_result().unwrap();
+        assert_eq!(
+            check(&program),
+            vec![Diagnostic::error(
+                "pattern 3000000000 out of range for i32",
+                109..119
+            )]
+        );
+    }
+
+    #[test]
+    fn test_check_sizeof() {
+        let input = indoc! {"
+            struct Point { x: i32, y: i32 }
+            let a = sizeof(i64) + sizeof(Point);
+            let b = sizeof(void);
+            sizeof(Missing) + a
+        "};
+        assert_eq!(
+            check_source(input),
+            vec!["Cannot take the size of void", "Struct 'Missing' not found"]
+        );
+    }
+
+    #[test]
+    fn test_check_labeled_block() {
+        let input = indoc! {"
+            let a = 'outer: {
+                if true { break 'outer 1; }
+                break 'inner 2;
+                true
+            };
+            let b = 'empty: { break 'empty 3; };
+            a + b
+        "};
+        assert_eq!(
+            check_source(input),
+            vec![
+                "Unknown label 'inner",
+                "Labeled block 'outer yields different types: expected i32, found bool",
+                "Labeled block 'empty must end with a value",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_redeclared_variable() {
+        let input = indoc! {"
+            let a = 1;
+            let a = 2;
+            a
+        "};
+        let program = parse(input).into_result().unwrap();
+        let diagnostics = check(&program);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].message,
+            "Variable 'a' already declared in this scope"
+        );
+        assert_eq!(diagnostics[0].span, 11..21);
+        assert_eq!(
+            diagnostics[0].labels,
+            vec![(0..10, "previously declared here".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_check_unused_variable() {
+        let input = indoc! {"
+            let used = 1;
+            let unused = 2;
+            let _ignored = 3;
+            used
+        "};
+        let program = parse(input).into_result().unwrap();
+        let diagnostics = check(&program);
+        assert_eq!(
+            diagnostics,
+            vec![Diagnostic::warning("unused variable 'unused'", 14..29)]
+        );
+    }
+
+    #[test]
+    fn test_check_statements_on_one_line() {
+        let input = "let a: bool = 1; var b = 2; b = true; a";
+        let program = parse(input).into_result().unwrap();
+        let errors: Vec<_> = check(&program)
+            .into_iter()
+            .filter(Diagnostic::is_error)
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                Diagnostic::error(
+                    "Type mismatch in let declaration: expected bool, found i32",
+                    14..15
+                ),
+                Diagnostic::error(
+                    "Type mismatch in assignment to variable 'b': expected i32, found bool",
+                    32..36
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_void_variable() {
+        let input = indoc! {"
+            fn nothing() -> void {
+                return;
+            }
+            let a: void = nothing();
+            let b = nothing();
+            var c: void;
+            0
+        "};
+        let program = parse(input).into_result().unwrap();
+        let diagnostics = check(&program)
+            .into_iter()
+            .filter(Diagnostic::is_error)
+            .map(|diagnostic| (diagnostic.message, diagnostic.span))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            diagnostics,
+            vec![
+                ("cannot declare a variable of type void".to_string(), 37..61),
+                ("cannot declare a variable of type void".to_string(), 62..80),
+                ("cannot declare a variable of type void".to_string(), 81..93),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_discarded_void_value() {
+        let input = indoc! {"
+            fn nothing() -> void {
+                return;
+            }
+            nothing();
+            let _ = nothing();
+            let _ = 1;
+            0
+        "};
+        assert_eq!(check_source(input), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_check_boolean_equality() {
+        let input = indoc! {"
+            let a = true != false;
+            let b = a == (1 < 2);
+            if b { 1 }
+            true == 1
+        "};
+        assert_eq!(
+            check_source(input),
+            vec!["Type mismatch in binary operation: bool and i32"]
+        );
+    }
+
+    #[test]
+    fn test_check_named_args() {
+        let input = indoc! {"
+            fn sub(a: i32, b: i32) -> i32 { a - b }
+            let ok = sub(b: 1, a: 2) + sub(2, b: 1);
+            let unknown = sub(1, c: 2);
+            let twice = sub(1, a: 2);
+            let mismatch = sub(b: true, a: 2);
+            let builtin = max(a: 1, b: 2);
+        "};
+        assert_eq!(
+            check_source(input),
+            vec![
+                "Function 'sub' has no parameter named 'c'",
+                "Argument 'a' to 'sub' is given more than once",
+                "Type mismatch in argument to 'sub': expected i32, found bool",
+                "Builtin 'max' does not take named arguments",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_extern_functions() {
+        let input = indoc! {"
+            let a = abs(-1);
+            let b = abs(true);
+            extern fn abs(x: i32) -> i32;
+            fn f() -> i64 {
+                extern fn labs(x: i64) -> i64;
+                labs(1 + 1)
+            }
+            labs(1 as i64)
+        "};
+        assert_eq!(
+            check_source(input),
+            vec![
+                "Type mismatch in argument to 'abs': expected i32, found bool",
+                "Type mismatch in argument to 'labs': expected i64, found i32",
+                "Function 'labs' not found",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_abs_sqrt() {
+        let input = indoc! {"
+            let a = abs(-3) + abs(2);
+            let b = sqrt(abs(-2.0));
+            let c = sqrt(4);
+            let d = abs(true);
+            let e = abs(1, 2);
+        "};
+        assert_eq!(
+            check_source(input),
+            vec![
+                "Builtin 'sqrt' does not support an argument of type i32",
+                "Builtin 'abs' does not support an argument of type bool",
+                "Builtin 'abs' expects 1 argument, but 2 were given",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_min_max() {
+        let input = indoc! {"
+            let a = max(3, 7) + min(1, 2);
+            let b = min(1.5, 2.5);
+            let c = max(1, 2.5);
+            let d = min(true, false);
+            let e = max(1);
+        "};
+        assert_eq!(
+            check_source(input),
+            vec![
+                "Builtin 'max' does not support arguments of type i32 and f64",
+                "Builtin 'min' does not support arguments of type bool and bool",
+                "Builtin 'max' expects 2 arguments, but 1 were given",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_infer_expression_types() {
+        let input = "let big: i64 = 1;\nlet sum = big + big;\nlet flag = sum > big;";
+        let mut program = parse(input).into_result().unwrap();
+        let (diagnostics, types) = infer_with_types(&mut program, Options::default());
+        assert!(!diagnostics.iter().any(Diagnostic::is_error));
+        assert_eq!(
+            types,
+            [
+                (15..16, ast::Type::I64),
+                (28..31, ast::Type::I64),
+                (28..37, ast::Type::I64),
+                (34..37, ast::Type::I64),
+                (50..53, ast::Type::I64),
+                (50..59, ast::Type::Bool),
+                (56..59, ast::Type::I64),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_block_scope() {
+        assert_eq!(
+            check_source("{\n    let x = 1;\n    x;\n}\nx"),
+            ["Variable 'x' not found"]
+        );
+        assert!(check_source("{\n    let x = 1;\n    x;\n}\nlet x = 2;\nx").is_empty());
+    }
+
+    #[test]
+    fn test_check_warn_shadow() {
+        let input = indoc! {"
+            let x = 1;
+            if x > 0 {
+                let x = 2;
+                return x;
+            }
+            x
+        "};
+        let program = parse(input).into_result().unwrap();
+        assert!(check(&program).is_empty());
+
+        let options = Options {
+            warn_shadow: true,
+            ..Options::default()
+        };
+        let diagnostics = check_with(&program, options);
+        assert_eq!(diagnostics.len(), 1);
+        assert!(!diagnostics[0].is_error());
+        assert_eq!(
+            diagnostics[0].message,
+            "'x' shadows a variable of an enclosing scope"
+        );
+        assert_eq!(diagnostics[0].span, 26..36);
+        assert_eq!(
+            diagnostics[0].labels,
+            [(0..10, "shadowed variable declared here".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_check_const_fn() {
+        let input = indoc! {"
+            const fn square(x: i32) -> i32 { x * x }
+            const fn abs(x: i32) -> i32 { if x < 0 { -x } else { x } }
+            fn twice(x: i32) -> i32 { x * 2 }
+            const fn sum_to(n: i32) -> i32 {
+                var total = 0;
+                while n > 0 { total = total + n; }
+                total
+            }
+            const fn calls(x: i32) -> i32 { twice(square(x)) + abs(x) }
+            const fn half(x: f64) -> f64 { x / 2.0 }
+            square(4) + calls(3) + sum_to(3)
+        "};
+        assert_eq!(
+            check_source(input),
+            [
+                CONST_FN_SUBSET,
+                CONST_FN_SUBSET,
+                CONST_FN_SUBSET,
+                "Cannot call non-const function 'twice' in a const fn",
+                "A const fn can only take and return i32 and bool values",
+                CONST_FN_SUBSET,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_const_item() {
+        let input = indoc! {"
+            const fn square(x: i32) -> i32 { x * x }
+            const N: i32 = square(4);
+            const HALF: f64 = 0.5;
+            fn f() -> i32 {
+                const M: i32 = 2;
+                M
+            }
+            N + f()
+        "};
+        assert_eq!(
+            check_source(input),
+            [
+                "A const item can only have type i32 or bool",
+                "A const item can only be declared at the top level",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_logical_xor() {
+        assert!(check_source("let a = true;\nlet b = a ^^ false;\nb as i32").is_empty());
+        assert_eq!(
+            check_source("let a = 1;\na ^^ 2"),
+            ["Operator Xor is not supported for i32"]
+        );
+    }
+
+    #[test]
+    fn test_check_bool_arithmetic() {
+        let input = indoc! {"
+            let a = 1;
+            let ok = (a < 2) as i32 + (a > 0) as i32;
+            let sum = (a < 2) + (a > 0);
+            let mixed = (a < 2) + 1;
+        "};
+        let program = parse(input).into_result().unwrap();
+        let diagnostics = check(&program);
+        let errors: Vec<_> = diagnostics.iter().filter(|d| d.is_error()).collect();
+        assert_eq!(
+            errors
+                .iter()
+                .map(|d| d.message.as_str())
+                .collect::<Vec<_>>(),
+            [
+                "Operator Add is not supported for bool",
+                "Type mismatch in binary operation: bool and i32",
+            ]
+        );
+        let label_counts: Vec<_> = errors.iter().map(|d| d.labels.len()).collect();
+        assert_eq!(label_counts, [2, 1]);
+        assert_eq!(errors[1].labels[0].1, "use `as i32` to count it as 0 or 1");
+    }
+
+    #[test]
+    fn test_check_int_literal_context() {
+        let input = indoc! {"
+            fn wide(x: i64) -> i64 { x }
+            fn narrow() -> i32 { return 5000000000; }
+            let a: i64 = 1;
+            let b = wide(-2);
+            let c = wide(1 + 1);
+            let d = 3;
+            let e: i64 = d;
+            narrow() + wide(5000000000) as i32
+        "};
+        assert_eq!(
+            check_source(input),
+            vec![
+                "literal 5000000000 out of range for i32",
+                "Type mismatch in argument to 'wide': expected i64, found i32",
+                "Type mismatch in let declaration: expected i64, found i32",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_int_literal_assignment_and_operand() {
+        let input = indoc! {"
+            var n: i64 = 0;
+            n = 1;
+            n = n + 1;
+            let m = 2 * n;
+            let small: i32 = 1;
+            n = 5000000000;
+            let x = small + 5000000000;
+            (m > 0) as i32
+        "};
+        assert_eq!(
+            check_source(input),
+            vec!["literal 5000000000 out of range for i32"]
+        );
+    }
+
+    #[test]
+    fn test_check_implicit_truncation() {
+        let input = indoc! {"
+            let y: i64 = 1 as i64;
+            let x: i32 = y;
+            var z = 0;
+            z = y;
+            let w: i32 = y as i32;
+            let v: i64 = x;
+        "};
+        assert_eq!(
+            check_source(input),
+            vec![
+                "possible truncation; use an explicit cast",
+                "possible truncation; use an explicit cast",
+                "Type mismatch in let declaration: expected i64, found i32",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_c_truthiness() {
+        let input = indoc! {"
+            var n = 3;
+            while n {
+                n = n - 1;
+            }
+            if 1.5 { 1 } else { 0 }
+        "};
+        assert_eq!(
+            check_source(input),
+            vec![
+                "Condition must be a boolean value, found i32",
+                "Condition must be a boolean value, found f64",
+            ]
+        );
+        let options = Options {
+            c_truthiness: true,
+            ..Options::default()
+        };
+        assert_eq!(
+            check_source_with(input, options),
+            vec!["Condition must be a boolean value, found f64"]
+        );
+    }
+
+    #[test]
+    fn test_check_reports_all_errors() {
+        let input = indoc! {"
+            let a = 1;
+            a = 2;
+            if a { 1 }
+            b + true
+        "};
+        assert_eq!(
+            check_source(input),
+            vec![
+                "Cannot assign to immutable variable 'a'",
+                "Condition must be a boolean value, found i32",
+                "Variable 'b' not found",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_inferred_return_types() {
+        let input = indoc! {"
+            fn sign(x: i32) {
+                if x < 0 {
+                    return -1;
+                }
+                1
+            }
+            fn nothing() {}
+            let _ = nothing();
+            let y: i32 = sign(2);
+            y
+        "};
+        assert_eq!(check_source(input), Vec::<String>::new());
+
+        let input = indoc! {"
+            fn f(x: bool) {
+                if x {
+                    return 1;
+                }
+                return;
+            }
+            fn g() {
+                return 1;
+                2.0
+            }
+            h();
+            fn h() { 1 }
+        "};
+        assert_eq!(
+            check_source(input),
+            vec![
+                "Conflicting return types: expected i32, found void",
+                "Conflicting return types: expected i32, found f64",
+                "Cannot call 'h' before its return type is inferred; add an explicit `-> type`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_infer_fills_in_return_types() {
+        let mut program = parse("fn one() { 1 }\nfn none() {}\none()")
+            .into_result()
+            .unwrap();
+        assert!(infer_with(&mut program, Options::default()).is_empty());
+        let types = program
+            .statements
+            .iter()
+            .filter_map(|stmt| match stmt {
+                ast::Stmt::FnDecl { r#type, .. } => Some(*r#type),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(types, vec![Some(ast::Type::I32), Some(ast::Type::Void)]);
+    }
+
+    #[test]
+    fn test_check_increment_decrement() {
+        let input = indoc! {"
+            var i = 0;
+            let j = 0;
+            var flag = true;
+            ++i;
+            i--;
+            j++;
+            --flag;
+            k++;
+            i
+        "};
+        assert_eq!(
+            check_source(input),
+            vec![
+                "Cannot assign to immutable variable 'j'",
+                "Operator Decrement is not supported for bool",
+                "Variable 'k' not found",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_large_literals_are_i64() {
+        let input = indoc! {"
+            let big = 5000000000;
+            let max: i64 = 9223372036854775807;
+            let min: i32 = -2147483648;
+            let small: i32 = 5000000000 - 1;
+            (big + max) as i32 + min + small
+        "};
+        assert_eq!(
+            check_source(input),
+            vec!["possible truncation; use an explicit cast"]
+        );
+    }
+
+    #[test]
+    fn test_check_if_expression_types() {
+        let input = indoc! {"
+            let a = if true { 1 } else { 2 };
+            let b: f64 = if a > 1 { let half = 0.5; half } else if a < 0 { 1.5 } else { 2.5 };
+            let _ = if b > 1.0 { true } else { false };
+            a
+        "};
+        assert_eq!(check_source(input), Vec::<String>::new());
+
+        let input = indoc! {"
+            let _ = if true { 1 } else { 2.5 };
+            let _ = if true { 1 } else if false { true } else { false };
+            let _ = if true { 1 } else { let x = 2; };
+            0
+        "};
+        assert_eq!(
+            check_source(input),
+            vec![
+                "if and else have incompatible types: i32 vs f64",
+                "if and else have incompatible types: i32 vs bool",
+                "else branch must end with a value",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_global_variables() {
+        // Functions see the top-level `var`s declared before them, and no other outer variables
+        let input = indoc! {"
+            var counter = 0;
+            let limit = 10;
+            fn bump() { counter = counter + 1; }
+            fn over() -> bool { counter > limit }
+            fn early() -> i32 { late }
+            var late = 1;
+            bump();
+            let _ = over();
+            early()
+        "};
+        assert_eq!(
+            check_source(input),
+            vec!["Variable 'limit' not found", "Variable 'late' not found"]
+        );
+
+        // A global that is only read inside a function is used
+        let input = "var hits = 0;\nfn hit() -> i32 { hits = hits + 1; hits }\nhit()";
+        let program = parse(input).into_result().unwrap();
+        assert_eq!(check(&program), Vec::<Diagnostic>::new());
+    }
+
+    #[test]
+    fn test_check_shadowing_with_different_mutability() {
+        let input = indoc! {"
+            let x = 1;
+            if true {
+                var x = 2;
+                x = 3;
+                x;
+            }
+            x
+        "};
+        assert_eq!(check_source(input), Vec::<String>::new());
+
+        let input = indoc! {"
+            var x = 1;
+            if true {
+                let x = 2;
+                x = 3;
+            }
+            x = 4;
+            x
+        "};
+        assert_eq!(
+            check_source(input),
+            vec!["Cannot assign to immutable variable 'x'"]
+        );
+    }
+}
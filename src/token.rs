@@ -1,6 +1,14 @@
-use logos::Logos;
+use logos::{Filter, FilterResult, Logos};
+
+/// Settings the lexer consults while tokenizing
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LexOptions {
+    /// Emit comment tokens instead of skipping them, for tools such as formatters
+    pub preserve_comments: bool,
+}
 
 #[derive(Logos, Clone, PartialEq, Debug)]
+#[logos(extras = LexOptions)]
 pub enum Token<'a> {
     Error,
 
@@ -22,18 +30,74 @@ pub enum Token<'a> {
     #[token("else")]
     Else,
 
+    #[token("struct")]
+    Struct,
+
+    #[token("enum")]
+    Enum,
+
+    #[token("as")]
+    As,
+
+    #[token("match")]
+    Match,
+
+    #[token("while")]
+    While,
+
+    #[token("do")]
+    Do,
+
+    #[token("break")]
+    Break,
+
+    #[token("continue")]
+    Continue,
+
+    #[token("sizeof")]
+    SizeOf,
+
+    #[token("extern")]
+    Extern,
+
+    #[token("const")]
+    Const,
+
     #[regex(r"[a-zA-Z_][a-zA-Z0-9_]*")]
     Identifier(&'a str),
 
-    #[regex(r"[0-9]+")]
+    /// A block label such as `'outer`, without the leading quote
+    #[regex(r"'[a-zA-Z_][a-zA-Z0-9_]*", |lex| &lex.slice()[1..])]
+    Label(&'a str),
+
+    #[regex(r"[0-9][0-9_]*|0[xX][0-9a-fA-F_]+|0[oO][0-7_]+|0[bB][01_]+")]
     Integer(&'a str),
 
+    #[regex(r"[0-9]+\.[0-9]+")]
+    #[regex(r"[0-9]+(\.[0-9]+)?[eE][+-]?[0-9]*", float_with_exponent)]
+    Float(&'a str),
+
+    /// A `"..."` string literal without its quotes. Escapes are processed by the parser
+    #[regex(r#""([^"\\]|\\.)*""#, unquote)]
+    String(&'a str),
+
+    /// A raw `r"..."` or multiline `"""..."""` string literal, whose contents are taken verbatim
+    #[token("r\"", |lex| raw_string(lex, "\""))]
+    #[token("\"\"\"", |lex| raw_string(lex, "\"\"\""))]
+    RawString(&'a str),
+
     #[token("+")]
     Add,
 
     #[token("-")]
     Sub,
 
+    #[token("++")]
+    PlusPlus,
+
+    #[token("--")]
+    MinusMinus,
+
     #[token("*")]
     Mul,
 
@@ -64,18 +128,33 @@ pub enum Token<'a> {
     #[token("||")]
     Or,
 
+    #[token("^^")]
+    Xor,
+
     #[token("!")]
     Not,
 
+    #[token("~")]
+    Tilde,
+
     #[token(",")]
     Comma,
 
     #[token("->")]
     RightArrow,
 
+    #[token("=>")]
+    FatArrow,
+
     #[token(":")]
     Colon,
 
+    #[token("::")]
+    ColonColon,
+
+    #[token(".")]
+    Dot,
+
     #[token(";")]
     Semicolon,
 
@@ -89,19 +168,88 @@ pub enum Token<'a> {
     #[token("}")]
     RBrace,
 
+    #[token("[")]
+    LBracket,
+    #[token("]")]
+    RBracket,
+
+    /// Starts an attribute such as `#[inline]`
+    #[token("#")]
+    Hash,
+
     #[token("=")]
     Assign,
 
-    #[regex(r"[ \t\f\n]+", logos::skip)]
+    #[regex(r"[ \t\f\r\n]+", logos::skip)]
     Whitespace,
 
-    // Skip line comments (// ...)
-    #[regex(r"//.*", logos::skip)]
-    LineComment,
+    /// A `// ...` comment, including the slashes. Skipped unless comments are preserved
+    #[regex(r"//.*", comment)]
+    LineComment(&'a str),
+
+    /// A `/* ... */` comment, including the delimiters. Skipped unless comments are preserved
+    #[token("/*", block_comment)]
+    BlockComment(&'a str),
+}
+
+/// Create a lexer that emits `LineComment` and `BlockComment` tokens instead of skipping them
+pub fn lexer_preserving_comments(src: &str) -> logos::Lexer<'_, Token<'_>> {
+    Token::lexer_with_extras(
+        src,
+        LexOptions {
+            preserve_comments: true,
+        },
+    )
+}
+
+/// Keep the text of a comment only when the lexer preserves comments
+fn comment<'a>(lex: &mut logos::Lexer<'a, Token<'a>>) -> Filter<&'a str> {
+    if lex.extras.preserve_comments {
+        Filter::Emit(lex.slice())
+    } else {
+        Filter::Skip
+    }
+}
+
+/// Take a block comment up to its closing `*/`.
+/// An unterminated comment consumes the rest of the input, so it is reported as a single error.
+fn block_comment<'a>(lex: &mut logos::Lexer<'a, Token<'a>>) -> FilterResult<&'a str, ()> {
+    let remainder = lex.remainder();
+    let Some(end) = remainder.find("*/") else {
+        lex.bump(remainder.len());
+        return FilterResult::Error(());
+    };
+    lex.bump(end + "*/".len());
+    match comment(lex) {
+        Filter::Emit(text) => FilterResult::Emit(text),
+        Filter::Skip => FilterResult::Skip,
+    }
+}
+
+/// Accept a float literal with an exponent only if the exponent has digits, so that `1e` is a lex error
+fn float_with_exponent<'a>(lex: &mut logos::Lexer<'a, Token<'a>>) -> Option<&'a str> {
+    let slice = lex.slice();
+    slice
+        .ends_with(|c: char| c.is_ascii_digit())
+        .then_some(slice)
+}
+
+/// Strip the quotes around a string literal
+fn unquote<'a>(lex: &mut logos::Lexer<'a, Token<'a>>) -> &'a str {
+    let slice = lex.slice();
+    &slice[1..slice.len() - 1]
+}
 
-    // Skip block comments (/* ... */)
-    #[regex(r"/\*[^*]*\*+(?:[^/*][^*]*\*+)*/", logos::skip)]
-    BlockComment,
+/// Take everything up to the closing delimiter verbatim, newlines included.
+/// An unterminated string consumes the rest of the input, so it is reported as a single error.
+fn raw_string<'a>(lex: &mut logos::Lexer<'a, Token<'a>>, delimiter: &str) -> Option<&'a str> {
+    let remainder = lex.remainder();
+    let Some(end) = remainder.find(delimiter) else {
+        lex.bump(remainder.len());
+        return None;
+    };
+    lex.bump(end + delimiter.len());
+    Some(&remainder[..end])
 }
 
 impl std::fmt::Display for Token<'_> {
@@ -113,10 +261,27 @@ impl std::fmt::Display for Token<'_> {
             Self::Return => write!(f, "return"),
             Self::If => write!(f, "if"),
             Self::Else => write!(f, "else"),
+            Self::Struct => write!(f, "struct"),
+            Self::Enum => write!(f, "enum"),
+            Self::As => write!(f, "as"),
+            Self::Match => write!(f, "match"),
+            Self::While => write!(f, "while"),
+            Self::Do => write!(f, "do"),
+            Self::Break => write!(f, "break"),
+            Self::Continue => write!(f, "continue"),
+            Self::SizeOf => write!(f, "sizeof"),
+            Self::Extern => write!(f, "extern"),
+            Self::Const => write!(f, "const"),
             Self::Identifier(value) => write!(f, "{value}"),
+            Self::Label(value) => write!(f, "'{value}"),
             Self::Integer(value) => write!(f, "{value}"),
+            Self::Float(value) => write!(f, "{value}"),
+            Self::String(value) => write!(f, "\"{value}\""),
+            Self::RawString(value) => write!(f, "r\"{value}\""),
             Self::Add => write!(f, "+"),
             Self::Sub => write!(f, "-"),
+            Self::PlusPlus => write!(f, "++"),
+            Self::MinusMinus => write!(f, "--"),
             Self::Mul => write!(f, "*"),
             Self::Div => write!(f, "/"),
             Self::Equal => write!(f, "=="),
@@ -127,19 +292,26 @@ impl std::fmt::Display for Token<'_> {
             Self::GreaterThanOrEqual => write!(f, ">="),
             Self::And => write!(f, "&&"),
             Self::Or => write!(f, "||"),
+            Self::Xor => write!(f, "^^"),
             Self::Not => write!(f, "!"),
+            Self::Tilde => write!(f, "~"),
             Self::Comma => write!(f, ","),
             Self::Colon => write!(f, ":"),
+            Self::ColonColon => write!(f, "::"),
+            Self::Dot => write!(f, "."),
             Self::Semicolon => write!(f, ";"),
             Self::LParen => write!(f, "("),
             Self::RParen => write!(f, ")"),
             Self::LBrace => write!(f, "{{"),
             Self::RBrace => write!(f, "}}"),
+            Self::LBracket => write!(f, "["),
+            Self::RBracket => write!(f, "]"),
+            Self::Hash => write!(f, "#"),
             Self::RightArrow => write!(f, "->"),
+            Self::FatArrow => write!(f, "=>"),
             Self::Assign => write!(f, "="),
             Self::Whitespace => write!(f, "<whitespace>"),
-            Self::LineComment => write!(f, "<line_comment>"),
-            Self::BlockComment => write!(f, "<block_comment>"),
+            Self::LineComment(value) | Self::BlockComment(value) => write!(f, "{value}"),
             Self::Error => write!(f, "<e>"),
         }
     }
@@ -0,0 +1,337 @@
+//! Generic traversal over the AST
+//!
+//! Implement [`Visitor`] (or [`VisitorMut`] to rewrite nodes in place) and
+//! override only the methods for the nodes you care about. The default
+//! methods delegate to the `walk_*` functions, which visit every child; call
+//! them from an overridden method to continue the traversal.
+
+use crate::ast;
+
+/// A read-only AST visitor
+pub trait Visitor<'a> {
+    fn visit_program(&mut self, program: &ast::Program<'a>) {
+        walk_program(self, program);
+    }
+
+    fn visit_stmt(&mut self, stmt: &ast::Stmt<'a>) {
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &ast::Expr<'a>) {
+        walk_expr(self, expr);
+    }
+}
+
+/// Visit every statement of a program
+pub fn walk_program<'a, V: Visitor<'a> + ?Sized>(visitor: &mut V, program: &ast::Program<'a>) {
+    for stmt in &program.statements {
+        visitor.visit_stmt(stmt);
+    }
+}
+
+/// Visit the children of a statement
+pub fn walk_stmt<'a, V: Visitor<'a> + ?Sized>(visitor: &mut V, stmt: &ast::Stmt<'a>) {
+    match stmt {
+        ast::Stmt::FnDecl { body, .. } => {
+            for stmt in body {
+                visitor.visit_stmt(stmt);
+            }
+        }
+        ast::Stmt::Break { value, .. } => {
+            if let Some(value) = value {
+                visitor.visit_expr(value);
+            }
+        }
+        ast::Stmt::ExternDecl { .. }
+        | ast::Stmt::StructDecl { .. }
+        | ast::Stmt::EnumDecl { .. }
+        | ast::Stmt::Continue { .. } => {}
+        ast::Stmt::LetDecl { value, .. } | ast::Stmt::VarDecl { value, .. } => {
+            if let Some(value) = value {
+                visitor.visit_expr(value);
+            }
+        }
+        ast::Stmt::Assign { value, .. } => visitor.visit_expr(value),
+        ast::Stmt::If {
+            condition,
+            then_branch,
+            else_branch,
+        } => {
+            visitor.visit_expr(condition);
+            for stmt in then_branch {
+                visitor.visit_stmt(stmt);
+            }
+            for stmt in else_branch.iter().flatten() {
+                visitor.visit_stmt(stmt);
+            }
+        }
+        ast::Stmt::While { condition, body } => {
+            visitor.visit_expr(condition);
+            for stmt in body {
+                visitor.visit_stmt(stmt);
+            }
+        }
+        ast::Stmt::DoWhile { body, condition } => {
+            for stmt in body {
+                visitor.visit_stmt(stmt);
+            }
+            visitor.visit_expr(condition);
+        }
+        ast::Stmt::Block { body } => {
+            for stmt in body {
+                visitor.visit_stmt(stmt);
+            }
+        }
+        ast::Stmt::Return { expr, .. } => {
+            if let Some(expr) = expr {
+                visitor.visit_expr(expr);
+            }
+        }
+        ast::Stmt::ExprStmt { expr } | ast::Stmt::Expr { expr } => visitor.visit_expr(expr),
+    }
+}
+
+/// Visit the children of an expression
+pub fn walk_expr<'a, V: Visitor<'a> + ?Sized>(visitor: &mut V, expr: &ast::Expr<'a>) {
+    match &expr.kind {
+        ast::ExprKind::IntLit(_)
+        | ast::ExprKind::FloatLit(_)
+        | ast::ExprKind::BoolLit(_)
+        | ast::ExprKind::StrLit(_)
+        | ast::ExprKind::VarRef { .. }
+        | ast::ExprKind::EnumVariant { .. }
+        | ast::ExprKind::SizeOf { .. }
+        | ast::ExprKind::IncDec { .. } => {}
+        ast::ExprKind::BinOp { lhs, rhs, .. } => {
+            visitor.visit_expr(lhs);
+            visitor.visit_expr(rhs);
+        }
+        ast::ExprKind::UnaryOp { expr, .. }
+        | ast::ExprKind::FieldAccess { expr, .. }
+        | ast::ExprKind::Cast { expr, .. } => visitor.visit_expr(expr),
+        ast::ExprKind::FnCall { args, .. } => {
+            for arg in args {
+                visitor.visit_expr(arg);
+            }
+        }
+        ast::ExprKind::StructLit { fields, .. } => {
+            for field in fields {
+                visitor.visit_expr(&field.value);
+            }
+        }
+        ast::ExprKind::Block { body, .. } => {
+            for stmt in body {
+                visitor.visit_stmt(stmt);
+            }
+        }
+        ast::ExprKind::If {
+            condition,
+            then_branch,
+            else_branch,
+        } => {
+            visitor.visit_expr(condition);
+            for stmt in then_branch.iter().chain(else_branch) {
+                visitor.visit_stmt(stmt);
+            }
+        }
+        ast::ExprKind::Match { scrutinee, arms } => {
+            visitor.visit_expr(scrutinee);
+            for arm in arms {
+                visitor.visit_expr(&arm.value);
+            }
+        }
+        ast::ExprKind::Sequence { exprs } => {
+            for expr in exprs {
+                visitor.visit_expr(expr);
+            }
+        }
+    }
+}
+
+/// An AST visitor that may rewrite nodes in place
+pub trait VisitorMut<'a> {
+    fn visit_program_mut(&mut self, program: &mut ast::Program<'a>) {
+        walk_program_mut(self, program);
+    }
+
+    fn visit_stmt_mut(&mut self, stmt: &mut ast::Stmt<'a>) {
+        walk_stmt_mut(self, stmt);
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut ast::Expr<'a>) {
+        walk_expr_mut(self, expr);
+    }
+}
+
+/// Visit every statement of a program mutably
+pub fn walk_program_mut<'a, V: VisitorMut<'a> + ?Sized>(
+    visitor: &mut V,
+    program: &mut ast::Program<'a>,
+) {
+    for stmt in &mut program.statements {
+        visitor.visit_stmt_mut(stmt);
+    }
+}
+
+/// Visit the children of a statement mutably
+pub fn walk_stmt_mut<'a, V: VisitorMut<'a> + ?Sized>(visitor: &mut V, stmt: &mut ast::Stmt<'a>) {
+    match stmt {
+        ast::Stmt::FnDecl { body, .. } => {
+            for stmt in body {
+                visitor.visit_stmt_mut(stmt);
+            }
+        }
+        ast::Stmt::Break { value, .. } => {
+            if let Some(value) = value {
+                visitor.visit_expr_mut(value);
+            }
+        }
+        ast::Stmt::ExternDecl { .. }
+        | ast::Stmt::StructDecl { .. }
+        | ast::Stmt::EnumDecl { .. }
+        | ast::Stmt::Continue { .. } => {}
+        ast::Stmt::LetDecl { value, .. } | ast::Stmt::VarDecl { value, .. } => {
+            if let Some(value) = value {
+                visitor.visit_expr_mut(value);
+            }
+        }
+        ast::Stmt::Assign { value, .. } => visitor.visit_expr_mut(value),
+        ast::Stmt::If {
+            condition,
+            then_branch,
+            else_branch,
+        } => {
+            visitor.visit_expr_mut(condition);
+            for stmt in then_branch {
+                visitor.visit_stmt_mut(stmt);
+            }
+            for stmt in else_branch.iter_mut().flatten() {
+                visitor.visit_stmt_mut(stmt);
+            }
+        }
+        ast::Stmt::While { condition, body } => {
+            visitor.visit_expr_mut(condition);
+            for stmt in body {
+                visitor.visit_stmt_mut(stmt);
+            }
+        }
+        ast::Stmt::DoWhile { body, condition } => {
+            for stmt in body {
+                visitor.visit_stmt_mut(stmt);
+            }
+            visitor.visit_expr_mut(condition);
+        }
+        ast::Stmt::Block { body } => {
+            for stmt in body {
+                visitor.visit_stmt_mut(stmt);
+            }
+        }
+        ast::Stmt::Return { expr, .. } => {
+            if let Some(expr) = expr {
+                visitor.visit_expr_mut(expr);
+            }
+        }
+        ast::Stmt::ExprStmt { expr } | ast::Stmt::Expr { expr } => visitor.visit_expr_mut(expr),
+    }
+}
+
+/// Visit the children of an expression mutably
+pub fn walk_expr_mut<'a, V: VisitorMut<'a> + ?Sized>(visitor: &mut V, expr: &mut ast::Expr<'a>) {
+    match &mut expr.kind {
+        ast::ExprKind::IntLit(_)
+        | ast::ExprKind::FloatLit(_)
+        | ast::ExprKind::BoolLit(_)
+        | ast::ExprKind::StrLit(_)
+        | ast::ExprKind::VarRef { .. }
+        | ast::ExprKind::EnumVariant { .. }
+        | ast::ExprKind::SizeOf { .. }
+        | ast::ExprKind::IncDec { .. } => {}
+        ast::ExprKind::BinOp { lhs, rhs, .. } => {
+            visitor.visit_expr_mut(lhs);
+            visitor.visit_expr_mut(rhs);
+        }
+        ast::ExprKind::UnaryOp { expr, .. }
+        | ast::ExprKind::FieldAccess { expr, .. }
+        | ast::ExprKind::Cast { expr, .. } => visitor.visit_expr_mut(expr),
+        ast::ExprKind::FnCall { args, .. } => {
+            for arg in args {
+                visitor.visit_expr_mut(arg);
+            }
+        }
+        ast::ExprKind::StructLit { fields, .. } => {
+            for field in fields {
+                visitor.visit_expr_mut(&mut field.value);
+            }
+        }
+        ast::ExprKind::Block { body, .. } => {
+            for stmt in body {
+                visitor.visit_stmt_mut(stmt);
+            }
+        }
+        ast::ExprKind::If {
+            condition,
+            then_branch,
+            else_branch,
+        } => {
+            visitor.visit_expr_mut(condition);
+            for stmt in then_branch.iter_mut().chain(else_branch) {
+                visitor.visit_stmt_mut(stmt);
+            }
+        }
+        ast::ExprKind::Match { scrutinee, arms } => {
+            visitor.visit_expr_mut(scrutinee);
+            for arm in arms {
+                visitor.visit_expr_mut(&mut arm.value);
+            }
+        }
+        ast::ExprKind::Sequence { exprs } => {
+            for expr in exprs {
+                visitor.visit_expr_mut(expr);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use indoc::indoc;
+
+    #[derive(Default)]
+    struct NodeCounter {
+        stmts: usize,
+        exprs: usize,
+    }
+
+    impl<'a> Visitor<'a> for NodeCounter {
+        fn visit_stmt(&mut self, stmt: &ast::Stmt<'a>) {
+            self.stmts += 1;
+            walk_stmt(self, stmt);
+        }
+
+        fn visit_expr(&mut self, expr: &ast::Expr<'a>) {
+            self.exprs += 1;
+            walk_expr(self, expr);
+        }
+    }
+
+    #[test]
+    fn test_count_nodes() {
+        let input = indoc! {"
+            fn add(a: i32, b: i32) -> i32 {
+                return a + b;
+            }
+            let x = add(1, 2 * 3);
+            if x == 7 { 1 } else { 0 }
+        "};
+        let program = parse(input).into_result().unwrap();
+        let mut counter = NodeCounter::default();
+        counter.visit_program(&program);
+
+        // fn, return, let, if, and the two branch expressions
+        assert_eq!(counter.stmts, 6);
+        // a + b (3), add(1, 2 * 3) (5), x == 7 (3), 1, 0
+        assert_eq!(counter.exprs, 13);
+    }
+}
@@ -11,6 +11,20 @@ pub struct RunResult {
 
 /// Compiles, links, and runs an AIC program. Returns exit code, stdout, and stderr.
 fn compile_and_run_aic<P: AsRef<Path>>(aic_path: P) -> RunResult {
+    compile_and_run_aic_with(aic_path, &[])
+}
+
+/// Like [`compile_and_run_aic`], passing extra flags to the compiler.
+fn compile_and_run_aic_with<P: AsRef<Path>>(aic_path: P, flags: &[&str]) -> RunResult {
+    compile_and_run_aic_linked(aic_path, flags, &[])
+}
+
+/// Like [`compile_and_run_aic_with`], also passing extra flags to the linker.
+fn compile_and_run_aic_linked<P: AsRef<Path>>(
+    aic_path: P,
+    flags: &[&str],
+    link_flags: &[&str],
+) -> RunResult {
     let aic_path = aic_path.as_ref();
     let stem = aic_path.file_stem().unwrap().to_str().unwrap();
     let temp_dir = tempdir().expect("Failed to create temp dir");
@@ -28,6 +42,7 @@ fn compile_and_run_aic<P: AsRef<Path>>(aic_path: P) -> RunResult {
             "-o",
             obj_file.to_str().unwrap(),
         ])
+        .args(flags)
         .stdout(std::process::Stdio::null())
         .status()
         .expect("Failed to run cargo build");
@@ -41,6 +56,7 @@ fn compile_and_run_aic<P: AsRef<Path>>(aic_path: P) -> RunResult {
             "-o",
             exe_file.to_str().unwrap(),
         ])
+        .args(link_flags)
         .stdout(std::process::Stdio::null())
         .status()
         .expect("Failed to run clang with mold");
@@ -57,6 +73,20 @@ fn compile_and_run_aic<P: AsRef<Path>>(aic_path: P) -> RunResult {
     }
 }
 
+/// Runs the AIC compiler with the given arguments. Returns exit code, stdout, and stderr.
+fn run_aic(args: &[&str]) -> RunResult {
+    let output = Command::new("cargo")
+        .args(["run", "--release", "--"])
+        .args(args)
+        .output()
+        .expect("Failed to run cargo");
+    RunResult {
+        code: output.status.code().unwrap_or(-1),
+        stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+        stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+    }
+}
+
 #[test]
 fn test_simple_aic() {
     let result = compile_and_run_aic("tests/fixtures/simple.aic");
@@ -80,6 +110,92 @@ fn test_negative_aic() {
     assert_eq!(result.code, 1, "exit code was {}, expected 1", result.code);
 }
 
+#[test]
+fn test_emit_llvm_clean() {
+    let emit_llvm = |flags: &[&str]| {
+        let mut args = vec!["--input", "tests/fixtures/let_and_var.aic", "--emit-llvm"];
+        args.extend_from_slice(flags);
+        let result = run_aic(&args);
+        assert_eq!(result.code, 0, "stderr was: {}", result.stderr);
+        result.stdout
+    };
+
+    let default = emit_llvm(&["--sanitize=address"]);
+    let clean = emit_llvm(&["--sanitize=address", "--clean"]);
+    assert!(
+        clean.len() < default.len(),
+        "clean output should be shorter:\n{clean}"
+    );
+    for noise in ["; ModuleID", "attributes #", ", align "] {
+        assert!(default.contains(noise), "stdout was: {default}");
+        assert!(!clean.contains(noise), "stdout was: {clean}");
+    }
+
+    let result = run_aic(&["--input", "tests/fixtures/let_and_var.aic", "--clean"]);
+    assert_ne!(result.code, 0);
+    assert!(
+        result
+            .stderr
+            .contains("--clean requires --emit-llvm or --emit llvm-ir"),
+        "stderr was: {}",
+        result.stderr
+    );
+}
+
+#[test]
+fn test_fast_math_flags() {
+    let emit_llvm = |flags: &[&str]| {
+        let mut args = vec!["--input", "tests/fixtures/float_call.aic", "--emit-llvm"];
+        args.extend_from_slice(flags);
+        let result = run_aic(&args);
+        assert_eq!(result.code, 0, "stderr was: {}", result.stderr);
+        result.stdout
+    };
+
+    let ir = emit_llvm(&["--ffast-math"]);
+    assert!(ir.contains("fdiv fast double"), "stdout was: {ir}");
+    assert!(ir.contains("fmul fast float"), "stdout was: {ir}");
+
+    // Float operations are IEEE-exact unless fast-math is requested, and the last flag wins
+    let cases: [&[&str]; 3] = [
+        &[],
+        &["--fno-fast-math"],
+        &["--ffast-math", "--fno-fast-math"],
+    ];
+    for flags in cases {
+        let ir = emit_llvm(flags);
+        assert!(
+            ir.contains("fdiv double") && !ir.contains(" fast "),
+            "{flags:?}: stdout was: {ir}"
+        );
+    }
+}
+
+#[test]
+fn test_wrap_exit_code_aic() {
+    // Masking with 0xFF makes -1 exit with 255 regardless of the platform
+    let actual =
+        compile_and_run_aic_with("tests/fixtures/exit_minus_one.aic", &["--wrap-exit-code"]).code;
+    let expected = 255;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/exit_minus_one.aic",
+        "--wrap-exit-code",
+        "--emit-llvm",
+    ]);
+    assert_eq!(result.code, 0, "stderr was: {}", result.stderr);
+    assert!(
+        result.stdout.contains("and i32") && result.stdout.contains(", 255"),
+        "stdout was: {}",
+        result.stdout
+    );
+}
+
 #[test]
 fn test_function_call_aic() {
     let actual = compile_and_run_aic("tests/fixtures/function_call.aic").code;
@@ -189,3 +305,1871 @@ fn test_conditional_var_assign() {
         "exit code was {actual}, expected {expected}",
     );
 }
+
+#[test]
+fn test_struct_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/struct.aic").code;
+    let expected = 23;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_bool_return_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/bool_return.aic").code;
+    let expected = 1;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_while_break_continue_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/while_break_continue.aic").code;
+    let expected = 52;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_break_outside_loop_aic() {
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/break_outside_loop.aic",
+        "--emit-llvm",
+    ]);
+    assert_ne!(result.code, 0, "compilation should fail");
+    assert!(
+        result.stderr.contains("`break` outside of a loop"),
+        "stderr was: {}",
+        result.stderr
+    );
+}
+
+#[test]
+fn test_hoisting_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/hoisting.aic").code;
+    let expected = 41;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_literal_out_of_range_i32_aic() {
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/literal_out_of_range_i32.aic",
+        "--emit-llvm",
+    ]);
+    assert_ne!(result.code, 0, "compilation should fail");
+    assert!(
+        result
+            .stderr
+            .contains("literal 5000000000 out of range for i32"),
+        "stderr was: {}",
+        result.stderr
+    );
+}
+
+#[test]
+fn test_literal_out_of_range_negative_i32_aic() {
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/literal_out_of_range_negative_i32.aic",
+        "--emit-llvm",
+    ]);
+    assert_ne!(result.code, 0, "compilation should fail");
+    assert!(
+        result
+            .stderr
+            .contains("literal -2147483649 out of range for i32"),
+        "stderr was: {}",
+        result.stderr
+    );
+}
+
+#[test]
+fn test_literal_out_of_range_i64_aic() {
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/literal_out_of_range_i64.aic",
+        "--emit-llvm",
+    ]);
+    assert_ne!(result.code, 0, "compilation should fail");
+    assert!(
+        result.stderr.contains("integer literal too large"),
+        "stderr was: {}",
+        result.stderr
+    );
+}
+
+#[test]
+fn test_literal_out_of_range_negative_i64_aic() {
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/literal_out_of_range_negative_i64.aic",
+        "--emit-llvm",
+    ]);
+    assert_ne!(result.code, 0, "compilation should fail");
+    assert!(
+        result.stderr.contains("integer literal too large"),
+        "stderr was: {}",
+        result.stderr
+    );
+}
+
+#[test]
+fn test_literal_out_of_range_argument_aic() {
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/literal_out_of_range_argument.aic",
+        "--emit-llvm",
+    ]);
+    assert_ne!(result.code, 0, "compilation should fail");
+    assert!(
+        result
+            .stderr
+            .contains("literal 5000000000 out of range for i32"),
+        "stderr was: {}",
+        result.stderr
+    );
+}
+
+#[test]
+fn test_int_literal_context_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/int_literal_context.aic").code;
+    let expected = 42;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_int_literal_assignment_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/int_literal_assignment.aic").code;
+    let expected = 42;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_assert_pass_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/assert_pass.aic").code;
+    let expected = 42;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_assert_fail_aic() {
+    let result = compile_and_run_aic("tests/fixtures/assert_fail.aic");
+    // abort() kills the program with SIGABRT instead of exiting
+    assert_eq!(result.code, -1, "exit code was {}", result.code);
+    assert_eq!(
+        result.stderr,
+        "Assertion failed at tests/fixtures/assert_fail.aic:3\n"
+    );
+}
+
+#[test]
+fn test_pow_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/pow.aic").code;
+    let expected = 25;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_pow_float_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/pow_float.aic").code;
+    let expected = 31;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+
+    let result = run_aic(&["--input", "tests/fixtures/pow_float.aic", "--emit-llvm"]);
+    assert_eq!(result.code, 0, "stderr was: {}", result.stderr);
+    for intrinsic in ["@llvm.pow.f64", "@llvm.powi.f64.i32"] {
+        assert!(
+            result.stdout.contains(intrinsic),
+            "missing {intrinsic}, stdout was: {}",
+            result.stdout
+        );
+    }
+}
+
+#[test]
+fn test_timings() {
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/simple.aic",
+        "--emit-llvm",
+        "--timings",
+    ]);
+    assert_eq!(result.code, 0, "stderr was: {}", result.stderr);
+    for phase in ["phase", "parse", "codegen", "total"] {
+        assert!(
+            result.stderr.lines().any(|line| line.starts_with(phase)),
+            "missing '{phase}' in stderr: {}",
+            result.stderr
+        );
+    }
+}
+
+#[test]
+fn test_nested_functions_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/nested_functions.aic").code;
+    let expected = 12;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_check_mode_emits_nothing() {
+    let dir = tempdir().expect("Failed to create temp dir");
+    let output = dir.path().join("simple.o");
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/simple.aic",
+        "--output",
+        output.to_str().unwrap(),
+        "--check",
+    ]);
+    assert_eq!(result.code, 0, "stderr was: {}", result.stderr);
+    assert!(
+        !output.exists(),
+        "check mode should not produce an object file"
+    );
+}
+
+#[test]
+fn test_check_mode_reports_type_errors() {
+    let result = run_aic(&["--input", "tests/fixtures/type_error.aic", "--check"]);
+    assert_ne!(result.code, 0, "type-checking should fail");
+    for message in [
+        "Condition must be a boolean value, found i32",
+        "Type mismatch in binary operation: bool and i32",
+    ] {
+        assert!(
+            result.stderr.contains(message),
+            "missing '{message}' in stderr: {}",
+            result.stderr
+        );
+    }
+}
+
+#[test]
+fn test_if_expr_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/if_expr.aic").code;
+    let expected = 42;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_if_expr_mismatch_aic() {
+    let result = run_aic(&["--input", "tests/fixtures/if_expr_mismatch.aic", "--check"]);
+    assert_ne!(result.code, 0, "type-checking should fail");
+    assert!(
+        result
+            .stderr
+            .contains("if and else have incompatible types: i32 vs f64"),
+        "stderr was: {}",
+        result.stderr
+    );
+}
+
+#[test]
+fn test_enum_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/enum.aic").code;
+    let expected = 6;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_match_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/match.aic").code;
+    let expected = 18;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_redeclared_let_aic() {
+    let result = run_aic(&["--input", "tests/fixtures/redeclared_let.aic", "--check"]);
+    assert_ne!(result.code, 0, "type-checking should fail");
+    for label in [
+        "Variable 'count' already declared in this scope",
+        "previously declared here",
+    ] {
+        assert!(
+            result.stderr.contains(label),
+            "missing '{label}' in stderr: {}",
+            result.stderr
+        );
+    }
+}
+
+#[test]
+fn test_float_call_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/float_call.aic").code;
+    let expected = 27;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_unused_variable_warning_aic() {
+    let result = run_aic(&["--input", "tests/fixtures/unused_variable.aic", "--check"]);
+    assert_eq!(result.code, 0, "stderr was: {}", result.stderr);
+    assert!(
+        result.stderr.contains("unused variable 'unused'"),
+        "stderr was: {}",
+        result.stderr
+    );
+}
+
+#[test]
+fn test_werror_aic() {
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/unused_variable.aic",
+        "--check",
+        "--werror",
+    ]);
+    assert_ne!(result.code, 0, "warnings should fail the build");
+    assert!(
+        result.stderr.contains("unused variable 'unused'"),
+        "stderr was: {}",
+        result.stderr
+    );
+}
+
+#[test]
+fn test_do_while_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/do_while.aic").code;
+    let expected = 31;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_int_literal_bases_aic() {
+    for fixture in [
+        "tests/fixtures/int_literal_decimal.aic",
+        "tests/fixtures/int_literal_hex.aic",
+        "tests/fixtures/int_literal_octal.aic",
+        "tests/fixtures/int_literal_binary.aic",
+    ] {
+        let actual = compile_and_run_aic(fixture).code;
+        assert_eq!(
+            actual, 31,
+            "exit code of {fixture} was {actual}, expected 31"
+        );
+    }
+}
+
+#[test]
+fn test_int_literal_misplaced_underscore_aic() {
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/int_literal_misplaced_underscore.aic",
+        "--check",
+    ]);
+    assert_ne!(result.code, 0, "parsing should fail");
+    assert!(
+        result
+            .stderr
+            .contains("misplaced underscore in integer literal `0x_FF`"),
+        "stderr was: {}",
+        result.stderr
+    );
+}
+
+#[test]
+fn test_passes_mem2reg() {
+    let count_allocas = |extra_args: &[&str]| {
+        let mut args = vec!["--input", "tests/fixtures/let_and_var.aic", "--emit-llvm"];
+        args.extend_from_slice(extra_args);
+        let result = run_aic(&args);
+        assert_eq!(result.code, 0, "stderr was: {}", result.stderr);
+        result.stdout.matches(" alloca ").count()
+    };
+
+    let before = count_allocas(&[]);
+    let after = count_allocas(&["--passes=mem2reg"]);
+    assert!(before > 0, "expected allocas before mem2reg");
+    assert!(
+        after < before,
+        "mem2reg should promote allocas ({after} >= {before})"
+    );
+}
+
+#[test]
+fn test_passes_unknown() {
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/simple.aic",
+        "--emit-llvm",
+        "--passes=mem2reg,bogus",
+    ]);
+    assert_ne!(result.code, 0, "unknown passes should be rejected");
+    assert!(
+        result.stderr.contains("Unknown pass 'bogus'"),
+        "stderr was: {}",
+        result.stderr
+    );
+}
+
+#[test]
+fn test_void_variable_aic() {
+    let result = run_aic(&["--input", "tests/fixtures/void_variable.aic", "--check"]);
+    assert_ne!(result.code, 0, "type-checking should fail");
+    assert!(
+        result
+            .stderr
+            .contains("cannot declare a variable of type void"),
+        "stderr was: {}",
+        result.stderr
+    );
+}
+
+#[test]
+fn test_if_without_else_value_aic() {
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/if_without_else_value.aic",
+        "--check",
+    ]);
+    assert_ne!(result.code, 0, "parsing should fail");
+    assert!(
+        result
+            .stderr
+            .contains("if expression without else cannot produce a value"),
+        "stderr was: {}",
+        result.stderr
+    );
+}
+
+#[test]
+fn test_syntax_errors_aic() {
+    // Both independent errors are reported, not just the first one
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/syntax_errors.aic",
+        "--check",
+        "--max-errors=1",
+    ]);
+    assert_ne!(result.code, 0, "parsing should fail");
+    assert!(
+        result.stderr.contains("...and 1 more error."),
+        "stderr was: {}",
+        result.stderr
+    );
+}
+
+#[test]
+fn test_int_literal_too_large_aic() {
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/int_literal_too_large.aic",
+        "--check",
+    ]);
+    assert_ne!(result.code, 0, "parsing should fail");
+    assert!(
+        result.stderr.contains("integer literal too large"),
+        "stderr was: {}",
+        result.stderr
+    );
+    assert!(
+        !result.stderr.contains("panicked"),
+        "stderr was: {}",
+        result.stderr
+    );
+}
+
+#[test]
+fn test_float_scientific_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/float_scientific.aic").code;
+    let expected = 1;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_inferred_i32_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/inferred_i32.aic").code;
+    let expected = 42;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_inferred_void_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/inferred_void.aic").code;
+    let expected = 7;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_void_call_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/void_call.aic").code;
+    let expected = 7;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_empty_function_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/empty_function.aic").code;
+    let expected = 3;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_bool_not_equal_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/bool_not_equal.aic").code;
+    let expected = 1;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_bitwise_not_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/bitwise_not.aic").code;
+    let expected = 1;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_const_fn_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/const_fn.aic").code;
+    let expected = 42;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_const_not_constant_aic() {
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/const_not_constant.aic",
+        "--emit-llvm",
+    ]);
+    assert_ne!(result.code, 0, "compilation should fail");
+    assert!(
+        result
+            .stderr
+            .contains("The value of const 'N' can't be computed at compile time"),
+        "stderr was: {}",
+        result.stderr
+    );
+}
+
+#[test]
+fn test_logical_xor_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/logical_xor.aic").code;
+    let expected = 1;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_logical_xor_same_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/logical_xor_same.aic").code;
+    let expected = 0;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_negate_int_min_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/negate_int_min.aic").code;
+    let expected = 1;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_negate_int_min_checked_aic() {
+    let result = compile_and_run_aic_with(
+        "tests/fixtures/negate_int_min_checked.aic",
+        &["--checked-arith"],
+    );
+    // llvm.trap kills the program with SIGILL instead of exiting
+    assert_eq!(result.code, -1, "exit code was {}", result.code);
+}
+
+#[test]
+fn test_block_scope_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/block_scope.aic").code;
+    let expected = 42;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_bool_sum_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/bool_sum.aic").code;
+    let expected = 2;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_bool_equal_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/bool_equal.aic").code;
+    let expected = 1;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_multiline_expression_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/multiline_expression.aic").code;
+    let expected = 18;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_if_select_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/if_select.aic").code;
+    let expected = 14;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_if_select_emits_select() {
+    let result = run_aic(&["--input", "tests/fixtures/if_select.aic", "--emit-llvm"]);
+    assert_eq!(result.code, 0, "stderr was: {}", result.stderr);
+    assert!(
+        result.stdout.contains(" select i1 "),
+        "stdout was: {}",
+        result.stdout
+    );
+    assert!(
+        !result.stdout.contains("br i1"),
+        "simple if/else should not branch, stdout was: {}",
+        result.stdout
+    );
+
+    // An if without an else still branches
+    let result = run_aic(&["--input", "tests/fixtures/if_statement.aic", "--emit-llvm"]);
+    assert_eq!(result.code, 0, "stderr was: {}", result.stderr);
+    assert!(
+        !result.stdout.contains(" select "),
+        "stdout was: {}",
+        result.stdout
+    );
+}
+
+#[test]
+fn test_max_errors_default_aic() {
+    let result = run_aic(&["--input", "tests/fixtures/many_errors.aic", "--check"]);
+    assert_ne!(result.code, 0, "type errors should fail the build");
+    assert!(
+        result.stderr.contains("_v20 "),
+        "stderr was: {}",
+        result.stderr
+    );
+    assert!(
+        !result.stderr.contains("_v21 "),
+        "stderr was: {}",
+        result.stderr
+    );
+    assert!(
+        result.stderr.contains("...and 5 more errors."),
+        "stderr was: {}",
+        result.stderr
+    );
+}
+
+#[test]
+fn test_max_errors_aic() {
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/many_errors.aic",
+        "--check",
+        "--max-errors=3",
+    ]);
+    assert_ne!(result.code, 0, "type errors should fail the build");
+    assert!(
+        !result.stderr.contains("_v4 "),
+        "stderr was: {}",
+        result.stderr
+    );
+    assert!(
+        result.stderr.contains("...and 22 more errors."),
+        "stderr was: {}",
+        result.stderr
+    );
+
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/many_errors.aic",
+        "--check",
+        "--max-errors=0",
+    ]);
+    assert!(
+        result.stderr.contains("_v25 "),
+        "stderr was: {}",
+        result.stderr
+    );
+    assert!(
+        !result.stderr.contains("more error"),
+        "stderr was: {}",
+        result.stderr
+    );
+}
+
+#[test]
+fn test_call_and_variable_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/call_and_variable.aic").code;
+    let expected = 42;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_sizeof_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/sizeof.aic").code;
+    let expected = 96;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_target_wasm32() {
+    let temp_dir = tempdir().expect("Failed to create temp dir");
+    let obj_file = temp_dir.path().join("simple.wasm.o");
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/simple.aic",
+        "--target",
+        "wasm32-unknown-unknown",
+        "-o",
+        obj_file.to_str().unwrap(),
+    ]);
+    assert_eq!(result.code, 0, "stderr was: {}", result.stderr);
+
+    let bytes = std::fs::read(&obj_file).expect("Failed to read object file");
+    assert!(
+        bytes.starts_with(b"\0asm"),
+        "expected the wasm magic bytes, found {:?}",
+        &bytes[..bytes.len().min(4)]
+    );
+}
+
+#[test]
+fn test_target_unknown() {
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/simple.aic",
+        "--target",
+        "bogus-unknown-nowhere",
+        "--emit-llvm",
+    ]);
+    assert_ne!(result.code, 0, "unknown targets should be rejected");
+    assert!(
+        result.stderr.contains("bogus-unknown-nowhere"),
+        "stderr was: {}",
+        result.stderr
+    );
+}
+
+#[test]
+fn test_default_output_next_to_input() {
+    let temp_dir = tempdir().expect("Failed to create temp dir");
+    let dir = temp_dir.path().join("path").join("to");
+    std::fs::create_dir_all(&dir).expect("Failed to create input dir");
+    let input = dir.join("foo.aic");
+    std::fs::copy("tests/fixtures/simple.aic", &input).expect("Failed to copy fixture");
+
+    let result = run_aic(&["--input", input.to_str().unwrap()]);
+    assert_eq!(result.code, 0, "stderr was: {}", result.stderr);
+    assert!(dir.join("foo.o").exists(), "expected path/to/foo.o");
+    assert!(!dir.join("foo.aic.o").exists());
+}
+
+#[test]
+fn test_statements_after_return_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/statements_after_return.aic").code;
+    let expected = 13;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_trailing_value_mid_block_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/trailing_value_mid_block.aic").code;
+    let expected = 16;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_labeled_block_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/labeled_block.aic").code;
+    let expected = 84;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_pic_shared_library() {
+    let temp_dir = tempdir().expect("Failed to create temp dir");
+    let obj_file = temp_dir.path().join("function_call.o");
+    let lib_file = temp_dir.path().join("libfunction_call.so");
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/function_call.aic",
+        "--pic",
+        "-o",
+        obj_file.to_str().unwrap(),
+    ]);
+    assert_eq!(result.code, 0, "stderr was: {}", result.stderr);
+
+    let status = Command::new("clang")
+        .args([
+            "-shared",
+            "-fuse-ld=mold",
+            obj_file.to_str().unwrap(),
+            "-o",
+            lib_file.to_str().unwrap(),
+        ])
+        .status()
+        .expect("Failed to run clang with mold");
+    assert!(status.success(), "linking a shared library failed");
+    assert!(lib_file.exists());
+}
+
+#[test]
+fn test_pic_conflicts_with_reloc() {
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/simple.aic",
+        "--pic",
+        "--reloc",
+        "static",
+        "--emit-llvm",
+    ]);
+    assert_ne!(result.code, 0, "--pic and --reloc should be exclusive");
+}
+
+#[test]
+fn test_code_models() {
+    for model in ["default", "small", "kernel", "medium", "large"] {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let obj_file = temp_dir.path().join("simple.o");
+        let result = run_aic(&[
+            "--input",
+            "tests/fixtures/simple.aic",
+            "--code-model",
+            model,
+            "-o",
+            obj_file.to_str().unwrap(),
+        ]);
+        assert_eq!(result.code, 0, "{model}: stderr was: {}", result.stderr);
+        assert!(obj_file.exists(), "{model}: no object file was written");
+    }
+}
+
+#[test]
+fn test_code_model_unknown() {
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/simple.aic",
+        "--code-model",
+        "huge",
+        "--emit-llvm",
+    ]);
+    assert_ne!(result.code, 0, "an unknown code model should be rejected");
+    assert!(
+        result.stderr.contains("huge"),
+        "stderr was: {}",
+        result.stderr
+    );
+}
+
+#[test]
+fn test_chained_comparison_aic() {
+    let actual =
+        compile_and_run_aic_with("tests/fixtures/chained_comparison.aic", &["--sugar"]).code;
+    let expected = 42;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_chained_comparison_required() {
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/chained_comparison.aic",
+        "--emit-llvm",
+    ]);
+    assert_ne!(
+        result.code, 0,
+        "chained comparisons should be rejected by default"
+    );
+    assert!(
+        result
+            .stderr
+            .contains("comparison operators cannot be chained"),
+        "stderr was: {}",
+        result.stderr
+    );
+}
+
+#[test]
+fn test_c_truthiness_aic() {
+    let actual =
+        compile_and_run_aic_with("tests/fixtures/c_truthiness.aic", &["--c-truthiness"]).code;
+    let expected = 15;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_c_truthiness_required() {
+    let result = run_aic(&["--input", "tests/fixtures/c_truthiness.aic", "--emit-llvm"]);
+    assert_ne!(
+        result.code, 0,
+        "integer conditions should be rejected by default"
+    );
+    assert!(
+        result
+            .stderr
+            .contains("Condition must be a boolean value, found i32"),
+        "stderr was: {}",
+        result.stderr
+    );
+}
+
+#[test]
+fn test_strings_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/strings.aic").code;
+    let expected = 42;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_strings_are_verbatim() {
+    let result = run_aic(&["--input", "tests/fixtures/strings.aic", "--emit-llvm"]);
+    assert_eq!(result.code, 0, "stderr was: {}", result.stderr);
+    // LLVM escapes a backslash as \5C and a newline as \0A
+    for expected in [
+        r"C:\5Ctemp\5Cnew\00",
+        r"\0ADear {name},\0A    \22Thanks\22 for writing.\0A\00",
+    ] {
+        assert!(
+            result.stdout.contains(expected),
+            "missing {expected}, stdout was: {}",
+            result.stdout
+        );
+    }
+}
+
+#[test]
+fn test_print_symbols() {
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/function_call.aic",
+        "--print-symbols",
+        "--emit-llvm",
+    ]);
+    assert_eq!(result.code, 0, "stderr was: {}", result.stderr);
+    for expected in ["define main: i32 ()", "define zero: i32 ()"] {
+        assert!(
+            result.stderr.lines().any(|line| line == expected),
+            "missing {expected}, stderr was: {}",
+            result.stderr
+        );
+    }
+}
+
+#[test]
+fn test_dump_types() {
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/int_literal_context.aic",
+        "--check",
+        "--dump-types",
+    ]);
+    assert_eq!(result.code, 0, "stderr was: {}", result.stderr);
+    for expected in ["3:5: 40: i64", "10:16: 1: i64", "12:1: sum as i32 + 1: i32"] {
+        assert!(
+            result.stderr.lines().any(|line| line == expected),
+            "missing {expected}, stderr was: {}",
+            result.stderr
+        );
+    }
+}
+
+#[test]
+fn test_entry_renames_main() {
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/function_call.aic",
+        "--print-symbols",
+        "--emit-llvm",
+        "--entry",
+        "_start",
+    ]);
+    assert_eq!(result.code, 0, "stderr was: {}", result.stderr);
+    let symbols: Vec<_> = result.stderr.lines().collect();
+    assert!(
+        symbols.contains(&"define _start: i32 ()"),
+        "stderr was: {}",
+        result.stderr
+    );
+    assert!(
+        !symbols.iter().any(|line| line.starts_with("define main:")),
+        "stderr was: {}",
+        result.stderr
+    );
+}
+
+#[test]
+fn test_entry_start_aic() {
+    let actual = compile_and_run_aic_linked(
+        "tests/fixtures/entry_start.aic",
+        &["--entry", "_start"],
+        &["-nostartfiles", "-Wl,-e,_start"],
+    )
+    .code;
+    let expected = 42;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_no_std_main() {
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/freestanding_start.aic",
+        "--print-symbols",
+        "--emit-llvm",
+        "--no-std-main",
+    ]);
+    assert_eq!(result.code, 0, "stderr was: {}", result.stderr);
+    let symbols: Vec<_> = result.stderr.lines().collect();
+    assert!(
+        symbols.contains(&"define _start: void ()"),
+        "stderr was: {}",
+        result.stderr
+    );
+    assert!(
+        !symbols.iter().any(|line| line.contains(" main:")),
+        "stderr was: {}",
+        result.stderr
+    );
+
+    // The program still needs libc for `exit`, but not its startup files
+    let actual = compile_and_run_aic_linked(
+        "tests/fixtures/freestanding_start.aic",
+        &["--no-std-main"],
+        &["-nostartfiles", "-Wl,-e,_start"],
+    )
+    .code;
+    let expected = 42;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+
+    // Without the flag, the top-level declarations still get a synthetic main
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/freestanding_start.aic",
+        "--print-symbols",
+        "--emit-llvm",
+    ]);
+    assert_eq!(result.code, 0, "stderr was: {}", result.stderr);
+    assert!(
+        result
+            .stderr
+            .lines()
+            .any(|line| line == "define main: i32 ()"),
+        "stderr was: {}",
+        result.stderr
+    );
+}
+
+#[test]
+fn test_dump_fn() {
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/function_call.aic",
+        "--dump-fn",
+        "main",
+        "--emit-llvm",
+    ]);
+    assert_eq!(result.code, 0, "stderr was: {}", result.stderr);
+    assert!(
+        result.stderr.contains("define i32 @main()"),
+        "stderr was: {}",
+        result.stderr
+    );
+    assert!(
+        !result.stderr.contains("define i32 @zero()"),
+        "stderr was: {}",
+        result.stderr
+    );
+
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/function_call.aic",
+        "--dump-fn",
+        "missing",
+        "--emit-llvm",
+    ]);
+    assert_ne!(result.code, 0, "dumping an unknown function should fail");
+    assert!(
+        result.stderr.contains("Function 'missing' not found"),
+        "stderr was: {}",
+        result.stderr
+    );
+}
+
+#[test]
+fn test_dump_cfg() {
+    let dir = tempdir().expect("Failed to create temp dir");
+    let output = dir.path().join("clamp.dot");
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/inferred_i32.aic",
+        "--dump-cfg",
+        "clamp",
+        "--output",
+        output.to_str().unwrap(),
+    ]);
+    assert_eq!(result.code, 0, "stderr was: {}", result.stderr);
+
+    let dot = std::fs::read_to_string(&output).expect("Failed to read the CFG");
+    assert!(dot.starts_with("digraph \"clamp\" {"), "dot was: {dot}");
+    let nodes = dot
+        .lines()
+        .filter(|line| line.contains("[label=") && !line.contains(" -> "))
+        .count();
+    let edges = dot.lines().filter(|line| line.contains(" -> ")).count();
+    assert!(nodes > 1, "dot was: {dot}");
+    assert!(edges > 1, "dot was: {dot}");
+
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/inferred_i32.aic",
+        "--dump-cfg",
+        "clamp",
+    ]);
+    assert_ne!(result.code, 0, "--dump-cfg should require --output");
+}
+
+#[test]
+fn test_tail_recursion_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/tail_recursion.aic").code;
+    let expected = 42;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_list_targets() {
+    // No input file is needed
+    let result = run_aic(&["--list-targets"]);
+    assert_eq!(result.code, 0, "stderr was: {}", result.stderr);
+    let host_triple = format!("Host triple: {}-", std::env::consts::ARCH);
+    assert!(
+        result.stdout.contains(&host_triple),
+        "stdout was: {}",
+        result.stdout
+    );
+    assert!(
+        result.stdout.lines().any(|line| line.ends_with(" (host)")),
+        "the host target should be marked, stdout was: {}",
+        result.stdout
+    );
+    assert!(
+        result.stdout.contains("wasm32"),
+        "stdout was: {}",
+        result.stdout
+    );
+}
+
+#[test]
+fn test_input_required_without_list_targets() {
+    let result = run_aic(&["--emit-llvm"]);
+    assert_ne!(result.code, 0);
+    assert!(
+        result.stderr.contains("--input"),
+        "stderr was: {}",
+        result.stderr
+    );
+}
+
+#[test]
+fn test_escapes_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/escapes.aic").code;
+    let expected = 42;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_escapes_are_processed() {
+    let result = run_aic(&["--input", "tests/fixtures/escapes.aic", "--emit-llvm"]);
+    assert_eq!(result.code, 0, "stderr was: {}", result.stderr);
+    // LLVM prints non-printable bytes as \XX, and unicode escapes are UTF-8 encoded
+    let expected =
+        r"tab\09newline\0Areturn\0Dback\5Cslash quote\22 nul\00 e\C3\A9 smile\F0\9F\98\80\00";
+    assert!(
+        result.stdout.contains(expected),
+        "stdout was: {}",
+        result.stdout
+    );
+}
+
+#[test]
+fn test_invalid_escape() {
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/invalid_escape.aic",
+        "--emit-llvm",
+    ]);
+    assert_ne!(result.code, 0, "an unknown escape should be rejected");
+    assert!(
+        result.stderr.contains(r"unknown escape sequence `\q`"),
+        "stderr was: {}",
+        result.stderr
+    );
+}
+
+#[test]
+fn test_emit_c() {
+    for (fixture, expected) in [
+        ("function_call", 0),
+        ("let_and_var", 30),
+        ("if_statement_else", 1),
+        ("do_while", 31),
+        ("c_truthiness", 15),
+        ("if_select", 14),
+        ("escapes", 42),
+    ] {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let c_file = temp_dir.path().join(format!("{fixture}.c"));
+        let exe_file = temp_dir.path().join(format!("{fixture}.out"));
+        let result = run_aic(&[
+            "--input",
+            &format!("tests/fixtures/{fixture}.aic"),
+            "--c-truthiness",
+            "--emit=c",
+            "-o",
+            c_file.to_str().unwrap(),
+        ]);
+        assert_eq!(result.code, 0, "{fixture}: stderr was: {}", result.stderr);
+
+        let status = Command::new("cc")
+            .args(["-std=c99", c_file.to_str().unwrap(), "-o"])
+            .arg(&exe_file)
+            .status()
+            .expect("Failed to run cc");
+        assert!(
+            status.success(),
+            "{fixture}: the generated C doesn't compile"
+        );
+
+        let actual = Command::new(&exe_file)
+            .status()
+            .expect("Failed to run executable")
+            .code();
+        assert_eq!(
+            actual,
+            Some(expected),
+            "{fixture}: exit code was {actual:?}"
+        );
+    }
+}
+
+#[test]
+fn test_emit_c_unsupported() {
+    let result = run_aic(&["--input", "tests/fixtures/labeled_block.aic", "--emit=c"]);
+    assert_ne!(result.code, 0);
+    assert!(
+        result
+            .stderr
+            .contains("Labeled blocks are not supported by the C backend"),
+        "stderr was: {}",
+        result.stderr
+    );
+}
+
+#[test]
+fn test_conditional_aic() {
+    let fixture = "tests/fixtures/conditional.aic";
+    for (flags, expected) in [
+        (&[][..], 1),
+        (&["--define", "FAST"][..], 11),
+        (&["-D", "TURBO"][..], 1),
+        (&["-D", "FAST", "-D", "TURBO"][..], 111),
+    ] {
+        let actual = compile_and_run_aic_with(fixture, flags).code;
+        assert_eq!(
+            actual, expected,
+            "flags {flags:?}: exit code was {actual}, expected {expected}",
+        );
+    }
+}
+
+#[test]
+fn test_conditional_unclosed() {
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/conditional_unclosed.aic",
+        "--emit-llvm",
+    ]);
+    assert_ne!(result.code, 0);
+    assert!(
+        result.stderr.contains("`#if` is never closed by `#endif`"),
+        "stderr was: {}",
+        result.stderr
+    );
+}
+
+#[test]
+fn test_cache_dir() {
+    let temp_dir = tempdir().expect("Failed to create temp dir");
+    let cache_dir = temp_dir.path().join("cache");
+    let compile = |output: &str, flags: &[&str]| {
+        let output = temp_dir.path().join(output);
+        let mut args = vec![
+            "--input",
+            "tests/fixtures/simple.aic",
+            "--cache-dir",
+            cache_dir.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+        ];
+        args.extend_from_slice(flags);
+        let result = run_aic(&args);
+        assert_eq!(result.code, 0, "stderr was: {}", result.stderr);
+        (
+            result.stdout.contains("(cached)"),
+            std::fs::read(output).unwrap(),
+        )
+    };
+
+    let (cached, first) = compile("first.o", &[]);
+    assert!(!cached, "nothing should be cached yet");
+    let (cached, second) = compile("second.o", &[]);
+    assert!(
+        cached,
+        "an identical compile should be served from the cache"
+    );
+    assert_eq!(first, second);
+
+    // Changing a flag that affects the object file invalidates the entry
+    let (cached, _) = compile("pic.o", &["--pic"]);
+    assert!(
+        !cached,
+        "a different relocation model should not hit the cache"
+    );
+}
+
+#[test]
+fn test_profile_generate() {
+    let temp_dir = tempdir().expect("Failed to create temp dir");
+    let obj_file = temp_dir.path().join("simple.o");
+    let exe_file = temp_dir.path().join("simple.out");
+    let profile = temp_dir.path().join("simple.profraw");
+
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/simple.aic",
+        "--profile-generate",
+        "-o",
+        obj_file.to_str().unwrap(),
+    ]);
+    assert_eq!(result.code, 0, "stderr was: {}", result.stderr);
+
+    // The instrumented object needs the profile runtime
+    let status = Command::new("clang")
+        .args([
+            "-fuse-ld=mold",
+            "-fprofile-generate",
+            obj_file.to_str().unwrap(),
+            "-o",
+            exe_file.to_str().unwrap(),
+        ])
+        .status()
+        .expect("Failed to run clang with mold");
+    assert!(status.success(), "clang (mold) failed");
+
+    let output = Command::new(&exe_file)
+        .env("LLVM_PROFILE_FILE", &profile)
+        .output()
+        .expect("Failed to run executable");
+    assert_eq!(
+        output.status.code(),
+        Some(compile_and_run_aic("tests/fixtures/simple.aic").code),
+        "the instrumented program should behave the same"
+    );
+    assert!(profile.exists(), "the run should write a profile");
+}
+
+#[test]
+fn test_profile_use() {
+    let temp_dir = tempdir().expect("Failed to create temp dir");
+    let obj_file = temp_dir.path().join("loop.o");
+    let exe_file = temp_dir.path().join("loop.out");
+    let raw_profile = temp_dir.path().join("loop.profraw");
+    let profile = temp_dir.path().join("loop.profdata");
+    let link = |extra_args: &[&str]| {
+        let status = Command::new("clang")
+            .args(["-fuse-ld=mold"])
+            .args(extra_args)
+            .args([obj_file.to_str().unwrap(), "-o", exe_file.to_str().unwrap()])
+            .status()
+            .expect("Failed to run clang with mold");
+        assert!(status.success(), "clang (mold) failed");
+    };
+
+    // Record a profile with an instrumented build
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/while_break_continue.aic",
+        "--profile-generate",
+        "-o",
+        obj_file.to_str().unwrap(),
+    ]);
+    assert_eq!(result.code, 0, "stderr was: {}", result.stderr);
+    link(&["-fprofile-generate"]);
+    let status = Command::new(&exe_file)
+        .env("LLVM_PROFILE_FILE", &raw_profile)
+        .status()
+        .expect("Failed to run executable");
+    assert_eq!(status.code(), Some(52));
+    let status = Command::new("llvm-profdata")
+        .args(["merge", "-o", profile.to_str().unwrap()])
+        .arg(&raw_profile)
+        .status()
+        .expect("Failed to run llvm-profdata");
+    assert!(status.success(), "llvm-profdata failed");
+
+    // The optimized build reads the profile and behaves the same
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/while_break_continue.aic",
+        "--profile-use",
+        profile.to_str().unwrap(),
+        "-o",
+        obj_file.to_str().unwrap(),
+    ]);
+    assert_eq!(result.code, 0, "stderr was: {}", result.stderr);
+    link(&[]);
+    let output = Command::new(&exe_file)
+        .output()
+        .expect("Failed to run executable");
+    assert_eq!(output.status.code(), Some(52));
+}
+
+#[test]
+fn test_profile_use_missing_file() {
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/simple.aic",
+        "--profile-use",
+        "tests/fixtures/missing.profdata",
+    ]);
+    assert_ne!(result.code, 0);
+    assert!(
+        result
+            .stderr
+            .contains("Failed to read profile 'tests/fixtures/missing.profdata'"),
+        "stderr was: {}",
+        result.stderr
+    );
+}
+
+#[test]
+fn test_directory_as_input() {
+    let result = run_aic(&["--input", "tests/fixtures", "--emit-llvm"]);
+    assert_ne!(result.code, 0);
+    assert!(
+        result
+            .stderr
+            .contains("Failed to read input file 'tests/fixtures'"),
+        "stderr was: {}",
+        result.stderr
+    );
+    assert!(
+        !result.stderr.contains("panicked"),
+        "stderr was: {}",
+        result.stderr
+    );
+}
+
+#[test]
+fn test_directory_as_output() {
+    let temp_dir = tempdir().expect("Failed to create temp dir");
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/simple.aic",
+        "-o",
+        temp_dir.path().to_str().unwrap(),
+    ]);
+    assert_ne!(result.code, 0);
+    assert!(
+        result.stderr.contains("Failed to write object file"),
+        "stderr was: {}",
+        result.stderr
+    );
+    assert!(
+        !result.stderr.contains("panicked"),
+        "stderr was: {}",
+        result.stderr
+    );
+}
+
+#[test]
+fn test_max_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/max.aic").code;
+    let expected = 7;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_min_max_float_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/min_max_float.aic").code;
+    let expected = 42;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_min_max_intrinsics() {
+    let result = run_aic(&["--input", "tests/fixtures/max.aic", "--emit-llvm"]);
+    assert_eq!(result.code, 0, "stderr was: {}", result.stderr);
+    assert!(
+        result.stdout.contains("@llvm.smax.i32"),
+        "stdout was: {}",
+        result.stdout
+    );
+
+    let result = run_aic(&["--input", "tests/fixtures/min_max_float.aic", "--emit-llvm"]);
+    assert_eq!(result.code, 0, "stderr was: {}", result.stderr);
+    for intrinsic in ["@llvm.minnum.f64", "@llvm.maxnum.f64"] {
+        assert!(
+            result.stdout.contains(intrinsic),
+            "missing {intrinsic}, stdout was: {}",
+            result.stdout
+        );
+    }
+}
+
+#[test]
+fn test_math_prelude_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/math_prelude.aic").code;
+    let expected = 42;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+
+    let result = run_aic(&["--input", "tests/fixtures/math_prelude.aic", "--emit-llvm"]);
+    assert_eq!(result.code, 0, "stderr was: {}", result.stderr);
+    for intrinsic in ["@llvm.abs.i32", "@llvm.fabs.f64", "@llvm.sqrt.f64"] {
+        assert!(
+            result.stdout.contains(intrinsic),
+            "missing {intrinsic}, stdout was: {}",
+            result.stdout
+        );
+    }
+}
+
+#[test]
+fn test_increment_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/increment.aic").code;
+    let expected = 60;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_trailing_value_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/trailing_value.aic").code;
+    let expected = 42;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_trailing_semicolon_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/trailing_semicolon.aic").code;
+    let expected = 0;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_i64_max_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/i64_max.aic").code;
+    let expected = 7;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_struct_by_value_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/struct_by_value.aic").code;
+    let expected = 41;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_globals_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/globals.aic").code;
+    let expected = 42;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_shadowing_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/shadowing.aic").code;
+    let expected = 42;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_one_line_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/one_line.aic").code;
+    let expected = 42;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_truncating_div_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/floor_div.aic").code;
+    let expected = 77;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_floor_div_aic() {
+    let actual = compile_and_run_aic_with("tests/fixtures/floor_div.aic", &["--floor-div"]).code;
+    let expected = 66;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_named_args_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/named_args.aic").code;
+    let expected = 42;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_emit_metadata() {
+    let temp_dir = tempdir().expect("Failed to create temp dir");
+    let metadata_file = temp_dir.path().join("function_call.json");
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/function_call.aic",
+        "--target",
+        "wasm32-unknown-unknown",
+        "--emit-llvm",
+        "--emit-metadata",
+        metadata_file.to_str().unwrap(),
+    ]);
+    assert_eq!(result.code, 0, "stderr was: {}", result.stderr);
+
+    let json = std::fs::read_to_string(&metadata_file).expect("Failed to read metadata");
+    let metadata: serde_json::Value = serde_json::from_str(&json).expect("invalid JSON");
+    assert_eq!(metadata["target"], "wasm32-unknown-unknown");
+    let functions = metadata["functions"].as_array().expect("functions array");
+    assert!(
+        functions
+            .iter()
+            .any(|function| function["name"] == "main" && function["defined"] == true),
+        "metadata was: {json}"
+    );
+}
+
+#[test]
+fn test_comma_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/comma.aic").code;
+    let expected = 5;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_extern_aic() {
+    let actual = compile_and_run_aic("tests/fixtures/extern.aic").code;
+    let expected = 42;
+    assert_eq!(
+        actual, expected,
+        "exit code was {actual}, expected {expected}",
+    );
+}
+
+#[test]
+fn test_emit_ast_formats() {
+    for format in ["json", "yaml", "ron"] {
+        let result = run_aic(&[
+            "--input",
+            "tests/fixtures/function_call.aic",
+            "--emit-ast",
+            format,
+        ]);
+        assert_eq!(result.code, 0, "{format}: stderr was: {}", result.stderr);
+        assert!(
+            result.stdout.contains("FnDecl") && result.stdout.contains("zero"),
+            "{format}: stdout was: {}",
+            result.stdout
+        );
+    }
+
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/function_call.aic",
+        "--emit-ast=json",
+    ]);
+    let program: serde_json::Value = serde_json::from_str(&result.stdout).expect("invalid JSON");
+    assert_eq!(program["statements"][0]["FnDecl"]["name"], "zero");
+
+    let result = run_aic(&[
+        "--input",
+        "tests/fixtures/function_call.aic",
+        "--emit-ast",
+        "toml",
+    ]);
+    assert_ne!(result.code, 0, "unknown formats should be rejected");
+    assert!(
+        result.stderr.contains("possible values"),
+        "stderr was: {}",
+        result.stderr
+    );
+}